@@ -1,22 +1,63 @@
 use std::f64::consts::PI;
 
-use crayfish::{shapes::Shape, object::Object, cli::Config, raytrace::render_scene, camera::Camera, tuples::Tuple, groups::ObjectGroup, materials, transformations};
+use crayfish::{shapes::Shape, object::Object, cli::Config, raytrace::render_scene, camera::{Camera, Projection}, tuples::Tuple, groups::ObjectGroup, materials, transformations, world::World};
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
 
 fn render_cube(c: &mut Criterion) {
-    let cube = Object::new(Shape::Cube);
+    let mut cube = ObjectGroup::new();
+    cube.add(Object::new(Shape::Cube));
+    let cube = World::new(cube);
 
     let config = Config {
         aspect_ratio: 1.,
         fov_radians: PI / 2.,
         aperture_radius: 0.,
+        aperture_blades: 0,
+        aperture_rotation_radians: 0.,
+        aperture_image: None,
+        anamorphic_squeeze: 1.,
+        focus: None,
+        projection: Projection::Perspective,
+        up: Tuple::vector(0., 1., 0.),
+        roll_radians: 0.,
+        panorama: None,
+        stereo: None,
+        interpupillary_distance: 0.065,
+        convergence_distance: None,
+        shutter: (0., 0.),
+        camera_keys: None,
         outfile: "test".into(),
         image_height: 20,
         row_range: (0, 20),
+        frames: None,
+        snapshot_passes: None,
+        snapshot_seconds: None,
+        sample_heatmap: false,
+        checkpoint_seconds: None,
+        resume: false,
         verbose: false,
         rays_per_pixel: 10,
+        time_limit: None,
+        samples_per_pass: None,
+        noise_threshold: None,
+        min_samples: 16,
+        max_samples: 10,
+        seed: 0,
         max_scatter_depth: 10,
+        dispersion: false,
+        spectral: false,
+        principled: false,
+        fog_density: None,
+        fog_anisotropy: 0.,
+        fog_far: 100.,
+        environment: None,
+        environment_rotation_radians: 0.,
+        environment_intensity: 1.,
+        sky: false,
+        sun_elevation_radians: std::f64::consts::FRAC_PI_4,
+        sun_azimuth_radians: 0.,
+        turbidity: 3.,
     };
 
     let camera = Camera::new(
@@ -50,17 +91,57 @@ fn render_cubes(c: &mut Criterion) {
         .with_material(Box::new(materials::Dielectric::new(1.52)))
         .with_transform(transformations::translation(-6., -6., -6.))
     );
+    let world = World::new(world);
 
     let config = Config {
         aspect_ratio: 1.,
         fov_radians: PI / 2.,
         aperture_radius: 0.,
+        aperture_blades: 0,
+        aperture_rotation_radians: 0.,
+        aperture_image: None,
+        anamorphic_squeeze: 1.,
+        focus: None,
+        projection: Projection::Perspective,
+        up: Tuple::vector(0., 1., 0.),
+        roll_radians: 0.,
+        panorama: None,
+        stereo: None,
+        interpupillary_distance: 0.065,
+        convergence_distance: None,
+        shutter: (0., 0.),
+        camera_keys: None,
         outfile: "test".into(),
         image_height: 20,
         row_range: (0, 20),
+        frames: None,
+        snapshot_passes: None,
+        snapshot_seconds: None,
+        sample_heatmap: false,
+        checkpoint_seconds: None,
+        resume: false,
         verbose: false,
         rays_per_pixel: 10,
+        time_limit: None,
+        samples_per_pass: None,
+        noise_threshold: None,
+        min_samples: 16,
+        max_samples: 10,
+        seed: 0,
         max_scatter_depth: 10,
+        dispersion: false,
+        spectral: false,
+        principled: false,
+        fog_density: None,
+        fog_anisotropy: 0.,
+        fog_far: 100.,
+        environment: None,
+        environment_rotation_radians: 0.,
+        environment_intensity: 1.,
+        sky: false,
+        sun_elevation_radians: std::f64::consts::FRAC_PI_4,
+        sun_azimuth_radians: 0.,
+        turbidity: 3.,
     };
 
     let camera = Camera::new(
@@ -0,0 +1,244 @@
+use crate::{
+    Result,
+    matrix::Matrix,
+    motion::Motion,
+    object::Object,
+    tuples::{Tuple, Point},
+};
+
+
+/// Something that can be blended between two keyframes.
+pub trait Keyframe: Clone {
+    /// The value `t` of the way from `self` to `other`, with `t` in [0, 1].
+    fn interpolate(&self, other: &Self, t: f64) -> Self;
+}
+
+impl Keyframe for f64 {
+    fn interpolate(&self, other: &f64, t: f64) -> f64 {
+        self + (other - self) * t
+    }
+}
+
+impl Keyframe for Tuple<Point> {
+    fn interpolate(&self, other: &Tuple<Point>, t: f64) -> Tuple<Point> {
+        self + (other - self) * t
+    }
+}
+
+impl Keyframe for Matrix {
+    /// Rotation turns along the shortest arc, as in motion blur.
+    fn interpolate(&self, other: &Matrix, t: f64) -> Matrix {
+        Motion::new(self, other).transform_at(t)
+    }
+}
+
+
+/// How a track gets from one keyframe to the next.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation {
+    /// At a constant rate.
+    Linear,
+    /// Easing out of each keyframe and into the next.
+    Smooth,
+}
+
+impl Interpolation {
+    fn ease(self, t: f64) -> f64 {
+        match self {
+            Interpolation::Linear => t,
+            Interpolation::Smooth => t * t * (3. - 2. * t),
+        }
+    }
+}
+
+
+/// A value keyed at given frames, and interpolated in between.
+/// Before the first key and after the last one, it holds still.
+#[derive(Debug, Clone)]
+pub struct Track<T: Keyframe> {
+    keys: Vec<(f64, T)>,
+    interpolation: Interpolation,
+}
+
+impl<T: Keyframe> Track<T> {
+    pub fn new(interpolation: Interpolation) -> Track<T> {
+        Track { keys: vec![], interpolation }
+    }
+
+    /// A track that is `value` at every frame.
+    pub fn constant(value: T) -> Track<T> {
+        Track::new(Interpolation::Linear).with_key(0., value)
+    }
+
+    /// Adds a key at `frame`, replacing any already there.
+    pub fn with_key(mut self, frame: f64, value: T) -> Track<T> {
+        match self.keys.binary_search_by(|(f, _)| f.total_cmp(&frame)) {
+            Ok(i) => self.keys[i].1 = value,
+            Err(i) => self.keys.insert(i, (frame, value)),
+        }
+        self
+    }
+
+    /// The value at `frame`; None if the track has no keys.
+    pub fn at(&self, frame: f64) -> Option<T> {
+        let next = self.keys.partition_point(|(f, _)| *f <= frame);
+        if next == 0 {
+            return self.keys.first().map(|(_, value)| value.clone());
+        }
+        let (from_frame, from) = &self.keys[next - 1];
+        let Some((to_frame, to)) = self.keys.get(next) else {
+            return Some(from.clone());
+        };
+        let t = (frame - from_frame) / (to_frame - from_frame);
+        Some(from.interpolate(to, self.interpolation.ease(t)))
+    }
+}
+
+impl Track<Matrix> {
+    /// Places `object` where the track has it at `frame`. If the shutter
+    /// stays open past the start of the frame, the object moves on
+    /// towards where it is at the next frame, for motion blur.
+    pub fn pose(&self, object: Object, frame: f64, shutter: (f64, f64)) -> Object {
+        let start = self.at(frame).unwrap_or_else(|| Matrix::identity(4));
+        if shutter.1 <= 0. {
+            return object.with_transform(start);
+        }
+        let end = self.at(frame + 1.).unwrap_or_else(|| Matrix::identity(4));
+        object.with_animated_transform(start, end)
+    }
+}
+
+
+/// Where the camera is and what it looks at, keyed over the animation.
+#[derive(Debug, Clone)]
+pub struct CameraKeys {
+    pub lookfrom: Track<Tuple<Point>>,
+    pub lookat: Track<Tuple<Point>>,
+}
+
+impl CameraKeys {
+    /// Parses camera keys, one per line as `FRAME  LOOKFROM_X Y Z  LOOKAT_X Y Z`.
+    ///
+    /// A line `interpolation linear` or `interpolation smooth` sets how
+    /// the camera gets between keys; it is linear otherwise. Anything after
+    /// a `#` is a comment.
+    pub fn parse(text: &str) -> Result<CameraKeys> {
+        let mut interpolation = Interpolation::Linear;
+        let mut keys = vec![];
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields[..] {
+                [] => {}
+                ["interpolation", "linear"] => interpolation = Interpolation::Linear,
+                ["interpolation", "smooth"] => interpolation = Interpolation::Smooth,
+                _ => {
+                    let values = fields.iter()
+                        .map(|field| field.parse::<f64>())
+                        .collect::<std::result::Result<Vec<_>, _>>();
+                    let Ok(&[frame, fx, fy, fz, ax, ay, az]) = values.as_deref() else {
+                        return Err(format!(
+                            "Camera key line {} should be a frame, then lookfrom and lookat as x y z",
+                            number + 1).into());
+                    };
+                    keys.push((frame, Tuple::point(fx, fy, fz), Tuple::point(ax, ay, az)));
+                }
+            }
+        }
+        if keys.is_empty() {
+            return Err("Camera keys need at least one key".into());
+        }
+        let mut camera = CameraKeys {
+            lookfrom: Track::new(interpolation),
+            lookat: Track::new(interpolation),
+        };
+        for (frame, lookfrom, lookat) in keys {
+            camera.lookfrom = camera.lookfrom.with_key(frame, lookfrom);
+            camera.lookat = camera.lookat.with_key(frame, lookat);
+        }
+        Ok(camera)
+    }
+
+    pub fn load(path: &str) -> Result<CameraKeys> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        CameraKeys::parse(&text).map_err(|e| format!("{}: {}", path, e).into())
+    }
+}
+
+
+/// Where frame `frame` of an image sequence is written, given the
+/// file's extension.
+pub fn frame_path(outfile: &str, frame: usize, extension: &str) -> String {
+    format!("{}_{:04}.{}", outfile, frame, extension)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transformations::translation;
+
+    #[test]
+    fn tracks_interpolate_between_keys() {
+        let track = Track::new(Interpolation::Linear)
+            .with_key(10., 1.)
+            .with_key(0., 0.)
+            .with_key(20., 3.);
+        assert_eq!(track.at(-5.), Some(0.));
+        assert_eq!(track.at(5.), Some(0.5));
+        assert_eq!(track.at(10.), Some(1.));
+        assert_eq!(track.at(15.), Some(2.));
+        assert_eq!(track.at(25.), Some(3.));
+        assert_eq!(Track::<f64>::new(Interpolation::Linear).at(0.), None);
+    }
+
+    #[test]
+    fn smooth_tracks_ease_in_and_out() {
+        let track = Track::new(Interpolation::Smooth).with_key(0., 0.).with_key(10., 1.);
+        assert_eq!(track.at(5.), Some(0.5));
+        let early = track.at(1.).unwrap();
+        assert!(early > 0. && early < 0.1);
+    }
+
+    #[test]
+    fn posed_objects_move_towards_the_next_frame() {
+        let track = Track::new(Interpolation::Linear)
+            .with_key(0., translation(0., 0., 0.))
+            .with_key(10., translation(10., 0., 0.));
+        let still = track.pose(Object::new_sphere(), 3., (0., 0.));
+        assert!(!still.is_moving());
+        assert_eq!(still.transform, translation(3., 0., 0.));
+        let blurred = track.pose(Object::new_sphere(), 3., (0., 0.5));
+        assert_eq!(*blurred.transform_at(1.), translation(4., 0., 0.));
+    }
+
+    #[test]
+    fn camera_keys_are_read_one_per_line() {
+        let keys = CameraKeys::parse(
+            "# frame  lookfrom  lookat\n\
+             interpolation smooth\n\
+             0    4 4 -10   1 1 0\n\
+             100 -4 4 -10   1 1 0  # pan left\n",
+        ).unwrap();
+        assert_eq!(keys.lookfrom.at(0.), Some(Tuple::point(4., 4., -10.)));
+        assert_eq!(keys.lookfrom.at(50.), Some(Tuple::point(0., 4., -10.)));
+        // Easing, the camera has barely moved by frame 10.
+        assert!(keys.lookfrom.at(10.).unwrap().x > 3.5);
+        assert_eq!(keys.lookat.at(70.), Some(Tuple::point(1., 1., 0.)));
+    }
+
+    #[test]
+    fn camera_keys_must_be_complete() {
+        let error = CameraKeys::parse("0 4 4 -10 1 1\n").unwrap_err();
+        assert_eq!(error.to_string(),
+            "Camera key line 1 should be a frame, then lookfrom and lookat as x y z");
+        assert!(CameraKeys::parse("interpolation bouncy\n").is_err());
+        assert!(CameraKeys::parse("# nothing\n").is_err());
+    }
+
+    #[test]
+    fn frames_are_numbered_with_four_digits() {
+        assert_eq!(frame_path("out", 1, "ppm"), "out_0001.ppm");
+        assert_eq!(frame_path("renders/spin", 12345, "chunk"), "renders/spin_12345.chunk");
+    }
+}
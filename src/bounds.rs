@@ -0,0 +1,122 @@
+use crate::{
+    matrix::Matrix,
+    ray::Ray,
+    tuples::{Tuple, Point},
+};
+
+
+/// An axis-aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Tuple<Point>,
+    pub max: Tuple<Point>,
+}
+
+impl Aabb {
+    pub fn new(min: Tuple<Point>, max: Tuple<Point>) -> Aabb {
+        Aabb { min, max }
+    }
+
+    /// A box containing nothing, which any union replaces.
+    pub fn empty() -> Aabb {
+        Aabb {
+            min: Tuple::point(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Tuple::point(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Tuple::point(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: Tuple::point(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    /// Grows the box by `margin` on every side.
+    pub fn pad(&self, margin: f64) -> Aabb {
+        let m = Tuple::vector(margin, margin, margin);
+        Aabb { min: self.min - m, max: self.max + m }
+    }
+
+    /// Length of the box's diagonal.
+    pub fn diagonal(&self) -> f64 {
+        (self.max - self.min).magnitude()
+    }
+
+    /// The box around this box after transforming it by `m`.
+    pub fn transform(&self, m: &Matrix) -> Aabb {
+        let mut out = Aabb::empty();
+        for corner in 0..8 {
+            let pick = |axis: usize, low: f64, high: f64| if corner >> axis & 1 == 1 { high } else { low };
+            let p = Tuple::point(
+                pick(0, self.min.x, self.max.x),
+                pick(1, self.min.y, self.max.y),
+                pick(2, self.min.z, self.max.z),
+            );
+            let q = m / p;
+            out = out.union(&Aabb::new(q, q));
+        }
+        out
+    }
+
+    /// Whether `ray` passes through the box between `min_t` and `max_t`.
+    pub fn hit(&self, ray: &Ray, min_t: f64, max_t: f64) -> bool {
+        let origin = [ray.origin.x, ray.origin.y, ray.origin.z];
+        let direction = [ray.direction.x, ray.direction.y, ray.direction.z];
+        let low = [self.min.x, self.min.y, self.min.z];
+        let high = [self.max.x, self.max.y, self.max.z];
+        let (mut from, mut to) = (min_t, max_t);
+        for axis in 0..3 {
+            let inverse = 1. / direction[axis];
+            let mut t0 = (low[axis] - origin[axis]) * inverse;
+            let mut t1 = (high[axis] - origin[axis]) * inverse;
+            if inverse < 0. {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // NaN, from a ray in the plane of a face, leaves the interval as it is.
+            from = if t0 > from { t0 } else { from };
+            to = if t1 < to { t1 } else { to };
+            if to < from {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transformations::{rotation, Axis};
+
+    fn unit_box() -> Aabb {
+        Aabb::new(Tuple::point(-1., -1., -1.), Tuple::point(1., 1., 1.))
+    }
+
+    #[test]
+    fn rays_hit_boxes_they_pass_through() {
+        let b = unit_box();
+        assert!(b.hit(&Ray::from_coords(-5., 0.5, 0., 1., 0., 0.), 0., f64::INFINITY));
+        assert!(!b.hit(&Ray::from_coords(-5., 1.5, 0., 1., 0., 0.), 0., f64::INFINITY));
+        assert!(!b.hit(&Ray::from_coords(-5., 0., 0., -1., 0., 0.), 0., f64::INFINITY));
+        // Flat boxes are hit by rays crossing them.
+        let flat = Aabb::new(Tuple::point(-1., 0., -1.), Tuple::point(1., 0., 1.));
+        assert!(flat.hit(&Ray::from_coords(0., 1., 0., 0., -1., 0.), 0., f64::INFINITY));
+    }
+
+    #[test]
+    fn transformed_boxes_contain_the_transformed_corners() {
+        let b = unit_box().transform(&rotation(Axis::Z, std::f64::consts::FRAC_PI_4));
+        let r = std::f64::consts::SQRT_2;
+        assert_eq!(b, Aabb::new(Tuple::point(-r, -r, -1.), Tuple::point(r, r, 1.)));
+    }
+}
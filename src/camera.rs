@@ -1,13 +1,116 @@
-use crate::{tuples::{Tuple, Point, Vector}, ray::Ray};
+use crate::{
+    Result,
+    tuples::{Tuple, Point, Vector},
+    ray::Ray,
+    groups::ObjectGroup,
+    intersection::intersect_shape,
+};
+use aperture::Aperture;
 
+pub mod aperture;
+pub mod panoramic;
+pub mod stereo;
+
+
+/// Anything the renderer can cast camera rays from.
+pub trait CameraModel {
+    /// Given x,y in [0, 1], with y pointing up the image, casts a ray
+    /// through the corresponding point of the image at `time`.
+    ///
+    /// None if the point is outside the area the projection covers,
+    /// such as the corners around a fisheye image circle.
+    fn cast_ray(&self, x: f64, y: f64, time: f64) -> Option<Ray>;
+}
+
+
+/// Orthonormal `(right, up, forward)` vectors of a camera looking along
+/// `forward`, with its up as close to `up` as possible, then rolled by
+/// `roll` radians (clockwise, as seen from behind the camera).
+///
+/// If `forward` is parallel to `up`, the world axis least aligned with
+/// `forward` stands in for `up`.
+pub fn view_basis(
+    forward: Tuple<Vector>,
+    up: Tuple<Vector>,
+    roll: f64,
+) -> (Tuple<Vector>, Tuple<Vector>, Tuple<Vector>) {
+    let forward = forward.unit();
+    let mut right = up.cross(&forward);
+    if right.magnitude() < 1e-9 * up.magnitude().max(1.) {
+        let axes = [
+            Tuple::vector(1., 0., 0.),
+            Tuple::vector(0., 1., 0.),
+            Tuple::vector(0., 0., 1.),
+        ];
+        let fallback = axes.into_iter()
+            .min_by(|a, b| a.dot(&forward).abs().total_cmp(&b.dot(&forward).abs()))
+            .unwrap();
+        right = fallback.cross(&forward);
+    }
+    let right = right.unit();
+    let up = forward.cross(&right).unit();
+    let (sin, cos) = roll.sin_cos();
+    (right * cos - up * sin, up * cos + right * sin, forward)
+}
+
+/// How a camera maps the image onto rays.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// Rays fan out from the eye, through a thin lens.
+    Perspective,
+    /// Rays run parallel to the view direction, from a viewport
+    /// `view_width` across. The field of view and aperture are ignored.
+    Orthographic { view_width: f64 },
+}
+
+/// What a camera keeps in sharp focus.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Focus {
+    /// Everything this far in front of the camera.
+    Distance(f64),
+    /// Everything as far in front of the camera as this point.
+    Point(Tuple<Point>),
+    /// Whatever surface is visible at x,y in [0, 1] on the image, with y up.
+    Pixel(f64, f64),
+}
+
+/// Where a camera's viewport is at one moment.
+#[derive(Debug, Clone, Copy)]
+struct Viewport {
+    origin: Tuple<Point>,
+    horizontal: Tuple<Vector>,
+    vertical: Tuple<Vector>,
+    lower_left_corner: Tuple<Point>,
+    u_horizontal: Tuple<Vector>,
+    u_vertical: Tuple<Vector>,
+    u_out: Tuple<Vector>,
+}
+
+#[derive(Debug, Clone)]
 pub struct Camera {
     pub origin: Tuple<Point>,
     pub horizontal: Tuple<Vector>,
     pub vertical: Tuple<Vector>,
     pub lower_left_corner: Tuple<Point>,
+    pub projection: Projection,
+    lookfrom: Tuple<Point>,
+    lookat: Tuple<Point>,
+    up: Tuple<Vector>,
+    roll: f64,
+    eye_offset: f64,
+    convergence_distance: f64,
     aperture_radius: f64,
+    aperture: Aperture,
+    anamorphic_squeeze: f64,
+    aspect_ratio: f64,
+    fov_radians: f64,
+    focus_distance: Option<f64>,
     u_horizontal: Tuple<Vector>,
     u_vertical: Tuple<Vector>,
+    u_out: Tuple<Vector>,
+    /// Where the camera moves to by time 1: `lookfrom` and `lookat`.
+    motion: Option<(Tuple<Point>, Tuple<Point>)>,
+    end: Option<Viewport>,
 }
 
 impl Camera {
@@ -19,43 +122,360 @@ impl Camera {
         focus_distance: Option<f64>, // if None, use |lookfrom - lookat|
         aperture_radius: f64,
     ) -> Camera {
+        let zero = Tuple::vector(0., 0., 0.);
+        Camera {
+            origin: lookfrom,
+            lookfrom,
+            lookat,
+            up: Tuple::vector(0., 1., 0.),
+            roll: 0.,
+            eye_offset: 0.,
+            convergence_distance: f64::INFINITY,
+            horizontal: zero,
+            vertical: zero,
+            lower_left_corner: lookfrom,
+            projection: Projection::Perspective,
+            aperture_radius,
+            aperture: Aperture::Circle,
+            anamorphic_squeeze: 1.,
+            aspect_ratio,
+            fov_radians,
+            focus_distance,
+            u_horizontal: zero,
+            u_vertical: zero,
+            u_out: zero,
+            motion: None,
+            end: None,
+        }.oriented()
+    }
+
+    /// Recomputes the viewport from the camera's position and orientation.
+    fn oriented(self) -> Camera {
+        let start = self.viewport(self.lookfrom, self.lookat);
+        let end = self.motion.map(|(lookfrom, lookat)| self.viewport(lookfrom, lookat));
+        Camera {
+            origin: start.origin,
+            horizontal: start.horizontal,
+            vertical: start.vertical,
+            lower_left_corner: start.lower_left_corner,
+            u_horizontal: start.u_horizontal,
+            u_vertical: start.u_vertical,
+            u_out: start.u_out,
+            end,
+            ..self
+        }
+    }
+
+    fn viewport(&self, lookfrom: Tuple<Point>, lookat: Tuple<Point>) -> Viewport {
         // Figure out the camera plane.
-        let up = Tuple::vector(0., 1., 0.);
-        let u_out = (lookat - lookfrom).unit();
-        let u_horizontal = up.cross(&u_out).unit();
-        let u_vertical = u_out.cross(&u_horizontal).unit();
+        let (u_horizontal, u_vertical, u_out) = view_basis(lookat - lookfrom, self.up, self.roll);
 
         // Find the viewport size based on aspect ratio and FoV.
         // Assume it is 1 * `out` in front of `lookfrom`.
-        let viewport_height = 2. * (fov_radians/2.).tan();
-        let viewport_width = aspect_ratio * viewport_height;
+        let viewport_height = 2. * (self.fov_radians/2.).tan();
+        let viewport_width = self.aspect_ratio * viewport_height;
 
-        let focus_distance = focus_distance.unwrap_or_else(||
+        let focus_distance = self.focus_distance.unwrap_or_else(||
             (lookfrom - lookat).magnitude());
         let horizontal = u_horizontal * viewport_width * focus_distance;
         let vertical = u_vertical * viewport_height * focus_distance;
         let lower_left_corner = lookfrom + u_out * focus_distance - horizontal * 0.5 - vertical * 0.5;
-        Camera {
-            origin: lookfrom,
+
+        // An eye off to the side keeps looking the same way, but shifts its
+        // viewport so its rays cross the centre eye's at the convergence distance.
+        let eye = u_horizontal * self.eye_offset;
+        let viewport_shift = eye * (1. - focus_distance / self.convergence_distance);
+        Viewport {
+            origin: lookfrom + eye,
             horizontal,
             vertical,
-            lower_left_corner,
-            aperture_radius,
+            lower_left_corner: lower_left_corner + viewport_shift,
             u_horizontal,
             u_vertical,
+            u_out,
         }
     }
 
+    /// The viewport at `time`, between where the camera starts at time 0
+    /// and where it ends up at time 1.
+    fn viewport_at(&self, time: f64) -> Viewport {
+        let start = Viewport {
+            origin: self.origin,
+            horizontal: self.horizontal,
+            vertical: self.vertical,
+            lower_left_corner: self.lower_left_corner,
+            u_horizontal: self.u_horizontal,
+            u_vertical: self.u_vertical,
+            u_out: self.u_out,
+        };
+        let Some(end) = self.end else { return start };
+        let t = time.clamp(0., 1.);
+        let vector = |a: Tuple<Vector>, b: Tuple<Vector>| a + (b - a) * t;
+        let point = |a: Tuple<Point>, b: Tuple<Point>| a + (b - a) * t;
+        Viewport {
+            origin: point(start.origin, end.origin),
+            horizontal: vector(start.horizontal, end.horizontal),
+            vertical: vector(start.vertical, end.vertical),
+            lower_left_corner: point(start.lower_left_corner, end.lower_left_corner),
+            u_horizontal: vector(start.u_horizontal, end.u_horizontal).unit(),
+            u_vertical: vector(start.u_vertical, end.u_vertical).unit(),
+            u_out: vector(start.u_out, end.u_out).unit(),
+        }
+    }
+
+    /// Moves the camera while the shutter is open, so that by time 1 it
+    /// is at `lookfrom` looking at `lookat`.
+    pub fn with_motion(self, lookfrom: Tuple<Point>, lookat: Tuple<Point>) -> Camera {
+        Camera { motion: Some((lookfrom, lookat)), ..self }.oriented()
+    }
+
+    /// Points the top of the image as close to `up` as the view direction allows.
+    pub fn with_up(self, up: Tuple<Vector>) -> Camera {
+        Camera { up, ..self }.oriented()
+    }
+
+    /// Rolls the camera about its view direction, in radians.
+    pub fn with_roll(self, roll: f64) -> Camera {
+        Camera { roll, ..self }.oriented()
+    }
+
+    /// Moves the eye `offset` to the right (negative for left) of where it
+    /// was, with an off-axis view that lines up with the original one at
+    /// `convergence_distance`.
+    pub fn with_eye_offset(self, offset: f64, convergence_distance: f64) -> Camera {
+        Camera { eye_offset: offset, convergence_distance, ..self }.oriented()
+    }
+
+    /// Moves the plane of focus; the field of view stays the same.
+    pub fn with_focus_distance(self, focus_distance: f64) -> Camera {
+        Camera { focus_distance: Some(focus_distance), ..self }.oriented()
+    }
+
+    /// Focuses the camera, looking for pixel targets among `objects`.
+    pub fn with_focus(self, focus: Focus, objects: &ObjectGroup) -> Result<Camera> {
+        let distance = match focus {
+            Focus::Distance(distance) => distance,
+            Focus::Point(point) => self.depth_of(&point),
+            Focus::Pixel(x, y) => self.probe_depth(x, y, objects)
+                .ok_or("Nothing to focus on at the chosen pixel")?,
+        };
+        if distance <= 0. {
+            return Err("Focus must be in front of the camera".into());
+        }
+        Ok(self.with_focus_distance(distance))
+    }
+
+    /// Distance of a point in front of the camera, along the view direction.
+    fn depth_of(&self, point: &Tuple<Point>) -> f64 {
+        (point - self.lookfrom).dot(&self.u_out)
+    }
+
+    /// Depth of the nearest surface seen through x,y from the centre of the
+    /// lens, ignoring any media inside objects.
+    fn probe_depth(&self, x: f64, y: f64, objects: &ObjectGroup) -> Option<f64> {
+        let ray = match self.projection {
+            // Orthographic rays don't go through the lens.
+            Projection::Orthographic { .. } => self.cast_ray(x, y),
+            Projection::Perspective => {
+                let destination = self.lower_left_corner + self.horizontal * x + self.vertical * y;
+                Ray::new(self.origin, destination - self.origin)
+            }
+        };
+        let nearest = objects
+            .iter()
+            .flat_map(|object| {
+                let local_ray = ray.transform(&object.pose_at(ray.time).inverse);
+                intersect_shape(&local_ray, object)
+            })
+            .map(|i| i.t)
+            .filter(|t| *t > 0.)
+            .min_by(f64::total_cmp)?;
+        Some(self.depth_of(&ray.position(nearest)))
+    }
+
+    /// Shapes the lens opening, and with it the out-of-focus blur.
+    pub fn with_aperture(self, aperture: Aperture) -> Camera {
+        Camera { aperture, ..self }
+    }
+
+    /// Narrows the aperture horizontally by `squeeze`, giving the tall
+    /// oval blur of an anamorphic lens.
+    pub fn with_anamorphic_squeeze(self, squeeze: f64) -> Camera {
+        Camera { anamorphic_squeeze: squeeze, ..self }
+    }
+
+    pub fn with_projection(self, projection: Projection) -> Camera {
+        Camera { projection, ..self }
+    }
+
     /// Given x,y in [0, 1], cast ray at the corresponding viewport coordinate.
     pub fn cast_ray(&self, x: f64, y: f64) -> Ray {
+        self.cast_ray_at(x, y, 0.)
+    }
+
+    /// Like `cast_ray`, with the camera where it is at `time`.
+    pub fn cast_ray_at(&self, x: f64, y: f64, time: f64) -> Ray {
+        let view = self.viewport_at(time);
+        if let Projection::Orthographic { view_width } = self.projection {
+            let view_height = view_width / self.aspect_ratio;
+            let origin = view.origin
+                + view.u_horizontal * ((x - 0.5) * view_width)
+                + view.u_vertical * ((y - 0.5) * view_height);
+            return Ray::new(origin, view.u_out).with_time(time);
+        }
+
         // We pick a random point on the aperture, and pretend the light goes through that.
-        let r = Tuple::random_in_unit_disc();
-        let offset = (self.u_horizontal * r.x + self.u_vertical * r.y) * self.aperture_radius;
+        let (ax, ay) = self.aperture.sample();
+        let offset = (view.u_horizontal * (ax / self.anamorphic_squeeze) + view.u_vertical * ay)
+            * self.aperture_radius;
 
-        let destination = self.lower_left_corner + self.horizontal * x + self.vertical * y;
+        let destination = view.lower_left_corner + view.horizontal * x + view.vertical * y;
         Ray::new(
-            self.origin + offset,
-            destination - self.origin - offset,
+            view.origin + offset,
+            destination - view.origin - offset,
+        ).with_time(time)
+    }
+}
+
+impl CameraModel for Camera {
+    fn cast_ray(&self, x: f64, y: f64, time: f64) -> Option<Ray> {
+        Some(self.cast_ray_at(x, y, time))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eq;
+
+    fn camera() -> Camera {
+        Camera::new(
+            Tuple::point(0., 0., -5.),
+            Tuple::point(0., 0., 0.),
+            2.,
+            std::f64::consts::FRAC_PI_2,
+            None,
+            0.,
         )
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn perspective_rays_start_at_the_eye() {
+        let camera = camera();
+        let ray = camera.cast_ray(0., 1.);
+        assert_eq!(ray.origin, Tuple::point(0., 0., -5.));
+        assert_eq!(ray.direction.unit(), Tuple::vector(-2., 1., 1.).unit());
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let camera = camera().with_projection(Projection::Orthographic { view_width: 4. });
+        let corner = camera.cast_ray(0., 0.);
+        let centre = camera.cast_ray(0.5, 0.5);
+        assert_eq!(centre.origin, Tuple::point(0., 0., -5.));
+        assert_eq!(corner.origin, Tuple::point(-2., -1., -5.));
+        assert_eq!(corner.direction, centre.direction);
+        assert_eq!(corner.direction, Tuple::vector(0., 0., 1.));
+    }
+
+    #[test]
+    fn anamorphic_squeeze_narrows_the_aperture() {
+        let camera = Camera::new(
+            Tuple::point(0., 0., -5.),
+            Tuple::point(0., 0., 0.),
+            1.,
+            std::f64::consts::FRAC_PI_2,
+            None,
+            1.,
+        ).with_aperture(Aperture::polygon(4, std::f64::consts::FRAC_PI_4))
+            .with_anamorphic_squeeze(2.);
+        let half_side = std::f64::consts::FRAC_1_SQRT_2;
+        for _ in 0..100 {
+            let origin = camera.cast_ray(0.5, 0.5).origin;
+            assert!(origin.x.abs() <= half_side / 2. + 1e-9);
+            assert!(origin.y.abs() <= half_side + 1e-9);
+        }
+    }
+
+    #[test]
+    fn focus_distance_moves_the_viewport_not_the_view() {
+        let camera = camera().with_focus_distance(10.);
+        let ray = camera.cast_ray(0., 1.);
+        assert_eq!(ray.direction.unit(), Tuple::vector(-2., 1., 1.).unit());
+        assert_eq!(camera.lower_left_corner, Tuple::point(-20., -10., 5.));
+    }
+
+    #[test]
+    fn focus_on_a_point_uses_its_depth() {
+        let focused = camera()
+            .with_focus(Focus::Point(Tuple::point(3., 1., 2.)), &ObjectGroup::new())
+            .unwrap();
+        assert!(eq(focused.focus_distance.unwrap(), 7.));
+        assert!(camera().with_focus(Focus::Distance(-1.), &ObjectGroup::new()).is_err());
+    }
+
+    #[test]
+    fn focus_on_a_pixel_probes_the_scene() {
+        let mut objects = ObjectGroup::new();
+        objects.add(crate::object::Object::new_sphere().with_transform(
+            crate::transformations::translation(0., 0., 3.)));
+        let focused = camera().with_focus(Focus::Pixel(0.5, 0.5), &objects).unwrap();
+        assert!(eq(focused.focus_distance.unwrap(), 7.));
+        assert!(camera().with_focus(Focus::Pixel(0., 0.), &objects).is_err());
+    }
+
+    #[test]
+    fn orthographic_focus_probes_along_the_view() {
+        let mut objects = ObjectGroup::new();
+        objects.add(crate::object::Object::new_sphere().with_transform(
+            crate::transformations::translation(1.5, 0., 3.)));
+        let focused = camera()
+            .with_projection(Projection::Orthographic { view_width: 4. })
+            .with_focus(Focus::Pixel(0.875, 0.5), &objects)
+            .unwrap();
+        assert!(eq(focused.focus_distance.unwrap(), 7.));
+    }
+
+    #[test]
+    fn moving_cameras_cast_rays_from_where_they_are() {
+        let camera = camera().with_motion(Tuple::point(2., 0., -5.), Tuple::point(2., 0., 0.));
+        let start = camera.cast_ray_at(0.5, 0.5, 0.);
+        let halfway = camera.cast_ray_at(0.5, 0.5, 0.5);
+        assert_eq!(start.origin, Tuple::point(0., 0., -5.));
+        assert_eq!(halfway.origin, Tuple::point(1., 0., -5.));
+        assert_eq!(halfway.direction.unit(), Tuple::vector(0., 0., 1.));
+        assert_eq!(halfway.time, 0.5);
+    }
+
+    #[test]
+    fn looking_straight_down_falls_back_to_another_up() {
+        let camera = Camera::new(
+            Tuple::point(0., 5., 0.),
+            Tuple::point(0., 0., 0.),
+            1.,
+            std::f64::consts::FRAC_PI_2,
+            None,
+            0.,
+        );
+        let ray = camera.cast_ray(0.5, 0.5);
+        assert_eq!(ray.direction.unit(), Tuple::vector(0., -1., 0.));
+        assert!(camera.cast_ray(0., 0.).direction.x.is_finite());
+        assert!(eq(camera.horizontal.dot(&camera.vertical), 0.));
+    }
+
+    #[test]
+    fn up_vector_sets_the_top_of_the_image() {
+        let camera = camera().with_up(Tuple::vector(1., 0., 0.));
+        let top = camera.cast_ray(0.5, 1.).direction.unit();
+        assert_eq!(top, Tuple::vector(1., 0., 1.).unit());
+    }
+
+    #[test]
+    fn roll_turns_the_image_clockwise() {
+        let camera = camera().with_roll(std::f64::consts::FRAC_PI_2);
+        // The top of the image now points where its right used to.
+        let top = camera.cast_ray(0.5, 1.).direction.unit();
+        assert_eq!(top, Tuple::vector(1., 0., 1.).unit());
+    }
+}
@@ -0,0 +1,142 @@
+use std::f64::consts::PI;
+use rand::Rng;
+
+use crate::{Result, hdr::HdrImage, distribution::Distribution2D, tuples::Tuple};
+
+
+/// The shape of a lens opening, which out-of-focus highlights take on.
+///
+/// Every shape fits within [-1, 1]², and is scaled by the camera's
+/// aperture radius.
+#[derive(Debug, Clone)]
+pub enum Aperture {
+    /// The unit disc.
+    Circle,
+    /// A regular polygon inscribed in the unit circle, as formed by
+    /// `blades` straight diaphragm blades, turned by `rotation` radians.
+    Polygon { blades: usize, rotation: f64 },
+    /// An arbitrary mask, open where it is bright.
+    Mask(ApertureMask),
+}
+
+impl Aperture {
+    /// Polygons need at least three blades; fewer give a circle.
+    pub fn polygon(blades: usize, rotation: f64) -> Aperture {
+        match blades {
+            0..=2 => Aperture::Circle,
+            _ => Aperture::Polygon { blades, rotation },
+        }
+    }
+
+    /// A point on the aperture, uniformly distributed over its open area.
+    /// Partly open parts of a mask are picked in proportion to how open they are.
+    pub fn sample(&self) -> (f64, f64) {
+        match self {
+            Aperture::Circle => {
+                let p = Tuple::random_in_unit_disc();
+                (p.x, p.y)
+            }
+            Aperture::Polygon { blades, rotation } => {
+                // All the triangles fanning out from the centre are the same size.
+                let mut rng = crate::rng::thread_rng();
+                let side = rng.gen_range(0..*blades);
+                let corner = |i: usize| {
+                    let angle = rotation + 2. * PI * i as f64 / *blades as f64;
+                    (angle.cos(), angle.sin())
+                };
+                let (a, b) = (corner(side), corner(side + 1));
+                let r = rng.gen_range(0.0..1.0f64).sqrt();
+                let t = rng.gen_range(0.0..1.0);
+                let (wa, wb) = (r * (1. - t), r * t);
+                (wa * a.0 + wb * b.0, wa * a.1 + wb * b.1)
+            }
+            Aperture::Mask(mask) => mask.sample(),
+        }
+    }
+}
+
+
+/// A grayscale aperture image, fitted into [-1, 1]² keeping its aspect ratio.
+#[derive(Debug, Clone)]
+pub struct ApertureMask {
+    distribution: Distribution2D,
+    /// Half the extent of the mask along x and y.
+    extent: (f64, f64),
+}
+
+impl ApertureMask {
+    pub fn new(image: &HdrImage) -> Result<ApertureMask> {
+        let mut weights = Vec::with_capacity(image.width * image.height);
+        for y in 0..image.height {
+            for x in 0..image.width {
+                weights.push(image.pixel_at(x, y).luminance().max(0.));
+            }
+        }
+        if weights.iter().all(|w| *w == 0.) {
+            return Err("Aperture image is entirely closed".into());
+        }
+        let longest = image.width.max(image.height) as f64;
+        Ok(ApertureMask {
+            distribution: Distribution2D::new(&weights, image.width, image.height),
+            extent: (image.width as f64 / longest, image.height as f64 / longest),
+        })
+    }
+
+    /// Loads a mask from any image `HdrImage::load` reads, such as a `.pgm`.
+    pub fn load(path: &str) -> Result<ApertureMask> {
+        ApertureMask::new(&HdrImage::load(path)?)
+    }
+
+    fn sample(&self) -> (f64, f64) {
+        let mut rng = crate::rng::thread_rng();
+        let ((x, y), _) = self.distribution.sample(rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0));
+        // Image rows run from the top.
+        ((2. * x - 1.) * self.extent.0, (1. - 2. * y) * self.extent.1)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors::Color;
+
+    #[test]
+    fn polygon_samples_stay_inside_the_polygon() {
+        // A square with its corners on the axes, |x| + |y| <= 1.
+        let aperture = Aperture::polygon(4, 0.);
+        let mut mean_abs_x = 0.;
+        let n = 20000;
+        for _ in 0..n {
+            let (x, y) = aperture.sample();
+            assert!(x.abs() + y.abs() <= 1. + 1e-9);
+            mean_abs_x += x.abs() / n as f64;
+        }
+        // Uniform over the square: E|x| = 1/3.
+        assert!((mean_abs_x - 1. / 3.).abs() < 0.01);
+    }
+
+    #[test]
+    fn too_few_blades_give_a_circle() {
+        assert!(matches!(Aperture::polygon(2, 0.), Aperture::Circle));
+    }
+
+    #[test]
+    fn mask_samples_only_open_pixels() {
+        // Open only in the top right quarter.
+        let black = Color::new(0., 0., 0.);
+        let white = Color::new(1., 1., 1.);
+        let image = HdrImage::new(2, 2, vec![black, white, black, black]).unwrap();
+        let aperture = Aperture::Mask(ApertureMask::new(&image).unwrap());
+        for _ in 0..100 {
+            let (x, y) = aperture.sample();
+            assert!((0. ..=1.).contains(&x) && (0. ..=1.).contains(&y));
+        }
+    }
+
+    #[test]
+    fn closed_masks_are_rejected() {
+        let image = HdrImage::new(1, 1, vec![Color::new(0., 0., 0.)]).unwrap();
+        assert!(ApertureMask::new(&image).is_err());
+    }
+}
@@ -0,0 +1,371 @@
+use std::f64::consts::PI;
+
+use crate::{tuples::{Tuple, Point, Vector}, ray::Ray};
+use super::{CameraModel, view_basis};
+
+
+/// Where a panoramic camera sits and which way it faces, and where it
+/// has moved to by time 1 if it moves while the shutter is open.
+#[derive(Debug, Clone)]
+struct Frame {
+    origin: Tuple<Point>,
+    right: Tuple<Vector>,
+    up: Tuple<Vector>,
+    forward: Tuple<Vector>,
+    orientation: (Tuple<Vector>, f64),
+    end: Option<Box<Frame>>,
+}
+
+impl Frame {
+    fn new(lookfrom: Tuple<Point>, lookat: Tuple<Point>) -> Frame {
+        let orientation = (Tuple::vector(0., 1., 0.), 0.);
+        let (right, up, forward) = view_basis(lookat - lookfrom, orientation.0, orientation.1);
+        Frame { origin: lookfrom, right, up, forward, orientation, end: None }
+    }
+
+    fn oriented(self, up: Tuple<Vector>, roll: f64) -> Frame {
+        let (right_axis, up_axis, forward) = view_basis(self.forward, up, roll);
+        let end = self.end.map(|end| Box::new(end.oriented(up, roll)));
+        Frame { right: right_axis, up: up_axis, forward, orientation: (up, roll), end, ..self }
+    }
+
+    fn moving_to(self, lookfrom: Tuple<Point>, lookat: Tuple<Point>) -> Frame {
+        let (up, roll) = self.orientation;
+        let end = Frame::new(lookfrom, lookat).oriented(up, roll);
+        Frame { end: Some(Box::new(end)), ..self }
+    }
+
+    fn ray(&self, right: f64, up: f64, forward: f64, time: f64) -> Ray {
+        let (origin, right_axis, up_axis, forward_axis) = match &self.end {
+            None => (self.origin, self.right, self.up, self.forward),
+            Some(end) => {
+                let t = time.clamp(0., 1.);
+                let vector = |a: Tuple<Vector>, b: Tuple<Vector>| (a + (b - a) * t).unit();
+                (
+                    self.origin + (end.origin - self.origin) * t,
+                    vector(self.right, end.right),
+                    vector(self.up, end.up),
+                    vector(self.forward, end.forward),
+                )
+            }
+        };
+        Ray::new(origin, right_axis * right + up_axis * up + forward_axis * forward)
+            .with_time(time)
+    }
+}
+
+
+/// A full-sphere latitude-longitude panorama, meant for a 2:1 image.
+///
+/// The centre of the image looks towards `lookat`; the left and right
+/// edges meet behind the camera.
+pub struct Equirectangular {
+    frame: Frame,
+}
+
+impl Equirectangular {
+    pub fn new(lookfrom: Tuple<Point>, lookat: Tuple<Point>) -> Equirectangular {
+        Equirectangular { frame: Frame::new(lookfrom, lookat) }
+    }
+
+    /// Points the camera's up as close to `up` as the view direction
+    /// allows, then rolls it about the view direction by `roll` radians.
+    pub fn with_orientation(self, up: Tuple<Vector>, roll: f64) -> Equirectangular {
+        Equirectangular { frame: self.frame.oriented(up, roll) }
+    }
+
+    /// Moves the camera while the shutter is open, so that by time 1 it
+    /// is at `lookfrom` looking at `lookat`.
+    pub fn with_motion(self, lookfrom: Tuple<Point>, lookat: Tuple<Point>) -> Equirectangular {
+        Equirectangular { frame: self.frame.moving_to(lookfrom, lookat) }
+    }
+}
+
+impl CameraModel for Equirectangular {
+    fn cast_ray(&self, x: f64, y: f64, time: f64) -> Option<Ray> {
+        let longitude = (x - 0.5) * 2. * PI;
+        let latitude = (y - 0.5) * PI;
+        Some(self.frame.ray(
+            latitude.cos() * longitude.sin(),
+            latitude.sin(),
+            latitude.cos() * longitude.cos(),
+            time,
+        ))
+    }
+}
+
+
+/// How a fisheye lens maps angles from its axis onto the image circle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FisheyeMapping {
+    /// Distance from the centre is proportional to the angle.
+    Equidistant,
+    /// Area on the image is proportional to solid angle.
+    Equisolid,
+}
+
+/// A fisheye lens with its image circle inscribed in the frame.
+pub struct Fisheye {
+    frame: Frame,
+    aspect_ratio: f64,
+    fov_radians: f64,
+    mapping: FisheyeMapping,
+}
+
+impl Fisheye {
+    /// `fov_radians` is the full angle across the image circle, up to 2π.
+    pub fn new(
+        lookfrom: Tuple<Point>,
+        lookat: Tuple<Point>,
+        aspect_ratio: f64,
+        fov_radians: f64,
+        mapping: FisheyeMapping,
+    ) -> Fisheye {
+        Fisheye {
+            frame: Frame::new(lookfrom, lookat),
+            aspect_ratio,
+            fov_radians: fov_radians.clamp(0., 2. * PI),
+            mapping,
+        }
+    }
+
+    /// Points the camera's up as close to `up` as the view direction
+    /// allows, then rolls it about the view direction by `roll` radians.
+    pub fn with_orientation(self, up: Tuple<Vector>, roll: f64) -> Fisheye {
+        Fisheye { frame: self.frame.oriented(up, roll), ..self }
+    }
+
+    /// Moves the camera while the shutter is open, so that by time 1 it
+    /// is at `lookfrom` looking at `lookat`.
+    pub fn with_motion(self, lookfrom: Tuple<Point>, lookat: Tuple<Point>) -> Fisheye {
+        Fisheye { frame: self.frame.moving_to(lookfrom, lookat), ..self }
+    }
+}
+
+impl CameraModel for Fisheye {
+    fn cast_ray(&self, x: f64, y: f64, time: f64) -> Option<Ray> {
+        // Coordinates in which the image circle has radius 1.
+        let (mut u, mut v) = (2. * x - 1., 2. * y - 1.);
+        if self.aspect_ratio >= 1. {
+            u *= self.aspect_ratio;
+        } else {
+            v /= self.aspect_ratio;
+        }
+        let r = (u * u + v * v).sqrt();
+        if r > 1. {
+            return None;
+        }
+        let half_fov = self.fov_radians / 2.;
+        let theta = match self.mapping {
+            FisheyeMapping::Equidistant => r * half_fov,
+            FisheyeMapping::Equisolid => 2. * (r * (half_fov / 2.).sin()).asin(),
+        };
+        let (cos_phi, sin_phi) = if r > 0. { (u / r, v / r) } else { (1., 0.) };
+        Some(self.frame.ray(
+            theta.sin() * cos_phi,
+            theta.sin() * sin_phi,
+            theta.cos(),
+            time,
+        ))
+    }
+}
+
+
+/// All six 90° views around the camera, laid out three across and two
+/// down, for a 3:2 image:
+///
+/// ```text
+/// +-------+-------+-------+
+/// | right | left  |  up   |
+/// +-------+-------+-------+
+/// | down  | front | back  |
+/// +-------+-------+-------+
+/// ```
+///
+/// The side faces keep the camera's up; the up and down faces have
+/// the back and the front at the top of the image, respectively.
+pub struct Cubemap {
+    frame: Frame,
+}
+
+impl Cubemap {
+    pub fn new(lookfrom: Tuple<Point>, lookat: Tuple<Point>) -> Cubemap {
+        Cubemap { frame: Frame::new(lookfrom, lookat) }
+    }
+
+    /// Points the camera's up as close to `up` as the view direction
+    /// allows, then rolls it about the view direction by `roll` radians.
+    pub fn with_orientation(self, up: Tuple<Vector>, roll: f64) -> Cubemap {
+        Cubemap { frame: self.frame.oriented(up, roll) }
+    }
+
+    /// Moves the camera while the shutter is open, so that by time 1 it
+    /// is at `lookfrom` looking at `lookat`.
+    pub fn with_motion(self, lookfrom: Tuple<Point>, lookat: Tuple<Point>) -> Cubemap {
+        Cubemap { frame: self.frame.moving_to(lookfrom, lookat) }
+    }
+}
+
+impl CameraModel for Cubemap {
+    fn cast_ray(&self, x: f64, y: f64, time: f64) -> Option<Ray> {
+        let column = ((x * 3.) as usize).min(2);
+        let row = if y >= 0.5 { 0 } else { 1 };
+        // Position within the face, from -1 to 1.
+        let a = (x * 3. - column as f64) * 2. - 1.;
+        let b = (y * 2. - (1 - row) as f64) * 2. - 1.;
+        // Each face's forward, right and up, in the camera's (right, up, forward) frame.
+        let (forward, right, up) = match (row, column) {
+            (0, 0) => ([1., 0., 0.], [0., 0., -1.], [0., 1., 0.]),
+            (0, 1) => ([-1., 0., 0.], [0., 0., 1.], [0., 1., 0.]),
+            (0, _) => ([0., 1., 0.], [1., 0., 0.], [0., 0., -1.]),
+            (_, 0) => ([0., -1., 0.], [1., 0., 0.], [0., 0., 1.]),
+            (_, 1) => ([0., 0., 1.], [1., 0., 0.], [0., 1., 0.]),
+            (_, _) => ([0., 0., -1.], [-1., 0., 0.], [0., 1., 0.]),
+        };
+        let d = |i: usize| forward[i] + right[i] * a + up[i] * b;
+        Some(self.frame.ray(d(0), d(1), d(2), time))
+    }
+}
+
+
+/// A choice of panoramic camera, as picked on the command line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Panorama {
+    Equirectangular,
+    Fisheye { mapping: FisheyeMapping, fov_radians: f64 },
+    Cubemap,
+}
+
+impl Panorama {
+    /// `end`, if given, is the `(lookfrom, lookat)` the camera moves to by time 1.
+    pub fn camera(
+        self,
+        lookfrom: Tuple<Point>,
+        lookat: Tuple<Point>,
+        end: Option<(Tuple<Point>, Tuple<Point>)>,
+        up: Tuple<Vector>,
+        roll: f64,
+        aspect_ratio: f64,
+    ) -> Box<dyn CameraModel> {
+        match self {
+            Panorama::Equirectangular => {
+                let camera = Equirectangular::new(lookfrom, lookat).with_orientation(up, roll);
+                match end {
+                    Some((lookfrom, lookat)) => Box::new(camera.with_motion(lookfrom, lookat)),
+                    None => Box::new(camera),
+                }
+            }
+            Panorama::Fisheye { mapping, fov_radians } => {
+                let camera = Fisheye::new(lookfrom, lookat, aspect_ratio, fov_radians, mapping)
+                    .with_orientation(up, roll);
+                match end {
+                    Some((lookfrom, lookat)) => Box::new(camera.with_motion(lookfrom, lookat)),
+                    None => Box::new(camera),
+                }
+            }
+            Panorama::Cubemap => {
+                let camera = Cubemap::new(lookfrom, lookat).with_orientation(up, roll);
+                match end {
+                    Some((lookfrom, lookat)) => Box::new(camera.with_motion(lookfrom, lookat)),
+                    None => Box::new(camera),
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eye() -> (Tuple<Point>, Tuple<Point>) {
+        (Tuple::point(0., 0., 0.), Tuple::point(0., 0., 1.))
+    }
+
+    fn direction(camera: &impl CameraModel, x: f64, y: f64) -> Tuple<Vector> {
+        camera.cast_ray(x, y, 0.).unwrap().direction.unit()
+    }
+
+    #[test]
+    fn equirectangular_covers_the_sphere() {
+        let (from, at) = eye();
+        let camera = Equirectangular::new(from, at);
+        assert_eq!(direction(&camera, 0.5, 0.5), Tuple::vector(0., 0., 1.));
+        assert_eq!(direction(&camera, 0.75, 0.5), Tuple::vector(1., 0., 0.));
+        assert_eq!(direction(&camera, 0., 0.5), Tuple::vector(0., 0., -1.));
+        assert_eq!(direction(&camera, 0.3, 1.), Tuple::vector(0., 1., 0.));
+    }
+
+    #[test]
+    fn fisheye_edge_is_at_half_the_field_of_view() {
+        let (from, at) = eye();
+        for mapping in [FisheyeMapping::Equidistant, FisheyeMapping::Equisolid] {
+            let camera = Fisheye::new(from, at, 1., PI, mapping);
+            assert_eq!(direction(&camera, 0.5, 0.5), Tuple::vector(0., 0., 1.));
+            assert_eq!(direction(&camera, 1., 0.5), Tuple::vector(1., 0., 0.));
+            assert!(camera.cast_ray(0., 0., 0.).is_none());
+        }
+    }
+
+    #[test]
+    fn fisheye_mappings_differ_inside_the_circle() {
+        let (from, at) = eye();
+        let angle = |mapping| {
+            let camera = Fisheye::new(from, at, 1., PI, mapping);
+            direction(&camera, 0.75, 0.5).z.acos()
+        };
+        assert!((angle(FisheyeMapping::Equidistant) - PI / 4.).abs() < 1e-9);
+        // Equisolid: r = sin(θ/2) / sin(π/4), so θ = 2 asin(0.5 sin(π/4)).
+        let expected = 2. * (0.5 * (PI / 4.).sin()).asin();
+        assert!((angle(FisheyeMapping::Equisolid) - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn equirectangular_follows_the_up_vector() {
+        let camera = Equirectangular::new(Tuple::point(0., 0., 0.), Tuple::point(0., 1., 0.))
+            .with_orientation(Tuple::vector(0., 0., 1.), 0.);
+        assert_eq!(direction(&camera, 0.5, 0.5), Tuple::vector(0., 1., 0.));
+        assert_eq!(direction(&camera, 0.5, 1.), Tuple::vector(0., 0., 1.));
+    }
+
+    #[test]
+    fn cubemap_faces_look_along_the_axes() {
+        let (from, at) = eye();
+        let camera = Cubemap::new(from, at);
+        let centre = |column: f64, row: f64| {
+            direction(&camera, (column + 0.5) / 3., 1. - (row + 0.5) / 2.)
+        };
+        assert_eq!(centre(0., 0.), Tuple::vector(1., 0., 0.));
+        assert_eq!(centre(1., 0.), Tuple::vector(-1., 0., 0.));
+        assert_eq!(centre(2., 0.), Tuple::vector(0., 1., 0.));
+        assert_eq!(centre(0., 1.), Tuple::vector(0., -1., 0.));
+        assert_eq!(centre(1., 1.), Tuple::vector(0., 0., 1.));
+        assert_eq!(centre(2., 1.), Tuple::vector(0., 0., -1.));
+    }
+
+    #[test]
+    fn cubemap_faces_share_edges() {
+        let (from, at) = eye();
+        let camera = Cubemap::new(from, at);
+        // The right edge of the front face meets the left edge of the right face.
+        let front_edge = direction(&camera, 2. / 3. - 1e-12, 0.25);
+        let right_edge = direction(&camera, 0., 0.75);
+        assert_eq!(front_edge, right_edge);
+    }
+
+    #[test]
+    fn panoramas_move_while_the_shutter_is_open() {
+        let (from, at) = eye();
+        let camera = Equirectangular::new(from, at)
+            .with_motion(Tuple::point(2., 0., 0.), Tuple::point(3., 0., 0.));
+        let start = camera.cast_ray(0.5, 0.5, 0.).unwrap();
+        assert_eq!(start.origin, from);
+        assert_eq!(start.direction.unit(), Tuple::vector(0., 0., 1.));
+        let end = camera.cast_ray(0.5, 0.5, 1.).unwrap();
+        assert_eq!(end.origin, Tuple::point(2., 0., 0.));
+        assert_eq!(end.direction.unit(), Tuple::vector(1., 0., 0.));
+        let halfway = camera.cast_ray(0.5, 0.5, 0.5).unwrap();
+        assert_eq!(halfway.origin, Tuple::point(1., 0., 0.));
+    }
+}
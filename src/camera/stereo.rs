@@ -0,0 +1,105 @@
+use crate::ray::Ray;
+use super::{Camera, CameraModel};
+
+
+/// How the two eyes share one image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StereoLayout {
+    /// Left eye in the left half, right eye in the right half.
+    SideBySide,
+    /// Left eye in the top half, right eye in the bottom half.
+    TopBottom,
+}
+
+impl StereoLayout {
+    /// Aspect ratio of the whole image, given that of each eye.
+    pub fn image_aspect_ratio(&self, eye_aspect_ratio: f64) -> f64 {
+        match self {
+            StereoLayout::SideBySide => eye_aspect_ratio * 2.,
+            StereoLayout::TopBottom => eye_aspect_ratio / 2.,
+        }
+    }
+}
+
+
+/// A pair of cameras a little way apart, rendered into one image.
+pub struct StereoCamera {
+    left: Camera,
+    right: Camera,
+    layout: StereoLayout,
+}
+
+impl StereoCamera {
+    /// Places the eyes either side of `camera`, `interpupillary_distance`
+    /// apart, with zero parallax at `convergence_distance`. Objects nearer
+    /// than that appear in front of the screen.
+    pub fn new(
+        camera: Camera,
+        interpupillary_distance: f64,
+        convergence_distance: f64,
+        layout: StereoLayout,
+    ) -> StereoCamera {
+        let half = interpupillary_distance / 2.;
+        StereoCamera {
+            left: camera.clone().with_eye_offset(-half, convergence_distance),
+            right: camera.with_eye_offset(half, convergence_distance),
+            layout,
+        }
+    }
+}
+
+impl CameraModel for StereoCamera {
+    fn cast_ray(&self, x: f64, y: f64, time: f64) -> Option<Ray> {
+        let ray = match self.layout {
+            StereoLayout::SideBySide if x < 0.5 => self.left.cast_ray_at(x * 2., y, time),
+            StereoLayout::SideBySide => self.right.cast_ray_at(x * 2. - 1., y, time),
+            StereoLayout::TopBottom if y >= 0.5 => self.left.cast_ray_at(x, y * 2. - 1., time),
+            StereoLayout::TopBottom => self.right.cast_ray_at(x, y * 2., time),
+        };
+        Some(ray)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tuples::Tuple;
+
+    fn stereo(layout: StereoLayout) -> StereoCamera {
+        let camera = Camera::new(
+            Tuple::point(0., 0., -5.),
+            Tuple::point(0., 0., 0.),
+            1.,
+            std::f64::consts::FRAC_PI_2,
+            None,
+            0.,
+        );
+        StereoCamera::new(camera, 0.2, 10., layout)
+    }
+
+    #[test]
+    fn eyes_sit_either_side_of_the_camera() {
+        let camera = stereo(StereoLayout::SideBySide);
+        let left = camera.cast_ray(0.25, 0.5, 0.).unwrap();
+        let right = camera.cast_ray(0.75, 0.5, 0.).unwrap();
+        assert_eq!(left.origin, Tuple::point(-0.1, 0., -5.));
+        assert_eq!(right.origin, Tuple::point(0.1, 0., -5.));
+    }
+
+    #[test]
+    fn eyes_converge_at_the_convergence_distance() {
+        let camera = stereo(StereoLayout::TopBottom);
+        let left = camera.cast_ray(0.5, 0.75, 0.).unwrap();
+        let right = camera.cast_ray(0.5, 0.25, 0.).unwrap();
+        let at_convergence = |ray: &Ray| ray.origin + ray.direction * (10. / ray.direction.z);
+        assert_eq!(at_convergence(&left), Tuple::point(0., 0., 5.));
+        assert_eq!(at_convergence(&right), Tuple::point(0., 0., 5.));
+    }
+
+    #[test]
+    fn layouts_change_the_image_shape() {
+        assert_eq!(StereoLayout::SideBySide.image_aspect_ratio(1.5), 3.);
+        assert_eq!(StereoLayout::TopBottom.image_aspect_ratio(1.5), 0.75);
+    }
+}
@@ -0,0 +1,211 @@
+use crate::{Result, cli::Config, film::Film};
+
+const MAGIC: &[u8] = b"crayfish checkpoint 3\n";
+
+
+/// How far a render has got, with everything needed to carry on from
+/// there and end up with the same image as if it had never stopped.
+///
+/// Every pixel of every pass is sampled from its own random stream,
+/// derived from the seed, so the seed and position stand in for the
+/// state of the random number generator.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub seed: u64,
+    /// The pass under way, counting from 1.
+    pub pass: usize,
+    /// The next row of that pass to render, counted from the bottom.
+    pub row: usize,
+    /// Seconds spent rendering so far, which count towards the time limit.
+    pub elapsed: f64,
+    /// `Config::scene_fingerprint` of the render.
+    pub fingerprint: u64,
+    pub settings: SamplingSettings,
+    pub film: Film,
+}
+
+
+/// The settings that decide which samples a render takes. A render can
+/// only carry on from a checkpoint made with the same ones.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SamplingSettings {
+    pub rays_per_pixel: usize,
+    pub samples_per_pass: Option<usize>,
+    pub row_range: (usize, usize),
+    pub noise_threshold: Option<f64>,
+    pub min_samples: usize,
+    pub max_samples: usize,
+    pub time_limit: Option<f64>,
+}
+
+impl SamplingSettings {
+    pub fn from_config(config: &Config) -> SamplingSettings {
+        SamplingSettings {
+            rays_per_pixel: config.rays_per_pixel,
+            samples_per_pass: config.samples_per_pass,
+            row_range: config.row_range,
+            noise_threshold: config.noise_threshold,
+            min_samples: config.min_samples,
+            max_samples: config.max_samples,
+            time_limit: config.time_limit,
+        }
+    }
+}
+
+impl Checkpoint {
+    /// Checks that a render with `config` can carry on from here.
+    pub fn check_resumable(&self, config: &Config) -> Result<()> {
+        let width = (config.aspect_ratio * config.image_height as f64) as usize;
+        if (self.film.width, self.film.height) != (width, config.image_height) {
+            return Err("Checkpoint is for a different image size".into());
+        }
+        let (then, now) = (&self.settings, SamplingSettings::from_config(config));
+        let changed = [
+            ("seed", self.seed != config.seed),
+            ("rays_per_pixel", then.rays_per_pixel != now.rays_per_pixel),
+            ("progressive", then.samples_per_pass != now.samples_per_pass),
+            ("row_range", then.row_range != now.row_range),
+            ("noise_threshold", then.noise_threshold != now.noise_threshold),
+            ("min_samples", then.min_samples != now.min_samples),
+            ("max_samples", then.max_samples != now.max_samples),
+            ("time_limit", then.time_limit != now.time_limit),
+        ];
+        if let Some((setting, _)) = changed.iter().find(|(_, changed)| *changed) {
+            return Err(format!("Checkpoint was made with a different --{}", setting).into());
+        }
+        if self.fingerprint != config.scene_fingerprint() {
+            return Err("Checkpoint was made with different camera, quality or scene settings".into());
+        }
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend(self.seed.to_le_bytes());
+        out.extend((self.pass as u64).to_le_bytes());
+        out.extend((self.row as u64).to_le_bytes());
+        out.extend(self.elapsed.to_le_bytes());
+        out.extend(self.fingerprint.to_le_bytes());
+        // Settings left unset are written as 0, which they can't be when set.
+        let settings = &self.settings;
+        for v in [
+            settings.rays_per_pixel,
+            settings.samples_per_pass.unwrap_or(0),
+            settings.row_range.0,
+            settings.row_range.1,
+            settings.min_samples,
+            settings.max_samples,
+        ] {
+            out.extend((v as u64).to_le_bytes());
+        }
+        out.extend(settings.noise_threshold.unwrap_or(0.).to_le_bytes());
+        out.extend(settings.time_limit.unwrap_or(0.).to_le_bytes());
+        out.extend(self.film.to_bytes());
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Checkpoint> {
+        let rest = bytes.strip_prefix(MAGIC).ok_or("Not a crayfish checkpoint")?;
+        if rest.len() < 104 {
+            return Err("Checkpoint is truncated".into());
+        }
+        let word = |i: usize| u64::from_le_bytes(rest[i * 8..i * 8 + 8].try_into().unwrap());
+        let count = |i: usize| Some(word(i) as usize).filter(|v| *v != 0);
+        let value = |i: usize| Some(f64::from_bits(word(i))).filter(|v| *v != 0.);
+        let (film, _) = Film::from_bytes(&rest[104..])?;
+        Ok(Checkpoint {
+            seed: word(0),
+            pass: word(1) as usize,
+            row: word(2) as usize,
+            elapsed: f64::from_bits(word(3)),
+            fingerprint: word(4),
+            settings: SamplingSettings {
+                rays_per_pixel: word(5) as usize,
+                samples_per_pass: count(6),
+                row_range: (word(7) as usize, word(8) as usize),
+                min_samples: word(9) as usize,
+                max_samples: word(10) as usize,
+                noise_threshold: value(11),
+                time_limit: value(12),
+            },
+            film,
+        })
+    }
+
+    /// Writes the checkpoint to `path`, by way of a temporary file so that
+    /// a render killed while saving still leaves the previous one intact.
+    pub fn save(&self, path: &str) -> Result<()> {
+        let temporary = format!("{}.tmp", path);
+        std::fs::write(&temporary, self.to_bytes())?;
+        std::fs::rename(temporary, path)?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<Checkpoint> {
+        Checkpoint::from_bytes(&std::fs::read(path)?)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors::Color;
+    use crate::cli::{cli, make_config};
+
+    fn config(options: &str) -> Config {
+        let args = format!("crayfish --aspect_ratio 1 1 --image_height 2 {}", options);
+        make_config(cli().get_matches_from(args.split_whitespace())).unwrap()
+    }
+
+    fn checkpoint(config: &Config) -> Checkpoint {
+        let mut film = Film::new(2, 2);
+        film.add_sample(1, 0, Color::new(0.25, 0.5, 1.));
+        Checkpoint {
+            seed: 12,
+            pass: 3,
+            row: 1,
+            elapsed: 2.5,
+            fingerprint: config.scene_fingerprint(),
+            settings: SamplingSettings::from_config(config),
+            film,
+        }
+    }
+
+    #[test]
+    fn checkpoints_survive_encoding() {
+        for options in ["", "--progressive 2 --noise_threshold 0.05 --time_limit 10m"] {
+            let checkpoint = checkpoint(&config(options));
+            let decoded = Checkpoint::from_bytes(&checkpoint.to_bytes()).unwrap();
+            assert_eq!(decoded.settings, checkpoint.settings);
+        }
+        let checkpoint = checkpoint(&config(""));
+        let decoded = Checkpoint::from_bytes(&checkpoint.to_bytes()).unwrap();
+        assert_eq!((decoded.seed, decoded.pass, decoded.row), (12, 3, 1));
+        assert_eq!(decoded.elapsed, 2.5);
+        assert_eq!(decoded.fingerprint, checkpoint.fingerprint);
+        assert_eq!(decoded.film.to_bytes(), checkpoint.film.to_bytes());
+        assert!(Checkpoint::from_bytes(b"P3\n1 1\n255\n0 0 0\n").is_err());
+    }
+
+    #[test]
+    fn resuming_needs_the_same_settings() {
+        let checkpoint = checkpoint(&config("--seed 12 --progressive 2"));
+        assert!(checkpoint.check_resumable(&config("--seed 12 --progressive 2")).is_ok());
+        assert_eq!(
+            checkpoint.check_resumable(&config("--seed 12 --progressive 4")).unwrap_err().to_string(),
+            "Checkpoint was made with a different --progressive"
+        );
+        assert!(checkpoint.check_resumable(&config("--seed 12 --progressive 2 --rays_per_pixel 8")).is_err());
+        assert!(checkpoint.check_resumable(&config("--progressive 2")).is_err());
+        assert_eq!(
+            checkpoint.check_resumable(&config("--seed 12 --progressive 2 --max_scatter_depth 5"))
+                .unwrap_err().to_string(),
+            "Checkpoint was made with different camera, quality or scene settings"
+        );
+        assert!(checkpoint.check_resumable(&config("--seed 12 --progressive 2 --outfile elsewhere")).is_ok());
+        let mut resized = checkpoint.clone();
+        resized.film = Film::new(2, 3);
+        assert!(resized.check_resumable(&config("--seed 12 --progressive 2")).is_err());
+    }
+}
@@ -0,0 +1,189 @@
+use crate::{Result, film::Film};
+
+const MAGIC: &[u8] = b"crayfish chunk 3\n";
+
+
+/// The rows of an image that one partial render covered, with their
+/// sample sums and counts so that chunks can be combined exactly.
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    /// Size of the whole image.
+    pub width: usize,
+    pub height: usize,
+    /// Rows covered, as in `Config::row_range`: counted from the bottom,
+    /// from the first up to but excluding the second.
+    pub rows: (usize, usize),
+    /// Seed the render was made with.
+    pub seed: u64,
+    /// `Config::scene_fingerprint` of the render.
+    pub fingerprint: u64,
+    /// Just the covered rows, top row first.
+    pub film: Film,
+}
+
+impl Chunk {
+    /// Cuts the rows in `rows` out of a full-size film rendered with `seed`
+    /// and settings with the given fingerprint.
+    pub fn from_film(film: &Film, rows: (usize, usize), seed: u64, fingerprint: u64) -> Chunk {
+        let (from, to) = rows;
+        Chunk {
+            width: film.width,
+            height: film.height,
+            rows,
+            seed,
+            fingerprint,
+            film: film.rows(film.height - to, to - from),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        for v in [self.width, self.height, self.rows.0, self.rows.1] {
+            out.extend((v as u64).to_le_bytes());
+        }
+        out.extend(self.seed.to_le_bytes());
+        out.extend(self.fingerprint.to_le_bytes());
+        out.extend(self.film.to_bytes());
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Chunk> {
+        let rest = bytes.strip_prefix(MAGIC).ok_or("Not a crayfish chunk")?;
+        if rest.len() < 48 {
+            return Err("Chunk is truncated".into());
+        }
+        let word = |i: usize| u64::from_le_bytes(rest[i * 8..i * 8 + 8].try_into().unwrap());
+        let (width, height) = (word(0) as usize, word(1) as usize);
+        let (rows, seed, fingerprint) = ((word(2) as usize, word(3) as usize), word(4), word(5));
+        let (film, _) = Film::from_bytes(&rest[48..])?;
+        if rows.0 >= rows.1 || rows.1 > height
+            || (film.width, film.height) != (width, rows.1 - rows.0) {
+            return Err("Chunk rows don't match its image".into());
+        }
+        Ok(Chunk { width, height, rows, seed, fingerprint, film })
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        std::fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<Chunk> {
+        Chunk::from_bytes(&std::fs::read(path)?)
+            .map_err(|e| format!("{}: {}", path, e).into())
+    }
+}
+
+
+/// Combines chunks of the same image into a film of the whole of it.
+///
+/// Chunks must be rendered with the same scene settings. They may
+/// overlap, such as renders of the same rows with different seeds; their
+/// samples are then pooled. Overlapping chunks with the same seed hold the
+/// same samples, so they are refused. Every row must be covered.
+pub fn merge(chunks: &[Chunk]) -> Result<Film> {
+    let first = chunks.first().ok_or("Nothing to merge")?;
+    let (width, height) = (first.width, first.height);
+    if let Some(other) = chunks.iter().find(|c| (c.width, c.height) != (width, height)) {
+        return Err(format!(
+            "Chunks are of different images: {}x{} and {}x{}",
+            width, height, other.width, other.height,
+        ).into());
+    }
+    if chunks.iter().any(|c| c.fingerprint != first.fingerprint) {
+        return Err("Chunks were rendered with different camera, quality or scene settings".into());
+    }
+    for (i, a) in chunks.iter().enumerate() {
+        let overlapping = |b: &&Chunk| a.rows.0 < b.rows.1 && b.rows.0 < a.rows.1;
+        if let Some(b) = chunks[i + 1..].iter().filter(overlapping).find(|b| b.seed == a.seed) {
+            return Err(format!(
+                "Chunks of rows {} to {} and {} to {} overlap with the same seed {}, \
+                 so their samples are the same",
+                a.rows.0, a.rows.1, b.rows.0, b.rows.1, a.seed,
+            ).into());
+        }
+    }
+
+    let mut film = Film::new(width, height);
+    let mut covered = vec![false; height];
+    for chunk in chunks {
+        let (from, to) = chunk.rows;
+        film.add_film(&chunk.film, height - to);
+        covered[from..to].iter_mut().for_each(|c| *c = true);
+    }
+    if let Some(row) = covered.iter().position(|c| !c) {
+        let end = covered[row..].iter().position(|c| *c).map_or(height, |n| row + n);
+        return Err(format!("No chunk covers rows {} to {}", row, end).into());
+    }
+    Ok(film)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors::Color;
+
+    /// A 1x4 image with each row as bright as its number, counted from the bottom.
+    fn rendered(rows: (usize, usize), seed: u64) -> Chunk {
+        let mut film = Film::new(1, 4);
+        for row in rows.0..rows.1 {
+            let v = row as f64;
+            film.add_sample(0, 3 - row, Color::new(v, v, v));
+        }
+        Chunk::from_film(&film, rows, seed, 5)
+    }
+
+    #[test]
+    fn chunks_keep_only_their_rows() {
+        let chunk = rendered((1, 3), 7);
+        assert_eq!(chunk.film.height, 2);
+        let decoded = Chunk::from_bytes(&chunk.to_bytes()).unwrap();
+        assert_eq!(decoded.rows, (1, 3));
+        assert_eq!(decoded.seed, 7);
+        assert_eq!(decoded.fingerprint, 5);
+        assert_eq!(decoded.film.pixel_at(0, 0), Color::new(2., 2., 2.));
+    }
+
+    #[test]
+    fn merging_restores_the_whole_image() {
+        let film = merge(&[rendered((2, 4), 0), rendered((0, 2), 0), rendered((1, 2), 1)]).unwrap();
+        for row in 0..4 {
+            let v = row as f64;
+            assert_eq!(film.pixel_at(0, 3 - row), Color::new(v, v, v));
+        }
+        // The overlapping row pools its samples.
+        assert_eq!(film.samples_at(0, 2), 2);
+    }
+
+    #[test]
+    fn merging_needs_every_row_of_one_image() {
+        let error = merge(&[rendered((0, 1), 0), rendered((3, 4), 0)]).unwrap_err();
+        assert_eq!(error.to_string(), "No chunk covers rows 1 to 3");
+        let mut other = rendered((1, 4), 0);
+        other.width = 2;
+        assert!(merge(&[rendered((0, 1), 0), other]).is_err());
+        assert!(merge(&[]).is_err());
+    }
+
+    #[test]
+    fn merging_refuses_repeated_samples() {
+        let error = merge(&[rendered((0, 4), 3), rendered((2, 3), 3)]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Chunks of rows 0 to 4 and 2 to 3 overlap with the same seed 3, so their samples are the same"
+        );
+        assert!(merge(&[rendered((0, 2), 3), rendered((2, 4), 3)]).is_ok());
+    }
+
+    #[test]
+    fn merging_refuses_different_scenes() {
+        let mut other = rendered((2, 4), 1);
+        other.fingerprint = 6;
+        let error = merge(&[rendered((0, 2), 0), other]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Chunks were rendered with different camera, quality or scene settings"
+        );
+    }
+}
@@ -1,19 +1,124 @@
 use std::error::Error;
 
+use crate::camera::{Projection, Focus};
+use crate::tuples::{Tuple, Vector};
+use crate::camera::panoramic::{Panorama, FisheyeMapping};
+use crate::camera::stereo::StereoLayout;
+
 #[derive(Debug, PartialEq)]
 pub struct Config {
     // Camera settings
     pub aspect_ratio: f64,
     pub fov_radians: f64,
     pub aperture_radius: f64,
+    /// Number of diaphragm blades; below 3, the aperture is round.
+    pub aperture_blades: usize,
+    pub aperture_rotation_radians: f64,
+    pub aperture_image: Option<String>,
+    pub anamorphic_squeeze: f64,
+    /// If None, focus on the look-at point.
+    pub focus: Option<Focus>,
+    pub projection: Projection,
+    pub up: Tuple<Vector>,
+    pub roll_radians: f64,
+    /// Replaces the regular camera with a panoramic one.
+    pub panorama: Option<Panorama>,
+    /// Renders both eyes into one image; the aspect ratio is then per eye.
+    pub stereo: Option<StereoLayout>,
+    pub interpupillary_distance: f64,
+    /// Distance of zero parallax; if None, the distance to the look-at point.
+    pub convergence_distance: Option<f64>,
+    /// When the shutter opens and closes, from 0 at the start of any
+    /// motion to 1 at its end.
+    pub shutter: (f64, f64),
+    /// If set, a file of camera keyframes to animate the camera with.
+    pub camera_keys: Option<String>,
     // Output settings
     pub outfile: String,
     pub image_height: usize,
     pub row_range: (usize, usize),
+    /// If set, renders this range of frames, inclusive, as a sequence of
+    /// `.ppm` images.
+    pub frames: Option<(usize, usize)>,
+    /// When rendering progressively, how often to write the image so far.
+    pub snapshot_passes: Option<usize>,
+    pub snapshot_seconds: Option<f64>,
+    /// Also write an image of how many samples each pixel got.
+    pub sample_heatmap: bool,
+    /// If set, save a checkpoint this often, in seconds.
+    pub checkpoint_seconds: Option<f64>,
+    /// Carry on from a checkpoint.
+    pub resume: bool,
     pub verbose: bool,
     // Quality settings
+    /// Most samples per pixel; `usize::MAX` to leave it to the time limit.
     pub rays_per_pixel: usize,
+    /// If set, stop adding samples after this many seconds.
+    pub time_limit: Option<f64>,
+    /// If set, renders progressively, in passes of this many samples per pixel.
+    pub samples_per_pass: Option<usize>,
+    /// If set, samples adaptively: pixels stop once their relative error
+    /// is below this, and have between `min_samples` and `max_samples`.
+    pub noise_threshold: Option<f64>,
+    pub min_samples: usize,
+    pub max_samples: usize,
+    /// Renders with the same seed and settings come out the same.
+    pub seed: u64,
     pub max_scatter_depth: usize,
+    pub dispersion: bool,
+    pub spectral: bool,
+    // Scene settings
+    pub principled: bool,
+    pub fog_density: Option<f64>,
+    pub fog_anisotropy: f64,
+    pub fog_far: f64,
+    pub environment: Option<String>,
+    pub environment_rotation_radians: f64,
+    pub environment_intensity: f64,
+    pub sky: bool,
+    pub sun_elevation_radians: f64,
+    pub sun_azimuth_radians: f64,
+    pub turbidity: f64,
+}
+
+impl Config {
+    /// A hash of the settings that decide what the image is a picture of,
+    /// stable from run to run. It leaves out where the output goes, how
+    /// progress is shown and saved, and which samples are taken: the seed,
+    /// rows and sampling settings, which renders of the same picture can
+    /// differ in.
+    pub fn scene_fingerprint(&self) -> u64 {
+        // Listing every field makes new ones pick a side.
+        let Config {
+            aspect_ratio, fov_radians, aperture_radius, aperture_blades,
+            aperture_rotation_radians, aperture_image, anamorphic_squeeze, focus,
+            projection, up, roll_radians, panorama, stereo, interpupillary_distance,
+            convergence_distance, shutter, camera_keys,
+            outfile: _, image_height, row_range: _, frames: _, snapshot_passes: _,
+            snapshot_seconds: _, sample_heatmap: _, checkpoint_seconds: _, resume: _,
+            verbose: _,
+            rays_per_pixel: _, time_limit: _, samples_per_pass: _, noise_threshold: _,
+            min_samples: _, max_samples: _, seed: _,
+            max_scatter_depth, dispersion, spectral,
+            principled, fog_density, fog_anisotropy, fog_far, environment,
+            environment_rotation_radians, environment_intensity, sky,
+            sun_elevation_radians, sun_azimuth_radians, turbidity,
+        } = self;
+        let scene: [&dyn std::fmt::Debug; 32] = [
+            aspect_ratio, fov_radians, aperture_radius, aperture_blades,
+            aperture_rotation_radians, aperture_image, anamorphic_squeeze, focus,
+            projection, up, roll_radians, panorama, stereo, interpupillary_distance,
+            convergence_distance, shutter, camera_keys, image_height,
+            max_scatter_depth, dispersion, spectral,
+            principled, fog_density, fog_anisotropy, fog_far, environment,
+            environment_rotation_radians, environment_intensity, sky,
+            sun_elevation_radians, sun_azimuth_radians, turbidity,
+        ];
+        // FNV-1a, over the settings as written out.
+        format!("{:?}", scene).bytes().fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+    }
 }
 
 pub fn cli() -> clap::Command {
@@ -48,6 +153,153 @@ pub fn cli() -> clap::Command {
             .value_parser(clap::value_parser!(f64))
             .group("camera_settings")
         )
+        .arg(
+            clap::Arg::new("aperture_blades")
+            .long("aperture_blades")
+            .help("Number of diaphragm blades, giving polygonal bokeh. Below 3, the aperture is round.")
+            .default_value("0")
+            .value_parser(clap::value_parser!(usize))
+            .group("camera_settings")
+        )
+        .arg(
+            clap::Arg::new("aperture_rotation")
+            .long("aperture_rotation")
+            .help("Rotation of a polygonal aperture, in degrees.")
+            .default_value("0")
+            .allow_negative_numbers(true)
+            .value_parser(clap::value_parser!(f64))
+            .group("camera_settings")
+        )
+        .arg(
+            clap::Arg::new("aperture_image")
+            .long("aperture_image")
+            .value_name("PATH")
+            .help("Grayscale image (.pgm, .pfm or .hdr) to shape the aperture, open where bright.")
+            .conflicts_with("aperture_blades")
+            .group("camera_settings")
+        )
+        .arg(
+            clap::Arg::new("anamorphic_squeeze")
+            .long("anamorphic_squeeze")
+            .help("Horizontal squeeze of the aperture, as in anamorphic lenses.")
+            .default_value("1")
+            .value_parser(clap::value_parser!(f64))
+            .group("camera_settings")
+        )
+        .arg(
+            clap::Arg::new("focus_distance")
+            .long("focus_distance")
+            .help("Distance in front of the camera to focus at. [default: distance to the look-at point]")
+            .value_parser(clap::value_parser!(f64))
+            .group("camera_settings")
+        )
+        .arg(
+            clap::Arg::new("focus_point")
+            .long("focus_point")
+            .value_names(["X", "Y", "Z"])
+            .num_args(3)
+            .help("Focus at the depth of this point in the scene.")
+            .allow_negative_numbers(true)
+            .value_parser(clap::value_parser!(f64))
+            .conflicts_with("focus_distance")
+            .group("camera_settings")
+        )
+        .arg(
+            clap::Arg::new("focus_pixel")
+            .long("focus_pixel")
+            .value_names(["X", "Y"])
+            .num_args(2)
+            .help("Focus on whatever is visible at this pixel, counted from the top left.")
+            .value_parser(clap::value_parser!(usize))
+            .conflicts_with_all(["focus_distance", "focus_point"])
+            .group("camera_settings")
+        )
+        .arg(
+            clap::Arg::new("up")
+            .long("up")
+            .value_names(["X", "Y", "Z"])
+            .num_args(3)
+            .help("Direction towards the top of the image.")
+            .default_values(["0", "1", "0"])
+            .allow_negative_numbers(true)
+            .value_parser(clap::value_parser!(f64))
+            .group("camera_settings")
+        )
+        .arg(
+            clap::Arg::new("roll")
+            .long("roll")
+            .help("Rotation of the camera about its view direction in degrees, clockwise.")
+            .default_value("0")
+            .allow_negative_numbers(true)
+            .value_parser(clap::value_parser!(f64))
+            .group("camera_settings")
+        )
+        .arg(
+            clap::Arg::new("projection")
+            .long("projection")
+            .help("How the camera maps the scene onto the image.")
+            .default_value("perspective")
+            .value_parser([
+                "perspective", "orthographic", "equirectangular",
+                "fisheye_equidistant", "fisheye_equisolid", "cubemap",
+            ])
+            .group("camera_settings")
+        )
+        .arg(
+            clap::Arg::new("view_width")
+            .long("view_width")
+            .help("Width of the view of an orthographic camera, in scene units.")
+            .default_value("10")
+            .value_parser(clap::value_parser!(f64))
+            .group("camera_settings")
+        )
+        .arg(
+            clap::Arg::new("fisheye_fov")
+            .long("fisheye_fov")
+            .help("Field of view across the image circle of a fisheye camera, in degrees.")
+            .default_value("180")
+            .value_parser(clap::value_parser!(f64))
+            .group("camera_settings")
+        )
+        .arg(
+            clap::Arg::new("stereo")
+            .long("stereo")
+            .help("Render a left and right eye into one image, with a perspective projection. The aspect ratio is then that of each eye.")
+            .value_parser(["side_by_side", "top_bottom"])
+            .group("camera_settings")
+        )
+        .arg(
+            clap::Arg::new("interpupillary_distance")
+            .long("interpupillary_distance")
+            .help("Distance between the eyes of a stereo camera, in scene units.")
+            .default_value("0.065")
+            .value_parser(clap::value_parser!(f64))
+            .group("camera_settings")
+        )
+        .arg(
+            clap::Arg::new("convergence_distance")
+            .long("convergence_distance")
+            .help("Distance at which the eyes of a stereo camera converge. [default: distance to the look-at point]")
+            .value_parser(clap::value_parser!(f64))
+            .group("camera_settings")
+        )
+        .arg(
+            clap::Arg::new("shutter")
+            .long("shutter")
+            .value_names(["OPEN", "CLOSE"])
+            .num_args(2)
+            .help("When the shutter opens and closes, from 0 at the start of motion to 1 at its end.")
+            .default_values(["0", "0"])
+            .value_parser(clap::value_parser!(f64))
+            .group("camera_settings")
+        )
+        .arg(
+            clap::Arg::new("camera_keys")
+            .long("camera_keys")
+            .value_name("PATH")
+            .help("Camera keyframes, one per line as FRAME, then LOOKFROM and LOOKAT as X Y Z; a line `interpolation smooth` eases between them.")
+            .group("camera_settings")
+        )
         .group(clap::ArgGroup::new("output_settings").multiple(true))
         .next_help_heading("OUTPUT SETTINGS")
         .arg(
@@ -70,11 +322,60 @@ pub fn cli() -> clap::Command {
             clap::Arg::new("row_range")
             .long("row_range")
             .value_names(["FROM", "TO"])
-            .help("Will render only rows [FROM, TO).")
+            .help("Will render only rows [FROM, TO), counted from the bottom, to OUTFILE.chunk for `crayfish merge`.")
+            .num_args(2)
+            .value_parser(clap::value_parser!(usize))
+            .group("output_settings")
+        )
+        .arg(
+            clap::Arg::new("frames")
+            .long("frames")
+            .value_names(["START", "END"])
+            .help("Render frames START to END of the animation as .ppm images, OUTFILE_0001.ppm etc., skipping frames that already exist.")
             .num_args(2)
             .value_parser(clap::value_parser!(usize))
             .group("output_settings")
         )
+        .arg(
+            clap::Arg::new("snapshot_passes")
+            .long("snapshot_passes")
+            .value_name("K")
+            .help("When rendering progressively, write the image so far every K passes. [default: every pass]")
+            .value_parser(clap::value_parser!(usize))
+            .requires("progressive")
+            .group("output_settings")
+        )
+        .arg(
+            clap::Arg::new("snapshot_seconds")
+            .long("snapshot_seconds")
+            .value_name("T")
+            .help("When rendering progressively, write the image so far every T seconds.")
+            .value_parser(clap::value_parser!(f64))
+            .requires("progressive")
+            .group("output_settings")
+        )
+        .arg(
+            clap::Arg::new("sample_heatmap")
+            .long("sample_heatmap")
+            .help("Also write OUTFILE.samples.ppm, showing how many samples each pixel got.")
+            .action(clap::ArgAction::SetTrue)
+            .group("output_settings")
+        )
+        .arg(
+            clap::Arg::new("checkpoint")
+            .long("checkpoint")
+            .value_name("DURATION")
+            .help("Save the render so far to OUTFILE.checkpoint this often, in seconds or with an s, m or h suffix.")
+            .value_parser(parse_duration)
+            .group("output_settings")
+        )
+        .arg(
+            clap::Arg::new("resume")
+            .long("resume")
+            .help("Carry on from OUTFILE.checkpoint, made with the same settings. The result is the same as if the render had not stopped.")
+            .action(clap::ArgAction::SetTrue)
+            .group("output_settings")
+        )
         .group(clap::ArgGroup::new("quality_settings").multiple(true))
         .next_help_heading("QUALITY SETTINGS")
         .arg(
@@ -93,6 +394,200 @@ pub fn cli() -> clap::Command {
             .value_parser(clap::value_parser!(usize))
             .group("quality_settings")
         )
+        .arg(
+            clap::Arg::new("progressive")
+            .long("progressive")
+            .value_name("SAMPLES")
+            .help("Render in passes of SAMPLES rays per pixel over the whole image, writing the image so far between passes.")
+            .value_parser(clap::value_parser!(usize))
+            .group("quality_settings")
+        )
+        .arg(
+            clap::Arg::new("time_limit")
+            .long("time_limit")
+            .value_name("DURATION")
+            .help("Keep adding passes until this much time has passed, in seconds or with an s, m or h suffix. Unless rays_per_pixel is given too, it sets no limit.")
+            .value_parser(parse_duration)
+            .group("quality_settings")
+        )
+        .arg(
+            clap::Arg::new("noise_threshold")
+            .long("noise_threshold")
+            .help("Sample adaptively, stopping at pixels whose relative standard error falls below this.")
+            .value_parser(clap::value_parser!(f64))
+            .group("quality_settings")
+        )
+        .arg(
+            clap::Arg::new("min_samples")
+            .long("min_samples")
+            .help("Rays per pixel before adaptive sampling may stop.")
+            .default_value("16")
+            .value_parser(clap::value_parser!(usize))
+            .group("quality_settings")
+        )
+        .arg(
+            clap::Arg::new("max_samples")
+            .long("max_samples")
+            .help("Most rays per pixel when sampling adaptively. [default: rays_per_pixel]")
+            .value_parser(clap::value_parser!(usize))
+            .group("quality_settings")
+        )
+        .arg(
+            clap::Arg::new("seed")
+            .long("seed")
+            .help("Seed for the random numbers the render draws; the same seed gives the same image.")
+            .default_value("0")
+            .value_parser(clap::value_parser!(u64))
+            .group("quality_settings")
+        )
+        .arg(
+            clap::Arg::new("dispersion")
+            .long("dispersion")
+            .help("Trace one wavelength per camera ray, so dispersive materials split light.")
+            .action(clap::ArgAction::SetTrue)
+            .group("quality_settings")
+        )
+        .arg(
+            clap::Arg::new("spectral")
+            .long("spectral")
+            .help("Render spectrally: rays carry wavelengths and RGB colors are upsampled to spectra.")
+            .action(clap::ArgAction::SetTrue)
+            .group("quality_settings")
+        )
+        .group(clap::ArgGroup::new("scene_settings").multiple(true))
+        .next_help_heading("SCENE SETTINGS")
+        .arg(
+            clap::Arg::new("principled")
+            .long("principled")
+            .help("Give the scene principled materials instead of the separate diffuse, metal and glass ones.")
+            .action(clap::ArgAction::SetTrue)
+            .group("scene_settings")
+        )
+        .arg(
+            clap::Arg::new("fog")
+            .long("fog")
+            .value_name("DENSITY")
+            .help("Fill the scene with fog of this density (per unit distance).")
+            .value_parser(clap::value_parser!(f64))
+            .group("scene_settings")
+        )
+        .arg(
+            clap::Arg::new("fog_anisotropy")
+            .long("fog_anisotropy")
+            .help("Henyey-Greenstein asymmetry of the fog: >0 scatters forwards, <0 backwards.")
+            .default_value("0")
+            .allow_negative_numbers(true)
+            .value_parser(clap::value_parser!(f64))
+            .group("scene_settings")
+        )
+        .arg(
+            clap::Arg::new("fog_far")
+            .long("fog_far")
+            .value_name("DISTANCE")
+            .help("How far the fog reaches beyond the scene, towards the sky.")
+            .default_value("100")
+            .value_parser(clap::value_parser!(f64))
+            .group("scene_settings")
+        )
+        .arg(
+            clap::Arg::new("environment")
+            .long("environment")
+            .value_name("PATH")
+            .help("Light the scene with an equirectangular HDR image (.hdr or .pfm) instead of the sky.")
+            .group("scene_settings")
+        )
+        .arg(
+            clap::Arg::new("environment_rotation")
+            .long("environment_rotation")
+            .help("Rotation of the environment around the vertical axis, in degrees.")
+            .default_value("0")
+            .allow_negative_numbers(true)
+            .value_parser(clap::value_parser!(f64))
+            .group("scene_settings")
+        )
+        .arg(
+            clap::Arg::new("environment_intensity")
+            .long("environment_intensity")
+            .help("Multiplier for the radiance of the environment.")
+            .default_value("1")
+            .value_parser(clap::value_parser!(f64))
+            .group("scene_settings")
+        )
+        .arg(
+            clap::Arg::new("sky")
+            .long("sky")
+            .help("Light the scene with a physically based sun and sky instead of the default sky.")
+            .action(clap::ArgAction::SetTrue)
+            .conflicts_with("environment")
+            .group("scene_settings")
+        )
+        .arg(
+            clap::Arg::new("sun_elevation")
+            .long("sun_elevation")
+            .help("Angle of the sun above the horizon, in degrees.")
+            .default_value("45")
+            .allow_negative_numbers(true)
+            .value_parser(clap::value_parser!(f64))
+            .group("scene_settings")
+        )
+        .arg(
+            clap::Arg::new("sun_azimuth")
+            .long("sun_azimuth")
+            .help("Direction of the sun in degrees, clockwise from -z towards +x.")
+            .default_value("0")
+            .allow_negative_numbers(true)
+            .value_parser(clap::value_parser!(f64))
+            .group("scene_settings")
+        )
+        .arg(
+            clap::Arg::new("turbidity")
+            .long("turbidity")
+            .help("Haziness of the sky, from 2 (clear) to 10 (hazy).")
+            .default_value("3")
+            .value_parser(clap::value_parser!(f64))
+            .group("scene_settings")
+        )
+        .subcommand(merge_cli())
+}
+
+/// Settings for `crayfish merge`.
+#[derive(Debug, PartialEq)]
+pub struct MergeConfig {
+    pub chunks: Vec<String>,
+    pub outfile: String,
+    pub sample_heatmap: bool,
+}
+
+fn merge_cli() -> clap::Command {
+    clap::Command::new("merge")
+        .about("Combine the chunks written by renders of separate row ranges into one image.")
+        .arg(
+            clap::Arg::new("chunks")
+            .value_name("CHUNK")
+            .help("Chunk files to combine. Chunks of the same rows pool their samples, so must have been rendered with different --seed values.")
+            .num_args(1..)
+            .required(true)
+        )
+        .arg(
+            clap::Arg::new("outfile")
+            .long("outfile")
+            .help("Output filepath without file extension.")
+            .default_value("out")
+        )
+        .arg(
+            clap::Arg::new("sample_heatmap")
+            .long("sample_heatmap")
+            .help("Also write OUTFILE.samples.ppm, showing how many samples each pixel got.")
+            .action(clap::ArgAction::SetTrue)
+        )
+}
+
+pub fn make_merge_config(matches: &clap::ArgMatches) -> MergeConfig {
+    MergeConfig {
+        chunks: matches.get_many::<String>("chunks").unwrap().cloned().collect(),
+        outfile: matches.get_one::<String>("outfile").unwrap().to_owned(),
+        sample_heatmap: matches.get_flag("sample_heatmap"),
+    }
 }
 
 pub fn make_config(matches: clap::ArgMatches) -> Result<Config, Box::<dyn Error>> {
@@ -105,20 +600,185 @@ pub fn make_config(matches: clap::ArgMatches) -> Result<Config, Box::<dyn Error>
         .map(|v| v.collect())
         .unwrap_or(vec![&0, &image_height]);
     let row_range = (*lh[0], *lh[1]);
+    if row_range.0 >= row_range.1 || row_range.1 > image_height {
+        return Err("Row range must be a non-empty part of the image".into());
+    }
+
+    let anamorphic_squeeze: f64 = *matches.get_one("anamorphic_squeeze").unwrap();
+    if anamorphic_squeeze <= 0. {
+        return Err("Anamorphic squeeze must be positive".into());
+    }
+
+    let image_width = (aspect_ratio * image_height as f64) as usize;
+    let focus = if let Some(distance) = matches.get_one::<f64>("focus_distance") {
+        Some(Focus::Distance(*distance))
+    } else if let Some(point) = matches.get_many::<f64>("focus_point") {
+        let p: Vec<&f64> = point.collect();
+        Some(Focus::Point(Tuple::point(*p[0], *p[1], *p[2])))
+    } else if let Some(pixel) = matches.get_many::<usize>("focus_pixel") {
+        let p: Vec<&usize> = pixel.collect();
+        if *p[0] >= image_width || *p[1] >= image_height {
+            return Err("Focus pixel must be inside the image".into());
+        }
+        Some(Focus::Pixel(
+            (*p[0] as f64 + 0.5) / image_width as f64,
+            1. - (*p[1] as f64 + 0.5) / image_height as f64,
+        ))
+    } else {
+        None
+    };
+
+    let up: Vec<&f64> = matches.get_many("up").unwrap().collect();
+    let up = Tuple::vector(*up[0], *up[1], *up[2]);
+    if up.magnitude() == 0. {
+        return Err("Up vector must not be zero".into());
+    }
+
+    let view_width = *matches.get_one::<f64>("view_width").unwrap();
+    let fisheye_fov_radians = matches.get_one::<f64>("fisheye_fov").unwrap().to_radians();
+    let (projection, panorama) = match matches.get_one::<String>("projection").unwrap().as_str() {
+        "orthographic" => (Projection::Orthographic { view_width }, None),
+        "equirectangular" => (Projection::Perspective, Some(Panorama::Equirectangular)),
+        "fisheye_equidistant" => (Projection::Perspective, Some(Panorama::Fisheye {
+            mapping: FisheyeMapping::Equidistant,
+            fov_radians: fisheye_fov_radians,
+        })),
+        "fisheye_equisolid" => (Projection::Perspective, Some(Panorama::Fisheye {
+            mapping: FisheyeMapping::Equisolid,
+            fov_radians: fisheye_fov_radians,
+        })),
+        "cubemap" => (Projection::Perspective, Some(Panorama::Cubemap)),
+        _ => (Projection::Perspective, None),
+    };
+    let stereo = matches.get_one::<String>("stereo").map(|layout| match layout.as_str() {
+        "top_bottom" => StereoLayout::TopBottom,
+        _ => StereoLayout::SideBySide,
+    });
+    // Parallel orthographic rays show everything with the same parallax,
+    // so only a perspective pair has depth.
+    if stereo.is_some() && (panorama.is_some() || projection != Projection::Perspective) {
+        return Err("Stereo rendering needs a perspective projection".into());
+    }
+    // Panoramic cameras have no lens to focus or shape.
+    let lens_settings = [
+        "aperture_radius", "aperture_blades", "aperture_rotation", "aperture_image",
+        "anamorphic_squeeze", "focus_distance", "focus_point", "focus_pixel",
+        "interpupillary_distance", "convergence_distance",
+    ];
+    let given = |id: &&&str| matches.value_source(id) == Some(clap::parser::ValueSource::CommandLine);
+    if let (Some(_), Some(setting)) = (panorama, lens_settings.iter().find(given)) {
+        return Err(format!("--{} can't be used with a panoramic projection", setting).into());
+    }
+
+    let frames = matches.get_many::<usize>("frames").map(|f| {
+        let f: Vec<&usize> = f.collect();
+        (*f[0], *f[1])
+    });
+    if let Some((start, end)) = frames {
+        if start > end {
+            return Err("The last frame must not come before the first".into());
+        }
+    }
+
+    let samples_per_pass = matches.get_one::<usize>("progressive").copied();
+    let snapshot_passes = matches.get_one::<usize>("snapshot_passes").copied();
+    let snapshot_seconds = matches.get_one::<f64>("snapshot_seconds").copied();
+    if samples_per_pass == Some(0) || snapshot_passes == Some(0)
+        || snapshot_seconds.is_some_and(|t| t <= 0.) {
+        return Err("Progressive passes and snapshot intervals must be positive".into());
+    }
+
+    let time_limit = matches.get_one::<f64>("time_limit").copied();
+    let rays_per_pixel = match (time_limit, matches.value_source("rays_per_pixel")) {
+        (Some(_), Some(clap::parser::ValueSource::DefaultValue)) => usize::MAX,
+        _ => *matches.get_one("rays_per_pixel").unwrap(),
+    };
+    let noise_threshold = matches.get_one::<f64>("noise_threshold").copied();
+    let min_samples = *matches.get_one("min_samples").unwrap();
+    let max_samples = matches.get_one("max_samples").copied().unwrap_or(rays_per_pixel);
+    if let Some(threshold) = noise_threshold {
+        if threshold <= 0. {
+            return Err("Noise threshold must be positive".into());
+        }
+        if min_samples < 2 || min_samples > max_samples {
+            return Err("Adaptive sampling needs 2 <= min_samples <= max_samples".into());
+        }
+    }
+
+    let shutter: Vec<&f64> = matches.get_many("shutter").unwrap().collect();
+    let shutter = (*shutter[0], *shutter[1]);
+    if !(0. <= shutter.0 && shutter.0 <= shutter.1 && shutter.1 <= 1.) {
+        return Err("Shutter must open and then close between 0 and 1".into());
+    }
 
     Ok(Config {
         aspect_ratio,
         fov_radians: matches.get_one::<f64>("fov").unwrap().to_radians(),
         aperture_radius: *matches.get_one("aperture_radius").unwrap(),
+        aperture_blades: *matches.get_one("aperture_blades").unwrap(),
+        aperture_rotation_radians: matches.get_one::<f64>("aperture_rotation")
+            .unwrap().to_radians(),
+        aperture_image: matches.get_one::<String>("aperture_image").cloned(),
+        anamorphic_squeeze,
+        focus,
+        projection,
+        up,
+        roll_radians: matches.get_one::<f64>("roll").unwrap().to_radians(),
+        panorama,
+        stereo,
+        interpupillary_distance: *matches.get_one("interpupillary_distance").unwrap(),
+        convergence_distance: matches.get_one("convergence_distance").copied(),
+        shutter,
+        camera_keys: matches.get_one::<String>("camera_keys").cloned(),
         outfile: matches.get_one::<String>("outfile").unwrap().to_owned(),
         image_height,
         row_range,
+        frames,
+        snapshot_passes,
+        snapshot_seconds,
+        sample_heatmap: matches.get_flag("sample_heatmap"),
+        checkpoint_seconds: matches.get_one("checkpoint").copied(),
+        resume: matches.get_flag("resume"),
         verbose: true,
-        rays_per_pixel: *matches.get_one("rays_per_pixel").unwrap(),
+        rays_per_pixel,
+        time_limit,
+        samples_per_pass,
+        noise_threshold,
+        min_samples,
+        max_samples,
+        seed: *matches.get_one("seed").unwrap(),
         max_scatter_depth: *matches.get_one("max_scatter_depth").unwrap(),
+        dispersion: matches.get_flag("dispersion"),
+        spectral: matches.get_flag("spectral"),
+        principled: matches.get_flag("principled"),
+        fog_density: matches.get_one("fog").copied(),
+        fog_anisotropy: *matches.get_one("fog_anisotropy").unwrap(),
+        fog_far: *matches.get_one("fog_far").unwrap(),
+        environment: matches.get_one::<String>("environment").cloned(),
+        environment_rotation_radians: matches.get_one::<f64>("environment_rotation")
+            .unwrap().to_radians(),
+        environment_intensity: *matches.get_one("environment_intensity").unwrap(),
+        sky: matches.get_flag("sky"),
+        sun_elevation_radians: matches.get_one::<f64>("sun_elevation").unwrap().to_radians(),
+        sun_azimuth_radians: matches.get_one::<f64>("sun_azimuth").unwrap().to_radians(),
+        turbidity: *matches.get_one("turbidity").unwrap(),
     })
 }
 
+/// Parses a duration in seconds, or in minutes or hours given a suffix.
+fn parse_duration(arg: &str) -> Result<f64, String> {
+    let (number, unit) = match arg.char_indices().last() {
+        Some((i, 's')) => (&arg[..i], 1.),
+        Some((i, 'm')) => (&arg[..i], 60.),
+        Some((i, 'h')) => (&arg[..i], 3600.),
+        _ => (arg, 1.),
+    };
+    match number.parse::<f64>() {
+        Ok(n) if n > 0. => Ok(n * unit),
+        _ => Err(format!("expected a positive duration such as 90, 90s, 10m or 1.5h, got {}", arg)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -134,13 +794,218 @@ mod tests {
                 aspect_ratio: 16./9.,
                 fov_radians: std::f64::consts::PI / 2.,
                 aperture_radius: 0.,
+                aperture_blades: 0,
+                aperture_rotation_radians: 0.,
+                aperture_image: None,
+                anamorphic_squeeze: 1.,
+                focus: None,
+                projection: Projection::Perspective,
+                up: Tuple::vector(0., 1., 0.),
+                roll_radians: 0.,
+                panorama: None,
+                stereo: None,
+                interpupillary_distance: 0.065,
+                convergence_distance: None,
+                shutter: (0., 0.),
+                camera_keys: None,
                 outfile: "out".to_string(),
                 image_height: 100,
                 row_range: (0, 100),
+                frames: None,
+                snapshot_passes: None,
+                snapshot_seconds: None,
+                sample_heatmap: false,
+                checkpoint_seconds: None,
+                resume: false,
                 verbose: true,
                 rays_per_pixel: 200,
+                time_limit: None,
+                samples_per_pass: None,
+                noise_threshold: None,
+                min_samples: 16,
+                max_samples: 200,
+                seed: 0,
                 max_scatter_depth: 30,
+                dispersion: false,
+                spectral: false,
+                principled: false,
+                fog_density: None,
+                fog_anisotropy: 0.,
+                fog_far: 100.,
+                environment: None,
+                environment_rotation_radians: 0.,
+                environment_intensity: 1.,
+                sky: false,
+                sun_elevation_radians: std::f64::consts::FRAC_PI_4,
+                sun_azimuth_radians: 0.,
+                turbidity: 3.,
             }
         )
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn scene_fingerprint_ignores_output_and_sampling() {
+        let fingerprint = |args: &str| {
+            let matches = cli().get_matches_from(format!("crayfish {}", args).split_whitespace());
+            make_config(matches).unwrap().scene_fingerprint()
+        };
+        let plain = fingerprint("");
+        assert_eq!(plain, fingerprint(""));
+        assert_eq!(plain, fingerprint("--outfile elsewhere --seed 3 --row_range 0 50 --rays_per_pixel 5"));
+        assert_ne!(plain, fingerprint("--max_scatter_depth 5"));
+        assert_ne!(plain, fingerprint("--fov 60"));
+    }
+
+    #[test]
+    fn orthographic_projection_takes_view_width() {
+        let matches = cli().get_matches_from(
+            "crayfish --projection orthographic --view_width 4".split(' '));
+        let config = make_config(matches).unwrap();
+        assert_eq!(config.projection, Projection::Orthographic { view_width: 4. });
+        assert_eq!(config.panorama, None);
+    }
+
+    #[test]
+    fn frames_must_be_in_order() {
+        let matches = cli().get_matches_from("crayfish --frames 1 24".split(' '));
+        assert_eq!(make_config(matches).unwrap().frames, Some((1, 24)));
+        let matches = cli().get_matches_from("crayfish --frames 24 1".split(' '));
+        assert!(make_config(matches).is_err());
+    }
+
+    #[test]
+    fn snapshots_need_progressive_rendering() {
+        let args = "crayfish --snapshot_passes 2";
+        assert!(cli().try_get_matches_from(args.split(' ')).is_err());
+        let matches = cli().get_matches_from(
+            "crayfish --progressive 4 --snapshot_seconds 30".split(' '));
+        let config = make_config(matches).unwrap();
+        assert_eq!(config.samples_per_pass, Some(4));
+        assert_eq!(config.snapshot_seconds, Some(30.));
+        let matches = cli().get_matches_from("crayfish --progressive 0".split(' '));
+        assert!(make_config(matches).is_err());
+    }
+
+    #[test]
+    fn adaptive_sampling_defaults_to_rays_per_pixel_at_most() {
+        let matches = cli().get_matches_from(
+            "crayfish --noise_threshold 0.01 --rays_per_pixel 64".split(' '));
+        let config = make_config(matches).unwrap();
+        assert_eq!(config.noise_threshold, Some(0.01));
+        assert_eq!((config.min_samples, config.max_samples), (16, 64));
+        let matches = cli().get_matches_from(
+            "crayfish --noise_threshold 0.01 --min_samples 32 --max_samples 8".split(' '));
+        assert!(make_config(matches).is_err());
+    }
+
+    #[test]
+    fn time_limits_lift_the_sample_limit_unless_given() {
+        let matches = cli().get_matches_from("crayfish --time_limit 10m".split(' '));
+        let config = make_config(matches).unwrap();
+        assert_eq!(config.time_limit, Some(600.));
+        assert_eq!(config.rays_per_pixel, usize::MAX);
+        let matches = cli().get_matches_from(
+            "crayfish --time_limit 1.5h --rays_per_pixel 50".split(' '));
+        let config = make_config(matches).unwrap();
+        assert_eq!(config.time_limit, Some(5400.));
+        assert_eq!(config.rays_per_pixel, 50);
+        assert!(cli().try_get_matches_from("crayfish --time_limit 0".split(' ')).is_err());
+        assert!(cli().try_get_matches_from("crayfish --time_limit soon".split(' ')).is_err());
+    }
+
+    #[test]
+    fn row_ranges_must_be_inside_the_image() {
+        let matches = cli().get_matches_from("crayfish --row_range 50 100".split(' '));
+        assert_eq!(make_config(matches).unwrap().row_range, (50, 100));
+        let matches = cli().get_matches_from("crayfish --row_range 50 101".split(' '));
+        assert!(make_config(matches).is_err());
+        let matches = cli().get_matches_from("crayfish --row_range 50 50".split(' '));
+        assert!(make_config(matches).is_err());
+    }
+
+    #[test]
+    fn merge_takes_chunk_files() {
+        let matches = cli().get_matches_from(
+            "crayfish merge top.chunk bottom.chunk --outfile whole".split(' '));
+        let Some(("merge", merge)) = matches.subcommand() else {
+            panic!("expected the merge subcommand");
+        };
+        assert_eq!(make_merge_config(merge), MergeConfig {
+            chunks: vec!["top.chunk".to_string(), "bottom.chunk".to_string()],
+            outfile: "whole".to_string(),
+            sample_heatmap: false,
+        });
+        assert!(cli().try_get_matches_from("crayfish merge".split(' ')).is_err());
+    }
+
+    #[test]
+    fn shutter_must_close_after_it_opens() {
+        let matches = cli().get_matches_from("crayfish --shutter 0.25 0.75".split(' '));
+        assert_eq!(make_config(matches).unwrap().shutter, (0.25, 0.75));
+        let matches = cli().get_matches_from("crayfish --shutter 0.75 0.25".split(' '));
+        assert!(make_config(matches).is_err());
+        let matches = cli().get_matches_from("crayfish --shutter 0 2".split(' '));
+        assert!(make_config(matches).is_err());
+    }
+
+    #[test]
+    fn up_vector_must_not_be_zero() {
+        let matches = cli().get_matches_from("crayfish --up 0 0 0".split(' '));
+        assert!(make_config(matches).is_err());
+        let matches = cli().get_matches_from("crayfish --up 0 0 -1 --roll 30".split(' '));
+        let config = make_config(matches).unwrap();
+        assert_eq!(config.up, Tuple::vector(0., 0., -1.));
+        assert!((config.roll_radians - std::f64::consts::PI / 6.).abs() < 1e-12);
+    }
+
+    #[test]
+    fn stereo_needs_a_flat_projection() {
+        let matches = cli().get_matches_from(
+            "crayfish --stereo top_bottom --convergence_distance 3".split(' '));
+        let config = make_config(matches).unwrap();
+        assert_eq!(config.stereo, Some(StereoLayout::TopBottom));
+        assert_eq!(config.convergence_distance, Some(3.));
+        let matches = cli().get_matches_from(
+            "crayfish --stereo side_by_side --projection cubemap".split(' '));
+        assert!(make_config(matches).is_err());
+        let matches = cli().get_matches_from(
+            "crayfish --stereo side_by_side --projection orthographic".split(' '));
+        assert!(make_config(matches).is_err());
+    }
+
+    #[test]
+    fn panoramas_have_no_lens_settings() {
+        let matches = cli().get_matches_from(
+            "crayfish --projection equirectangular --aperture_radius 0.1".split(' '));
+        assert_eq!(
+            make_config(matches).unwrap_err().to_string(),
+            "--aperture_radius can't be used with a panoramic projection"
+        );
+        let matches = cli().get_matches_from(
+            "crayfish --projection fisheye_equidistant --focus_distance 2".split(' '));
+        assert!(make_config(matches).is_err());
+    }
+
+    #[test]
+    fn focus_pixel_is_counted_from_the_top_left() {
+        let matches = cli().get_matches_from(
+            "crayfish --aspect_ratio 2 1 --image_height 10 --focus_pixel 0 9".split(' '));
+        let config = make_config(matches).unwrap();
+        let Some(Focus::Pixel(x, y)) = config.focus else { panic!("expected pixel focus") };
+        assert!(crate::eq(x, 0.025) && crate::eq(y, 0.05));
+        let matches = cli().get_matches_from(
+            "crayfish --image_height 10 --focus_pixel 0 10".split(' '));
+        assert!(make_config(matches).is_err());
+    }
+
+    #[test]
+    fn fisheye_projection_takes_field_of_view() {
+        let matches = cli().get_matches_from(
+            "crayfish --projection fisheye_equisolid --fisheye_fov 90".split(' '));
+        let config = make_config(matches).unwrap();
+        assert_eq!(config.panorama, Some(Panorama::Fisheye {
+            mapping: FisheyeMapping::Equisolid,
+            fov_radians: std::f64::consts::FRAC_PI_2,
+        }));
+    }
+}
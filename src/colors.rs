@@ -30,10 +30,16 @@ impl Color {
             self.blue.sqrt(),
         )
     }
+
+    /// Relative luminance of a linear Rec. 709 / sRGB color.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.red + 0.7152 * self.green + 0.0722 * self.blue
+    }
 }
 
 
 #[cfg(test)]
+#[allow(clippy::items_after_test_module)]
 mod constructor_tests {
     use super::*;
 
@@ -0,0 +1,163 @@
+/// A piecewise-constant distribution over [0, 1), with one piece per weight.
+///
+/// Used to importance sample tabulated functions such as the luminance
+/// of an environment map.
+#[derive(Debug, Clone)]
+pub struct Distribution1D {
+    weights: Vec<f64>,
+    cdf: Vec<f64>,
+    total: f64,
+}
+
+impl Distribution1D {
+    /// Weights must be non-negative. If they are all zero, the
+    /// distribution is uniform.
+    pub fn new(weights: Vec<f64>) -> Distribution1D {
+        let n = weights.len().max(1);
+        let mut total: f64 = weights.iter().sum();
+        let weights = match total > 0. {
+            true => weights,
+            false => {
+                total = 0.;
+                vec![1.; n]
+            }
+        };
+        let sum: f64 = weights.iter().sum();
+        let mut cdf = Vec::with_capacity(n + 1);
+        cdf.push(0.);
+        let mut running = 0.;
+        for w in &weights {
+            running += w / sum;
+            cdf.push(running);
+        }
+        cdf[n] = 1.;
+        Distribution1D { weights, cdf, total }
+    }
+
+    /// The sum of the weights it was built from.
+    pub fn total(&self) -> f64 {
+        self.total
+    }
+
+    pub fn len(&self) -> usize {
+        self.weights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.weights.is_empty()
+    }
+
+    /// Maps a uniform `u` in [0, 1) to a sample `x` in [0, 1).
+    ///
+    /// Returns the sample, the index of the piece it falls in,
+    /// and the sample's probability density.
+    pub fn sample(&self, u: f64) -> (f64, usize, f64) {
+        // Find the last piece whose cdf starts at or below u.
+        let index = match self.cdf.partition_point(|c| *c <= u) {
+            0 => 0,
+            i => (i - 1).min(self.len() - 1),
+        };
+        let start = self.cdf[index];
+        let width = self.cdf[index + 1] - start;
+        let offset = if width > 0. { (u - start) / width } else { 0. };
+        let x = ((index as f64 + offset) / self.len() as f64).min(1. - f64::EPSILON);
+        (x, index, self.density(index))
+    }
+
+    fn density(&self, index: usize) -> f64 {
+        (self.cdf[index + 1] - self.cdf[index]) * self.len() as f64
+    }
+
+    /// The probability density at `x` in [0, 1).
+    pub fn pdf(&self, x: f64) -> f64 {
+        let index = ((x * self.len() as f64) as usize).min(self.len() - 1);
+        self.density(index)
+    }
+}
+
+
+/// A piecewise-constant distribution over the unit square [0, 1)²,
+/// sampled by first picking a row from the marginal distribution and
+/// then a column from that row's conditional distribution.
+#[derive(Debug, Clone)]
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    /// `weights` are given row by row, `width` per row.
+    pub fn new(weights: &[f64], width: usize, height: usize) -> Distribution2D {
+        let conditional: Vec<Distribution1D> = (0..height)
+            .map(|row| Distribution1D::new(weights[row * width..(row + 1) * width].to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(conditional.iter().map(|d| d.total()).collect());
+        Distribution2D { conditional, marginal }
+    }
+
+    /// Returns a sample `(x, y)` and its probability density.
+    pub fn sample(&self, u1: f64, u2: f64) -> ((f64, f64), f64) {
+        let (y, row, row_pdf) = self.marginal.sample(u2);
+        let (x, _, column_pdf) = self.conditional[row].sample(u1);
+        ((x, y), row_pdf * column_pdf)
+    }
+
+    /// The probability density at `(x, y)`.
+    pub fn pdf(&self, x: f64, y: f64) -> f64 {
+        let row = ((y * self.marginal.len() as f64) as usize).min(self.marginal.len() - 1);
+        self.marginal.pdf(y) * self.conditional[row].pdf(x)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eq;
+
+    #[test]
+    fn samples_land_in_proportion_to_weights() {
+        let d = Distribution1D::new(vec![1., 0., 3.]);
+        let mut counts = [0; 3];
+        for i in 0..3000 {
+            let (_, index, _) = d.sample((i as f64 + 0.5) / 3000.);
+            counts[index] += 1;
+        }
+        assert_eq!(counts, [750, 0, 2250]);
+    }
+
+    #[test]
+    fn pdf_matches_sample_density() {
+        let d = Distribution1D::new(vec![1., 3.]);
+        let (x, index, pdf) = d.sample(0.9);
+        assert_eq!(index, 1);
+        assert!(x >= 0.5);
+        assert!(eq(pdf, 1.5));
+        assert!(eq(d.pdf(x), 1.5));
+        assert!(eq(d.pdf(0.1), 0.5));
+    }
+
+    #[test]
+    fn zero_weights_are_uniform() {
+        let d = Distribution1D::new(vec![0., 0.]);
+        assert!(eq(d.pdf(0.2), 1.));
+        assert!(eq(d.total(), 0.));
+    }
+
+    #[test]
+    fn two_dimensional_pdf_integrates_to_one() {
+        let weights = [1., 2., 0., 4., 5., 6.];
+        let d = Distribution2D::new(&weights, 3, 2);
+        let mut integral = 0.;
+        for row in 0..2 {
+            for col in 0..3 {
+                integral += d.pdf((col as f64 + 0.5) / 3., (row as f64 + 0.5) / 2.) / 6.;
+            }
+        }
+        assert!(eq(integral, 1.));
+        let ((x, y), pdf) = d.sample(0.5, 0.5);
+        assert!(eq(pdf, d.pdf(x, y)));
+        // Density is proportional to weight: the last cell has weight 6 of 18.
+        assert!(eq(d.pdf(0.9, 0.9), 6. / 18. * 6.));
+    }
+}
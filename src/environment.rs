@@ -0,0 +1,215 @@
+use std::f64::consts::PI;
+use rand::Rng;
+
+use crate::{
+    Result,
+    colors::Color,
+    distribution::Distribution2D,
+    hdr::HdrImage,
+    tuples::{Tuple, Vector},
+};
+
+pub mod sky;
+use sky::SunSky;
+
+
+/// Light arriving from infinitely far away, seen by rays that leave the scene.
+#[derive(Debug, Clone)]
+pub enum Environment {
+    /// The default blue sky gradient. It is not sampled as a light.
+    Gradient,
+    /// An equirectangular HDR image, importance sampled as a light.
+    Map(EnvironmentMap),
+    /// A physically based daylight sky. Only the sun is sampled as a light.
+    Sky(SunSky),
+}
+
+impl Environment {
+    /// Radiance arriving from the given direction.
+    pub fn radiance(&self, direction: &Tuple<Vector>) -> Color {
+        match self {
+            Environment::Gradient => {
+                let t = (1. + direction.unit().y) / 2.;
+                let blue = Color::from_u8(135, 181, 235);
+                let lightblue = Color::from_u8(135, 231, 235);
+                blue * t + lightblue * (1. - t)
+            }
+            Environment::Map(map) => map.radiance(direction),
+            Environment::Sky(sky) => sky.radiance(direction),
+        }
+    }
+
+    /// Whether lights should sample the environment directly.
+    pub fn is_sampled(&self) -> bool {
+        !matches!(self, Environment::Gradient)
+    }
+
+    /// Samples a unit direction towards the environment, returning it with
+    /// the radiance from there and the solid angle pdf of picking it.
+    pub fn sample(&self) -> Option<(Tuple<Vector>, Color, f64)> {
+        match self {
+            Environment::Gradient => None,
+            Environment::Map(map) => map.sample(),
+            Environment::Sky(sky) => sky.sample(),
+        }
+    }
+
+    /// The solid angle pdf with which `sample` picks `direction`.
+    pub fn pdf(&self, direction: &Tuple<Vector>) -> f64 {
+        match self {
+            Environment::Gradient => 0.,
+            Environment::Map(map) => map.pdf(direction),
+            Environment::Sky(sky) => sky.pdf(direction),
+        }
+    }
+}
+
+
+/// An equirectangular (latitude-longitude) environment map.
+///
+/// The top row of the image is straight up (+y), and the centre of the
+/// image faces -z before rotation. Directions are importance sampled
+/// in proportion to the luminance of the pixel they fall in.
+#[derive(Debug, Clone)]
+pub struct EnvironmentMap {
+    image: HdrImage,
+    /// Rotation around the vertical axis, in radians.
+    rotation: f64,
+    intensity: f64,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    pub fn new(image: HdrImage) -> EnvironmentMap {
+        let mut weights = Vec::with_capacity(image.width * image.height);
+        for y in 0..image.height {
+            // Rows near the poles cover less solid angle.
+            let sin_theta = (PI * (y as f64 + 0.5) / image.height as f64).sin();
+            for x in 0..image.width {
+                weights.push(image.pixel_at(x, y).luminance().max(0.) * sin_theta);
+            }
+        }
+        let distribution = Distribution2D::new(&weights, image.width, image.height);
+        EnvironmentMap { image, rotation: 0., intensity: 1., distribution }
+    }
+
+    /// Loads a Radiance `.hdr` or `.pfm` image.
+    pub fn load(path: &str) -> Result<EnvironmentMap> {
+        Ok(EnvironmentMap::new(HdrImage::load(path)?))
+    }
+
+    pub fn with_rotation(self, radians: f64) -> EnvironmentMap {
+        EnvironmentMap { rotation: radians, ..self }
+    }
+
+    pub fn with_intensity(self, intensity: f64) -> EnvironmentMap {
+        EnvironmentMap { intensity, ..self }
+    }
+
+    /// Image coordinates in [0, 1)² of a direction.
+    fn direction_to_uv(&self, direction: &Tuple<Vector>) -> (f64, f64) {
+        let d = direction.unit();
+        let theta = d.y.clamp(-1., 1.).acos();
+        let phi = d.x.atan2(-d.z) - self.rotation;
+        let u = (phi / (2. * PI)).rem_euclid(1.);
+        let v = (theta / PI).min(1. - f64::EPSILON);
+        (u, v)
+    }
+
+    fn uv_to_direction(&self, u: f64, v: f64) -> Tuple<Vector> {
+        let theta = PI * v;
+        let phi = 2. * PI * u + self.rotation;
+        Tuple::vector(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos())
+    }
+
+    pub fn radiance(&self, direction: &Tuple<Vector>) -> Color {
+        let (u, v) = self.direction_to_uv(direction);
+        let x = ((u * self.image.width as f64) as usize).min(self.image.width - 1);
+        let y = ((v * self.image.height as f64) as usize).min(self.image.height - 1);
+        self.image.pixel_at(x, y) * self.intensity
+    }
+
+    pub fn sample(&self) -> Option<(Tuple<Vector>, Color, f64)> {
+        let mut rng = crate::rng::thread_rng();
+        let ((u, v), uv_pdf) = self.distribution.sample(
+            rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0));
+        let sin_theta = (PI * v).sin();
+        if uv_pdf <= 0. || sin_theta <= 0. {
+            return None;
+        }
+        let direction = self.uv_to_direction(u, v);
+        let pdf = uv_pdf / (2. * PI * PI * sin_theta);
+        Some((direction, self.radiance(&direction), pdf))
+    }
+
+    pub fn pdf(&self, direction: &Tuple<Vector>) -> f64 {
+        let (u, v) = self.direction_to_uv(direction);
+        let sin_theta = (PI * v).sin();
+        if sin_theta <= 0. {
+            return 0.;
+        }
+        self.distribution.pdf(u, v) / (2. * PI * PI * sin_theta)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A map that is dark except for one bright pixel.
+    fn spot_map() -> EnvironmentMap {
+        let (width, height) = (8, 4);
+        let mut pixels = vec![Color::new(0.1, 0.1, 0.1); width * height];
+        pixels[width + 5] = Color::new(100., 100., 100.);
+        EnvironmentMap::new(HdrImage::new(width, height, pixels).unwrap())
+    }
+
+    #[test]
+    fn directions_map_to_the_image_and_back() {
+        let map = spot_map().with_rotation(0.7);
+        let direction = Tuple::vector(0.3, -0.4, 0.5).unit();
+        let (u, v) = map.direction_to_uv(&direction);
+        assert_eq!(map.uv_to_direction(u, v), direction);
+    }
+
+    #[test]
+    fn up_is_the_top_row() {
+        let map = spot_map();
+        let (_, v) = map.direction_to_uv(&Tuple::vector(0., 1., 0.));
+        assert!(v < 1e-6);
+    }
+
+    #[test]
+    fn sampling_prefers_bright_pixels() {
+        let map = spot_map();
+        let bright = (0..1000)
+            .filter_map(|_| map.sample())
+            .filter(|(_, radiance, _)| radiance.red > 1.)
+            .count();
+        assert!(bright > 900, "only {} samples hit the bright pixel", bright);
+    }
+
+    #[test]
+    fn sample_pdf_matches_pdf() {
+        let map = spot_map().with_rotation(1.3);
+        for _ in 0..100 {
+            let (direction, _, pdf) = map.sample().unwrap();
+            let expected = map.pdf(&direction);
+            assert!((pdf - expected).abs() < 1e-6 * expected.max(1.));
+        }
+    }
+
+    #[test]
+    fn importance_sampling_integrates_radiance() {
+        // A uniform map of radiance L has integral 4πL over the sphere.
+        let image = HdrImage::new(4, 2, vec![Color::new(2., 2., 2.); 8]).unwrap();
+        let map = EnvironmentMap::new(image).with_intensity(0.5);
+        let samples = 20000;
+        let estimate: f64 = (0..samples)
+            .filter_map(|_| map.sample())
+            .map(|(_, radiance, pdf)| radiance.red / pdf)
+            .sum::<f64>() / samples as f64;
+        assert!((estimate - 4. * PI).abs() < 0.2, "estimate was {}", estimate);
+    }
+}
@@ -0,0 +1,221 @@
+use std::f64::consts::PI;
+use rand::Rng;
+
+use crate::{
+    colors::Color,
+    spectrum::xyz_to_linear_srgb,
+    tuples::{Tuple, Vector},
+    normal::from_local,
+};
+
+/// Angular radius of the sun as seen from the earth, in radians.
+const SUN_ANGULAR_RADIUS: f64 = 0.00465;
+
+/// Scene radiance per kcd/m² of sky luminance.
+const LUMINANCE_SCALE: f64 = 0.1;
+
+/// Luminance of the sun above the atmosphere, in kcd/m².
+const SUN_LUMINANCE: f64 = 1.6e6;
+
+
+/// The analytic daylight model of Preetham, Shirley and Smits (1999):
+/// a clear sky lit by a sun disk at the given position, through an
+/// atmosphere of the given turbidity.
+#[derive(Debug, Clone)]
+pub struct SunSky {
+    /// Unit vector towards the centre of the sun.
+    sun_direction: Tuple<Vector>,
+    /// Angle between the zenith and the sun.
+    sun_zenith: f64,
+    /// Zenith luminance and chromaticity.
+    zenith: (f64, f64, f64),
+    /// Perez distribution coefficients for luminance and chromaticity.
+    perez: [[f64; 5]; 3],
+    sun_radiance: Color,
+}
+
+impl SunSky {
+    /// Sun elevation is measured up from the horizon, and azimuth clockwise
+    /// (seen from above) from -z towards +x, both in radians. Turbidity runs
+    /// from 2 (very clear) to about 10 (hazy).
+    pub fn new(elevation: f64, azimuth: f64, turbidity: f64) -> SunSky {
+        let sun_direction = Tuple::vector(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        );
+        // The model is only valid with the sun above the horizon.
+        let sun_zenith = (PI / 2. - elevation).clamp(0., PI / 2.);
+        let t = turbidity.max(1.);
+        let perez = [
+            [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251,
+             0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+            [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125,
+             -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+            [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102,
+             -0.0441 * t - 1.6537, -0.0109 * t + 0.0529],
+        ];
+
+        let chi = (4. / 9. - t / 120.) * (PI - 2. * sun_zenith);
+        let zenith_luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.);
+        let (s, s2, s3) = (sun_zenith, sun_zenith.powi(2), sun_zenith.powi(3));
+        let zenith_x = t * t * (0.00166 * s3 - 0.00375 * s2 + 0.00209 * s)
+            + t * (-0.02903 * s3 + 0.06377 * s2 - 0.03202 * s + 0.00394)
+            + (0.11693 * s3 - 0.21196 * s2 + 0.06052 * s + 0.25886);
+        let zenith_y = t * t * (0.00275 * s3 - 0.00610 * s2 + 0.00317 * s)
+            + t * (-0.04214 * s3 + 0.08970 * s2 - 0.04153 * s + 0.00516)
+            + (0.15346 * s3 - 0.26756 * s2 + 0.06670 * s + 0.26688);
+
+        SunSky {
+            sun_direction,
+            sun_zenith,
+            zenith: (zenith_luminance, zenith_x, zenith_y),
+            perez,
+            sun_radiance: sun_radiance(elevation, t),
+        }
+    }
+
+    /// Radiance of the sky alone, without the sun disk.
+    pub fn sky_radiance(&self, direction: &Tuple<Vector>) -> Color {
+        let d = direction.unit();
+        // Below the horizon, continue the horizon's color.
+        let cos_theta = d.y.max(0.01);
+        let cos_gamma = d.dot(&self.sun_direction).clamp(-1., 1.);
+        let gamma = cos_gamma.acos();
+
+        let relative = |c: &[f64; 5]| {
+            perez(c, cos_theta, gamma, cos_gamma)
+                / perez(c, 1., self.sun_zenith, self.sun_zenith.cos())
+        };
+        let (zenith_luminance, zenith_x, zenith_y) = self.zenith;
+        let luminance = zenith_luminance * relative(&self.perez[0]) * LUMINANCE_SCALE;
+        let x = zenith_x * relative(&self.perez[1]);
+        let y = zenith_y * relative(&self.perez[2]);
+        if luminance <= 0. || y <= 0. {
+            return Color::new(0., 0., 0.);
+        }
+        let color = xyz_to_linear_srgb(x / y * luminance, luminance, (1. - x - y) / y * luminance);
+        Color::new(color.red.max(0.), color.green.max(0.), color.blue.max(0.))
+    }
+
+    fn sees_sun(&self, direction: &Tuple<Vector>) -> bool {
+        self.sun_direction.y > -SUN_ANGULAR_RADIUS
+            && direction.unit().dot(&self.sun_direction) >= SUN_ANGULAR_RADIUS.cos()
+    }
+
+    /// Radiance of the sky, plus the sun if `direction` points at it.
+    pub fn radiance(&self, direction: &Tuple<Vector>) -> Color {
+        let sky = self.sky_radiance(direction);
+        match self.sees_sun(direction) {
+            true => sky + self.sun_radiance,
+            false => sky,
+        }
+    }
+
+    /// Samples a direction uniformly within the sun disk.
+    pub fn sample(&self) -> Option<(Tuple<Vector>, Color, f64)> {
+        if self.sun_direction.y <= -SUN_ANGULAR_RADIUS {
+            return None;
+        }
+        let mut rng = crate::rng::thread_rng();
+        let cos_max = SUN_ANGULAR_RADIUS.cos();
+        let cos_theta = 1. - rng.gen_range(0.0..1.0) * (1. - cos_max);
+        let sin_theta = (1. - cos_theta * cos_theta).sqrt();
+        let phi = rng.gen_range(0.0..2. * PI);
+        let local = Tuple::vector(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+        let direction = from_local(&local, &self.sun_direction);
+        Some((direction, self.radiance(&direction), sun_cone_pdf()))
+    }
+
+    pub fn pdf(&self, direction: &Tuple<Vector>) -> f64 {
+        match self.sees_sun(direction) {
+            true => sun_cone_pdf(),
+            false => 0.,
+        }
+    }
+}
+
+
+fn sun_cone_pdf() -> f64 {
+    1. / (2. * PI * (1. - SUN_ANGULAR_RADIUS.cos()))
+}
+
+
+/// The Perez et al. sky luminance distribution.
+fn perez(c: &[f64; 5], cos_theta: f64, gamma: f64, cos_gamma: f64) -> f64 {
+    (1. + c[0] * (c[1] / cos_theta).exp())
+        * (1. + c[2] * (c[3] * gamma).exp() + c[4] * cos_gamma * cos_gamma)
+}
+
+
+/// Radiance of the sun disk after Rayleigh and aerosol extinction along
+/// the path through the atmosphere, evaluated at a representative
+/// wavelength per channel (Preetham et al., appendix A.2).
+fn sun_radiance(elevation: f64, turbidity: f64) -> Color {
+    let zenith_degrees = 90. - elevation.to_degrees().max(0.);
+    let optical_mass = 1. / (zenith_degrees.to_radians().cos()
+        + 0.15 * (93.885 - zenith_degrees).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let transmittance = |micrometres: f64| {
+        let rayleigh = (-0.008735 * micrometres.powf(-4.08) * optical_mass).exp();
+        let aerosol = (-beta * micrometres.powf(-1.3) * optical_mass).exp();
+        rayleigh * aerosol
+    };
+    Color::new(transmittance(0.65), transmittance(0.55), transmittance(0.45))
+        * (SUN_LUMINANCE * LUMINANCE_SCALE)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::FRAC_PI_4;
+
+    #[test]
+    fn zenith_has_the_zenith_luminance() {
+        let sky = SunSky::new(FRAC_PI_4, 0., 3.);
+        let up = sky.sky_radiance(&Tuple::vector(0., 1., 0.));
+        let expected = sky.zenith.0 * LUMINANCE_SCALE;
+        assert!((up.luminance() - expected).abs() < 0.01 * expected);
+    }
+
+    #[test]
+    fn sky_is_brighter_near_the_sun() {
+        let sky = SunSky::new(0.5, 1., 3.);
+        let near_sun = from_local(&Tuple::vector(0.1, 0., 1.).unit(), &sky.sun_direction);
+        let away = Tuple::vector(-sky.sun_direction.x, 0.5, -sky.sun_direction.z);
+        assert!(sky.sky_radiance(&near_sun).luminance() > sky.sky_radiance(&away).luminance());
+    }
+
+    #[test]
+    fn clear_sky_is_blue() {
+        let sky = SunSky::new(1.2, 0., 2.5);
+        let color = sky.sky_radiance(&Tuple::vector(0.3, 0.6, 0.5));
+        assert!(color.blue > color.red);
+    }
+
+    #[test]
+    fn low_sun_is_dimmer_and_redder() {
+        let noon = sun_radiance(1.4, 3.);
+        let sunset = sun_radiance(0.05, 3.);
+        assert!(sunset.luminance() < noon.luminance());
+        assert!(sunset.red / sunset.blue > noon.red / noon.blue);
+    }
+
+    #[test]
+    fn samples_fall_within_the_sun() {
+        let sky = SunSky::new(0.7, 2., 4.);
+        for _ in 0..100 {
+            let (direction, radiance, pdf) = sky.sample().unwrap();
+            assert!(sky.sees_sun(&direction));
+            assert!(radiance.luminance() > sky.sun_radiance.luminance());
+            assert_eq!(pdf, sky.pdf(&direction));
+        }
+        assert_eq!(sky.pdf(&Tuple::vector(0., 1., 0.)), 0.);
+    }
+
+    #[test]
+    fn sun_below_horizon_is_not_sampled() {
+        assert!(SunSky::new(-0.2, 0., 3.).sample().is_none());
+    }
+}
@@ -0,0 +1,222 @@
+use crate::{Result, canvas::Canvas, colors::Color};
+
+
+/// Accumulates radiance samples for each pixel of an image, so that an
+/// image can be made from however many samples have been taken so far,
+/// and keeps track of how noisy each pixel still is.
+///
+/// Pixels are addressed like a `Canvas`, with the top row first.
+#[derive(Debug, Clone)]
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    sums: Vec<Color>,
+    /// Sums of squared sample luminances, for the variance.
+    square_sums: Vec<f64>,
+    counts: Vec<usize>,
+}
+
+/// Mean luminance below which noise is judged in absolute rather than
+/// relative terms, so that dark pixels converge.
+const NOISE_FLOOR: f64 = 1e-3;
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Film {
+        Film {
+            width,
+            height,
+            sums: vec![Color::new(0., 0., 0.); width * height],
+            square_sums: vec![0.; width * height],
+            counts: vec![0; width * height],
+        }
+    }
+
+    pub fn add_sample(&mut self, x: usize, y: usize, sample: Color) {
+        let i = y * self.width + x;
+        self.sums[i] = self.sums[i] + sample;
+        self.square_sums[i] += sample.luminance().powi(2);
+        self.counts[i] += 1;
+    }
+
+    /// Number of samples taken for a pixel.
+    pub fn samples_at(&self, x: usize, y: usize) -> usize {
+        self.counts[y * self.width + x]
+    }
+
+    /// Mean linear radiance of a pixel; black if it has no samples yet.
+    pub fn pixel_at(&self, x: usize, y: usize) -> Color {
+        let i = y * self.width + x;
+        match self.counts[i] {
+            0 => Color::new(0., 0., 0.),
+            n => self.sums[i] * (1. / n as f64),
+        }
+    }
+
+    /// Standard error of the pixel's mean luminance, relative to the
+    /// mean. Infinite until there are at least two samples.
+    pub fn relative_error(&self, x: usize, y: usize) -> f64 {
+        let i = y * self.width + x;
+        let n = self.counts[i] as f64;
+        if n < 2. {
+            return f64::INFINITY;
+        }
+        let mean = self.sums[i].luminance() / n;
+        let variance = ((self.square_sums[i] - n * mean * mean) / (n - 1.)).max(0.);
+        (variance / n).sqrt() / mean.max(NOISE_FLOOR)
+    }
+
+    /// The image as it stands, gamma encoded for display.
+    pub fn to_canvas(&self) -> Canvas {
+        let mut canvas = Canvas::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                canvas.write_pixel(x, y, self.pixel_at(x, y).gamma_encode());
+            }
+        }
+        canvas
+    }
+
+    /// How many samples each pixel got, from black for none to white
+    /// for the most any pixel got.
+    pub fn sample_heatmap(&self) -> Canvas {
+        let most = self.counts.iter().copied().max().unwrap_or(0).max(1) as f64;
+        let mut canvas = Canvas::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let v = self.samples_at(x, y) as f64 / most;
+                canvas.write_pixel(x, y, Color::new(v, v, v));
+            }
+        }
+        canvas
+    }
+
+    /// A copy of `count` rows of the film, starting at row `top`.
+    pub fn rows(&self, top: usize, count: usize) -> Film {
+        let (start, end) = (top * self.width, (top + count) * self.width);
+        Film {
+            width: self.width,
+            height: count,
+            sums: self.sums[start..end].to_vec(),
+            square_sums: self.square_sums[start..end].to_vec(),
+            counts: self.counts[start..end].to_vec(),
+        }
+    }
+
+    /// Adds all the samples of `other`, a film of the same width, to the
+    /// rows of this one starting at row `top`.
+    pub fn add_film(&mut self, other: &Film, top: usize) {
+        let offset = top * self.width;
+        for i in 0..other.counts.len() {
+            self.sums[offset + i] = self.sums[offset + i] + other.sums[i];
+            self.square_sums[offset + i] += other.square_sums[i];
+            self.counts[offset + i] += other.counts[i];
+        }
+    }
+
+    /// Little-endian binary encoding of the film, which keeps the exact
+    /// sums so that sampling can carry on from it.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(16 + self.counts.len() * 40);
+        out.extend((self.width as u64).to_le_bytes());
+        out.extend((self.height as u64).to_le_bytes());
+        for i in 0..self.counts.len() {
+            let sum = self.sums[i];
+            for v in [sum.red, sum.green, sum.blue, self.square_sums[i]] {
+                out.extend(v.to_le_bytes());
+            }
+            out.extend((self.counts[i] as u64).to_le_bytes());
+        }
+        out
+    }
+
+    /// Reads a film written by `to_bytes`, returning it and the bytes after it.
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Film, &[u8])> {
+        let mut words = bytes.chunks_exact(8).map(|w| <[u8; 8]>::try_from(w).unwrap());
+        let mut next = || words.next().ok_or("Film data is truncated");
+        let width = u64::from_le_bytes(next()?) as usize;
+        let height = u64::from_le_bytes(next()?) as usize;
+        let pixels = width.checked_mul(height).filter(|n| *n <= bytes.len() / 40)
+            .ok_or("Film data is truncated")?;
+        let mut film = Film::new(width, height);
+        for i in 0..pixels {
+            let mut value = || next().map(f64::from_le_bytes);
+            film.sums[i] = Color::new(value()?, value()?, value()?);
+            film.square_sums[i] = value()?;
+            film.counts[i] = u64::from_le_bytes(next()?) as usize;
+        }
+        Ok((film, &bytes[16 + pixels * 40..]))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixels_are_the_mean_of_their_samples() {
+        let mut film = Film::new(2, 1);
+        film.add_sample(1, 0, Color::new(1., 2., 3.));
+        film.add_sample(1, 0, Color::new(3., 2., 1.));
+        assert_eq!(film.samples_at(1, 0), 2);
+        assert_eq!(film.pixel_at(1, 0), Color::new(2., 2., 2.));
+        assert_eq!(film.pixel_at(0, 0), Color::new(0., 0., 0.));
+    }
+
+    #[test]
+    fn noisy_pixels_have_larger_errors() {
+        let mut film = Film::new(2, 1);
+        for i in 0..10 {
+            film.add_sample(0, 0, Color::new(0.5, 0.5, 0.5));
+            let v = if i % 2 == 0 { 0. } else { 1. };
+            film.add_sample(1, 0, Color::new(v, v, v));
+        }
+        assert!(film.relative_error(0, 0) < 1e-6);
+        // Variance 5/18 over 10 samples, relative to a mean of 0.5.
+        let expected = (5. / 18. / 10.0_f64).sqrt() / 0.5;
+        assert!((film.relative_error(1, 0) - expected).abs() < 1e-9);
+        assert_eq!(Film::new(1, 1).relative_error(0, 0), f64::INFINITY);
+    }
+
+    #[test]
+    fn films_survive_encoding() {
+        let mut film = Film::new(3, 2);
+        film.add_sample(2, 1, Color::new(0.1, 0.2, 0.3));
+        film.add_sample(2, 1, Color::new(0.7, 0.5, 0.3));
+        film.add_sample(0, 0, Color::new(1., 0., 0.));
+        let mut bytes = film.to_bytes();
+        bytes.push(42);
+        let (decoded, rest) = Film::from_bytes(&bytes).unwrap();
+        assert_eq!(rest, &[42]);
+        assert_eq!(decoded.to_bytes(), film.to_bytes());
+        assert_eq!(decoded.relative_error(2, 1), film.relative_error(2, 1));
+        assert!(Film::from_bytes(&bytes[..100]).is_err());
+    }
+
+    #[test]
+    fn films_combine_row_by_row() {
+        let mut film = Film::new(2, 3);
+        film.add_sample(1, 2, Color::new(1., 1., 1.));
+        let bottom = film.rows(1, 2);
+        assert_eq!((bottom.width, bottom.height), (2, 2));
+        assert_eq!(bottom.samples_at(1, 1), 1);
+
+        let mut combined = Film::new(2, 3);
+        combined.add_film(&bottom, 1);
+        combined.add_film(&bottom, 1);
+        assert_eq!(combined.samples_at(1, 2), 2);
+        assert_eq!(combined.pixel_at(1, 2), Color::new(1., 1., 1.));
+        assert_eq!(combined.samples_at(1, 0), 0);
+    }
+
+    #[test]
+    fn heatmap_is_brightest_where_most_samples_went() {
+        let mut film = Film::new(2, 1);
+        film.add_sample(0, 0, Color::new(1., 1., 1.));
+        film.add_sample(1, 0, Color::new(1., 1., 1.));
+        film.add_sample(1, 0, Color::new(1., 1., 1.));
+        let heatmap = film.sample_heatmap();
+        assert_eq!(heatmap.pixel_at(0, 0), Color::new(0.5, 0.5, 0.5));
+        assert_eq!(heatmap.pixel_at(1, 0), Color::new(1., 1., 1.));
+    }
+}
@@ -5,6 +5,7 @@ pub struct ObjectGroup {
 }
 
 impl ObjectGroup {
+    #[allow(clippy::new_without_default)]
     pub fn new() -> ObjectGroup {
         ObjectGroup {objects: vec![]}
     }
@@ -12,6 +13,14 @@ impl ObjectGroup {
     pub fn add(&mut self, obj: Object) {
         self.objects.push(obj)
     }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Object> {
+        self.objects.iter()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Object> {
+        self.objects.get(index)
+    }
 }
 
 impl Intersectable for ObjectGroup {
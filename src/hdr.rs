@@ -0,0 +1,344 @@
+use crate::Result;
+use crate::colors::Color;
+
+
+/// A high dynamic range image with linear float pixels,
+/// stored row by row from the top.
+#[derive(Debug, Clone)]
+pub struct HdrImage {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<Color>,
+}
+
+impl HdrImage {
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Result<HdrImage> {
+        if width == 0 || height == 0 || pixels.len() != width * height {
+            return Err("HDR image dimensions do not match its pixels".into());
+        }
+        Ok(HdrImage { width, height, pixels })
+    }
+
+    /// Pixel at column `x`, row `y` (counted from the top).
+    pub fn pixel_at(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Loads a Radiance `.hdr`, a `.pfm` or a `.pgm` file, based on its extension.
+    pub fn load(path: &str) -> Result<HdrImage> {
+        let bytes = std::fs::read(path)?;
+        let lower = path.to_lowercase();
+        if lower.ends_with(".pfm") {
+            HdrImage::from_pfm(&bytes)
+        } else if lower.ends_with(".pgm") {
+            HdrImage::from_pgm(&bytes)
+        } else if lower.ends_with(".hdr") || lower.ends_with(".pic") {
+            HdrImage::from_radiance(&bytes)
+        } else {
+            Err(format!("Unknown HDR image format: {}", path).into())
+        }
+    }
+
+    /// Parses a Radiance RGBE image, flat or run-length encoded.
+    ///
+    /// Only the standard `-Y height +X width` orientation is supported.
+    pub fn from_radiance(bytes: &[u8]) -> Result<HdrImage> {
+        let mut reader = ByteReader { bytes, position: 0 };
+        let magic = reader.line()?;
+        if !magic.starts_with("#?") {
+            return Err("Not a Radiance HDR file".into());
+        }
+        loop {
+            let line = reader.line()?;
+            if line.is_empty() {
+                break;
+            }
+            if let Some(format) = line.strip_prefix("FORMAT=") {
+                if format != "32-bit_rle_rgbe" {
+                    return Err(format!("Unsupported HDR pixel format: {}", format).into());
+                }
+            }
+        }
+        let resolution = reader.line()?;
+        let fields: Vec<&str> = resolution.split_whitespace().collect();
+        if fields.len() != 4 || fields[0] != "-Y" || fields[2] != "+X" {
+            return Err(format!("Unsupported HDR orientation: {}", resolution).into());
+        }
+        let height: usize = fields[1].parse()?;
+        let width: usize = fields[3].parse()?;
+
+        let mut pixels = Vec::with_capacity(width * height);
+        let mut scanline = vec![[0u8; 4]; width];
+        for _ in 0..height {
+            reader.scanline(&mut scanline)?;
+            pixels.extend(scanline.iter().map(rgbe_to_color));
+        }
+        HdrImage::new(width, height, pixels)
+    }
+
+    /// Parses a Portable Float Map, in color (`PF`) or grayscale (`Pf`).
+    pub fn from_pfm(bytes: &[u8]) -> Result<HdrImage> {
+        let mut reader = ByteReader { bytes, position: 0 };
+        let channels = match reader.token()?.as_str() {
+            "PF" => 3,
+            "Pf" => 1,
+            other => return Err(format!("Not a PFM file: {}", other).into()),
+        };
+        let width: usize = reader.token()?.parse()?;
+        let height: usize = reader.token()?.parse()?;
+        let scale: f64 = reader.token()?.parse()?;
+        // A single whitespace byte separates the header from the data.
+        reader.position += 1;
+        let little_endian = scale < 0.;
+
+        let data = &bytes[reader.position.min(bytes.len())..];
+        let expected = width * height * channels * 4;
+        if data.len() < expected {
+            return Err("PFM file is truncated".into());
+        }
+        let floats: Vec<f64> = data[..expected]
+            .chunks_exact(4)
+            .map(|b| {
+                let b = [b[0], b[1], b[2], b[3]];
+                match little_endian {
+                    true => f32::from_le_bytes(b) as f64,
+                    false => f32::from_be_bytes(b) as f64,
+                }
+            })
+            .collect();
+
+        // PFM rows run from the bottom up.
+        let mut pixels = Vec::with_capacity(width * height);
+        for row in (0..height).rev() {
+            for col in 0..width {
+                let i = (row * width + col) * channels;
+                pixels.push(match channels {
+                    3 => Color::new(floats[i], floats[i + 1], floats[i + 2]),
+                    _ => Color::new(floats[i], floats[i], floats[i]),
+                });
+            }
+        }
+        HdrImage::new(width, height, pixels)
+    }
+
+    /// Parses a plain (`P2`) or binary (`P5`) grayscale PGM, scaling values
+    /// linearly so that the maximum value is 1.
+    pub fn from_pgm(bytes: &[u8]) -> Result<HdrImage> {
+        let mut reader = ByteReader { bytes, position: 0 };
+        let binary = match reader.header_token()?.as_str() {
+            "P2" => false,
+            "P5" => true,
+            other => return Err(format!("Not a PGM file: {}", other).into()),
+        };
+        let width: usize = reader.header_token()?.parse()?;
+        let height: usize = reader.header_token()?.parse()?;
+        let max_value: u16 = reader.header_token()?.parse()?;
+        if max_value == 0 {
+            return Err("PGM maximum value must be positive".into());
+        }
+
+        let mut values = Vec::with_capacity(width * height);
+        if binary {
+            // A single whitespace byte separates the header from the data.
+            reader.position += 1;
+            let wide = max_value > 255;
+            for _ in 0..width * height {
+                values.push(match wide {
+                    true => u16::from_be_bytes([reader.byte()?, reader.byte()?]),
+                    false => reader.byte()? as u16,
+                });
+            }
+        } else {
+            for _ in 0..width * height {
+                values.push(reader.header_token()?.parse()?);
+            }
+        }
+        let pixels = values
+            .into_iter()
+            .map(|v| {
+                let v = v.min(max_value) as f64 / max_value as f64;
+                Color::new(v, v, v)
+            })
+            .collect();
+        HdrImage::new(width, height, pixels)
+    }
+}
+
+
+fn rgbe_to_color(rgbe: &[u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::new(0., 0., 0.);
+    }
+    let f = 2f64.powi(rgbe[3] as i32 - (128 + 8));
+    Color::new(rgbe[0] as f64 * f, rgbe[1] as f64 * f, rgbe[2] as f64 * f)
+}
+
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl ByteReader<'_> {
+    fn byte(&mut self) -> Result<u8> {
+        let b = *self.bytes.get(self.position).ok_or("Unexpected end of file")?;
+        self.position += 1;
+        Ok(b)
+    }
+
+    /// Reads up to (and consumes) the next newline.
+    fn line(&mut self) -> Result<String> {
+        let mut line = vec![];
+        loop {
+            match self.byte()? {
+                b'\n' => break,
+                b => line.push(b),
+            }
+        }
+        Ok(String::from_utf8_lossy(&line).trim_end().to_string())
+    }
+
+    /// Reads a whitespace-delimited token, leaving the delimiter unread.
+    fn token(&mut self) -> Result<String> {
+        while self.bytes.get(self.position).is_some_and(|b| b.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+        let start = self.position;
+        while self.bytes.get(self.position).is_some_and(|b| !b.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+        Ok(String::from_utf8_lossy(&self.bytes[start..self.position]).to_string())
+    }
+
+    /// Reads a token, skipping `#` comments as found in Netpbm headers.
+    fn header_token(&mut self) -> Result<String> {
+        loop {
+            let token = self.token()?;
+            if token.is_empty() {
+                return Err("Unexpected end of file".into());
+            }
+            if !token.starts_with('#') {
+                return Ok(token);
+            }
+            self.line()?;
+        }
+    }
+
+    /// Reads one RGBE scanline, in either the flat or the
+    /// (per-channel) run-length encoded layout.
+    fn scanline(&mut self, out: &mut [[u8; 4]]) -> Result<()> {
+        let width = out.len();
+        let header = self.bytes.get(self.position..self.position + 4);
+        let is_rle = (8..0x8000).contains(&width) && header.is_some_and(|h| {
+            h[0] == 2 && h[1] == 2 && ((h[2] as usize) << 8 | h[3] as usize) == width
+        });
+        if !is_rle {
+            for pixel in out.iter_mut() {
+                for channel in pixel.iter_mut() {
+                    *channel = self.byte()?;
+                }
+            }
+            return Ok(());
+        }
+
+        self.position += 4;
+        for channel in 0..4 {
+            let mut x = 0;
+            while x < width {
+                let count = self.byte()? as usize;
+                if count > 128 {
+                    let run = count - 128;
+                    let value = self.byte()?;
+                    if x + run > width {
+                        return Err("Bad HDR run length".into());
+                    }
+                    for pixel in out[x..x + run].iter_mut() {
+                        pixel[channel] = value;
+                    }
+                    x += run;
+                } else {
+                    if count == 0 || x + count > width {
+                        return Err("Bad HDR run length".into());
+                    }
+                    for pixel in out[x..x + count].iter_mut() {
+                        pixel[channel] = self.byte()?;
+                    }
+                    x += count;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(width: usize, height: usize) -> Vec<u8> {
+        format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", height, width).into_bytes()
+    }
+
+    #[test]
+    fn reads_flat_radiance_files() {
+        let mut bytes = header(2, 1);
+        bytes.extend([128, 64, 0, 129, 0, 0, 0, 0]);
+        let image = HdrImage::from_radiance(&bytes).unwrap();
+        assert_eq!(image.pixel_at(0, 0), Color::new(1., 0.5, 0.));
+        assert_eq!(image.pixel_at(1, 0), Color::new(0., 0., 0.));
+    }
+
+    #[test]
+    fn reads_run_length_encoded_radiance_files() {
+        let width = 8;
+        let mut bytes = header(width, 1);
+        bytes.extend([2, 2, 0, width as u8]);
+        // Red: a run of 8. Green: 8 literals. Blue: two runs of 4. Exponent: a run.
+        bytes.extend([128 + 8, 128]);
+        bytes.push(8);
+        bytes.extend(0..8);
+        bytes.extend([128 + 4, 0, 128 + 4, 64]);
+        bytes.extend([128 + 8, 128]);
+        let image = HdrImage::from_radiance(&bytes).unwrap();
+        assert_eq!(image.width, 8);
+        assert_eq!(image.pixel_at(0, 0), Color::new(0.5, 0., 0.));
+        assert_eq!(image.pixel_at(7, 0), Color::new(0.5, 7. / 256., 0.25));
+    }
+
+    #[test]
+    fn rejects_other_orientations() {
+        let bytes = b"#?RADIANCE\n\n+Y 1 +X 1\n\x80\x80\x80\x81".to_vec();
+        assert!(HdrImage::from_radiance(&bytes).is_err());
+    }
+
+    #[test]
+    fn reads_pfm_files_bottom_up() {
+        let mut bytes = b"PF\n1 2\n-1.0\n".to_vec();
+        for v in [1.0f32, 2., 3., 4., 5., 6.] {
+            bytes.extend(v.to_le_bytes());
+        }
+        let image = HdrImage::from_pfm(&bytes).unwrap();
+        assert_eq!(image.pixel_at(0, 0), Color::new(4., 5., 6.));
+        assert_eq!(image.pixel_at(0, 1), Color::new(1., 2., 3.));
+    }
+
+    #[test]
+    fn reads_plain_and_binary_pgm() {
+        let plain = HdrImage::from_pgm(b"P2\n# a comment\n2 1\n4\n0 2\n").unwrap();
+        assert_eq!(plain.pixel_at(1, 0), Color::new(0.5, 0.5, 0.5));
+        let mut bytes = b"P5 1 2 255\n".to_vec();
+        bytes.extend([255, 0]);
+        let binary = HdrImage::from_pgm(&bytes).unwrap();
+        assert_eq!(binary.pixel_at(0, 0), Color::new(1., 1., 1.));
+        assert_eq!(binary.pixel_at(0, 1), Color::new(0., 0., 0.));
+    }
+
+    #[test]
+    fn reads_big_endian_grayscale_pfm() {
+        let mut bytes = b"Pf\n1 1\n1.0\n".to_vec();
+        bytes.extend(0.5f32.to_be_bytes());
+        let image = HdrImage::from_pfm(&bytes).unwrap();
+        assert_eq!(image.pixel_at(0, 0), Color::new(0.5, 0.5, 0.5));
+    }
+}
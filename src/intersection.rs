@@ -4,6 +4,8 @@ use crate::{
     shapes::Shape,
     shapes::sphere::intersect_sphere,
     shapes::cube::intersect_cube,
+    shapes::quad::intersect_quad,
+    shapes::disk::intersect_disk,
     minimum_by_key,
 };
 
@@ -14,6 +16,7 @@ pub struct Intersection<'a> {
 }
 
 impl Intersection<'_> {
+    #[allow(clippy::extra_unused_lifetimes)]
     pub fn new<'a>(t: f64, object: & Object) -> Intersection<'_> {
         Intersection { t, object }
     }
@@ -26,11 +29,28 @@ pub trait Intersectable {
 
 impl Intersectable for Object {
     fn intersect<'a>(ray: &Ray, obj: &'a Self) -> Vec<Intersection<'a>> {
-        let ray_in_sphere_space = ray.transform(&obj.inverse_transform);
-        match &obj.shape {
-            Shape::Sphere => intersect_sphere(&ray_in_sphere_space, obj),
-            Shape::Cube => intersect_cube(&ray_in_sphere_space, obj),
+        // Moving objects are costly to pose, so skip them if the ray misses their motion.
+        if obj.is_moving() && !obj.bounds().hit(ray, f64::NEG_INFINITY, f64::INFINITY) {
+            return vec![];
         }
+        let ray_in_sphere_space = ray.transform(&obj.pose_at(ray.time).inverse);
+        let intersections = intersect_shape(&ray_in_sphere_space, obj);
+        match &obj.medium {
+            None => intersections,
+            Some(medium) => medium.collide(ray, &ray_in_sphere_space, obj, intersections),
+        }
+    }
+}
+
+
+/// Intersects a ray, already in object space, with the surface of the
+/// object's shape, ignoring any medium inside it.
+pub fn intersect_shape<'a>(local_ray: &Ray, obj: &'a Object) -> Vec<Intersection<'a>> {
+    match &obj.shape {
+        Shape::Sphere => intersect_sphere(local_ray, obj),
+        Shape::Cube => intersect_cube(local_ray, obj),
+        Shape::Quad => intersect_quad(local_ray, obj),
+        Shape::Disk => intersect_disk(local_ray, obj),
     }
 }
 
@@ -14,6 +14,20 @@ pub mod materials;
 pub mod cli;
 pub mod shapes;
 pub mod raytrace;
+pub mod spectrum;
+pub mod media;
+pub mod world;
+pub mod distribution;
+pub mod hdr;
+pub mod environment;
+pub mod lights;
+pub mod motion;
+pub mod bounds;
+pub mod animation;
+pub mod film;
+pub mod rng;
+pub mod checkpoint;
+pub mod chunk;
 
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
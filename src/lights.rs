@@ -0,0 +1,202 @@
+use crate::{
+    colors::Color,
+    tuples::{Tuple, Point, Vector},
+};
+
+pub mod ies;
+use ies::IesProfile;
+
+
+/// A direction towards a light, picked to light a point directly.
+#[derive(Debug, Clone, Copy)]
+pub struct LightSample {
+    /// Unit vector from the lit point towards the light.
+    pub direction: Tuple<Vector>,
+    /// Distance to the light along `direction`; infinite for the environment.
+    pub distance: f64,
+    /// Radiance arriving from the light, before any occlusion. For delta
+    /// lights, this is instead the irradiance they cast on a surface
+    /// facing them.
+    pub radiance: Color,
+    /// Solid angle pdf of the sample, including the chance of picking this
+    /// light. None for delta lights, which scattered rays can never hit.
+    pub pdf: Option<f64>,
+}
+
+
+/// An infinitely small light, with no geometry of its own.
+///
+/// Point and spot intensities are in W/sr, directional irradiance in W/m².
+/// Point and spot lights can take an IES profile, which scales their
+/// intensity by direction relative to its brightest direction.
+#[derive(Debug, Clone)]
+pub enum Light {
+    /// Shines equally in all directions, unless given a profile,
+    /// whose nadir then points down (-y).
+    Point {
+        position: Tuple<Point>,
+        intensity: Color,
+        profile: Option<IesProfile>,
+    },
+    /// Shines in a cone around `direction`, at full intensity within
+    /// `inner_angle` of it and fading smoothly to nothing at `outer_angle`.
+    /// A profile's nadir points along `direction`.
+    Spot {
+        position: Tuple<Point>,
+        direction: Tuple<Vector>,
+        intensity: Color,
+        inner_angle: f64,
+        outer_angle: f64,
+        profile: Option<IesProfile>,
+    },
+    /// Parallel light travelling along `direction`, such as sunlight.
+    Directional {
+        direction: Tuple<Vector>,
+        irradiance: Color,
+    },
+}
+
+impl Light {
+    pub fn point(position: Tuple<Point>, intensity: Color) -> Light {
+        Light::Point { position, intensity, profile: None }
+    }
+
+    /// Angles are in radians, measured from the cone's axis.
+    pub fn spot(
+        position: Tuple<Point>,
+        target: Tuple<Point>,
+        intensity: Color,
+        inner_angle: f64,
+        outer_angle: f64,
+    ) -> Light {
+        Light::Spot {
+            position,
+            direction: (target - position).unit(),
+            intensity,
+            inner_angle: inner_angle.min(outer_angle),
+            outer_angle,
+            profile: None,
+        }
+    }
+
+    pub fn directional(direction: Tuple<Vector>, irradiance: Color) -> Light {
+        Light::Directional { direction: direction.unit(), irradiance }
+    }
+
+    /// Shapes the light's intensity by a photometric profile.
+    /// Directional lights are left unchanged.
+    pub fn with_profile(self, profile: IesProfile) -> Light {
+        match self {
+            Light::Point { position, intensity, .. } =>
+                Light::Point { position, intensity, profile: Some(profile) },
+            Light::Spot { position, direction, intensity, inner_angle, outer_angle, .. } =>
+                Light::Spot {
+                    position, direction, intensity, inner_angle, outer_angle,
+                    profile: Some(profile),
+                },
+            directional => directional,
+        }
+    }
+
+    /// The light arriving at `position`, as a delta-light sample.
+    pub fn sample(&self, position: &Tuple<Point>) -> Option<LightSample> {
+        let (to_light, intensity) = match self {
+            Light::Directional { direction, irradiance } => return Some(LightSample {
+                direction: -*direction,
+                distance: f64::INFINITY,
+                radiance: *irradiance,
+                pdf: None,
+            }),
+            Light::Point { position: light, intensity, profile } => {
+                let to_light = light - position;
+                let nadir = Tuple::vector(0., -1., 0.);
+                (to_light, *intensity * profile_factor(profile, &to_light, &nadir))
+            }
+            Light::Spot { position: light, direction, intensity, inner_angle, outer_angle, profile } => {
+                let to_light = light - position;
+                let cos = -to_light.unit().dot(direction);
+                let falloff = smoothstep(outer_angle.cos(), inner_angle.cos(), cos)
+                    * profile_factor(profile, &to_light, direction);
+                if falloff <= 0. {
+                    return None;
+                }
+                (to_light, *intensity * falloff)
+            }
+        };
+        let distance = to_light.magnitude();
+        if distance <= 0. {
+            return None;
+        }
+        Some(LightSample {
+            direction: to_light * (1. / distance),
+            distance,
+            radiance: intensity * (1. / (distance * distance)),
+            pdf: None,
+        })
+    }
+}
+
+
+/// Relative intensity of an optional profile towards a point that sees
+/// the light along `to_light`.
+fn profile_factor(
+    profile: &Option<IesProfile>,
+    to_light: &Tuple<Vector>,
+    nadir: &Tuple<Vector>,
+) -> f64 {
+    match profile {
+        Some(profile) => profile.relative_intensity(&-to_light.unit(), nadir),
+        None => 1.,
+    }
+}
+
+
+fn smoothstep(from: f64, to: f64, x: f64) -> f64 {
+    if from >= to {
+        return if x >= to { 1. } else { 0. };
+    }
+    let t = ((x - from) / (to - from)).clamp(0., 1.);
+    t * t * (3. - 2. * t)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eq;
+
+    #[test]
+    fn point_lights_fall_off_with_the_square_of_distance() {
+        let light = Light::point(Tuple::point(0., 4., 0.), Color::new(8., 8., 8.));
+        let sample = light.sample(&Tuple::point(0., 0., 0.)).unwrap();
+        assert_eq!(sample.direction, Tuple::vector(0., 1., 0.));
+        assert!(eq(sample.distance, 4.));
+        assert_eq!(sample.radiance, Color::new(0.5, 0.5, 0.5));
+        assert!(sample.pdf.is_none());
+    }
+
+    #[test]
+    fn spot_lights_only_reach_inside_their_cone() {
+        let light = Light::spot(
+            Tuple::point(0., 1., 0.),
+            Tuple::point(0., 0., 0.),
+            Color::new(1., 1., 1.),
+            0.2,
+            0.4,
+        );
+        let centre = light.sample(&Tuple::point(0., 0., 0.)).unwrap();
+        assert_eq!(centre.radiance, Color::new(1., 1., 1.));
+        let edge = light.sample(&Tuple::point(0.3f64.tan(), 0., 0.)).unwrap();
+        assert!(edge.radiance.red < centre.radiance.red && edge.radiance.red > 0.);
+        assert!(light.sample(&Tuple::point(1., 0., 0.)).is_none());
+    }
+
+    #[test]
+    fn directional_lights_come_from_infinitely_far() {
+        let light = Light::directional(Tuple::vector(0., -2., 0.), Color::new(3., 3., 3.));
+        let sample = light.sample(&Tuple::point(5., 0., 5.)).unwrap();
+        assert_eq!(sample.direction, Tuple::vector(0., 1., 0.));
+        assert_eq!(sample.distance, f64::INFINITY);
+        assert_eq!(sample.radiance, Color::new(3., 3., 3.));
+    }
+}
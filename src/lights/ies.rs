@@ -0,0 +1,241 @@
+use crate::Result;
+use crate::tuples::{Tuple, Vector};
+use crate::normal::tangent_frame;
+
+
+/// A luminaire's intensity distribution, read from an IES LM-63 photometric file.
+///
+/// Only type C photometry (the usual one for architectural fixtures) is
+/// supported: vertical angles are measured from the nadir, which points
+/// straight down out of the luminaire, and horizontal angles around it.
+#[derive(Debug, Clone)]
+pub struct IesProfile {
+    /// In degrees, ascending.
+    vertical_angles: Vec<f64>,
+    /// In degrees, ascending.
+    horizontal_angles: Vec<f64>,
+    /// Candela, one row of vertical samples per horizontal angle.
+    candela: Vec<Vec<f64>>,
+    max_candela: f64,
+}
+
+impl IesProfile {
+    /// Parses the text of an LM-63 file (1986, 1991, 1995 or 2002 flavour).
+    pub fn parse(text: &str) -> Result<IesProfile> {
+        // Keywords and free-form header lines run up to the TILT line.
+        let mut lines = text.lines();
+        let tilt = loop {
+            let line = lines.next().ok_or("IES file has no TILT line")?.trim();
+            if let Some(tilt) = line.strip_prefix("TILT=") {
+                break tilt.trim().to_string();
+            }
+        };
+        let mut tokens = lines.flat_map(|line| line.split_whitespace());
+        let mut next = || -> Result<f64> {
+            Ok(tokens.next().ok_or("IES file ended early")?.parse::<f64>()?)
+        };
+
+        match tilt.as_str() {
+            "NONE" => {}
+            "INCLUDE" => {
+                // Lamp-to-luminaire geometry, then tilt angles and their factors.
+                next()?;
+                let count = next()? as usize;
+                for _ in 0..2 * count {
+                    next()?;
+                }
+            }
+            file => return Err(format!(
+                "IES tilt data in a separate file ({}) is not supported", file).into()),
+        }
+
+        let _lamps = next()?;
+        let _lumens_per_lamp = next()?;
+        let multiplier = next()?;
+        let vertical_count = next()? as usize;
+        let horizontal_count = next()? as usize;
+        let photometric_type = next()? as usize;
+        let _units = next()?;
+        let _dimensions = (next()?, next()?, next()?);
+        let ballast_factor = next()?;
+        let _ballast_lamp_factor = next()?;
+        let _input_watts = next()?;
+
+        if photometric_type != 1 {
+            return Err(format!(
+                "Only type C IES photometry is supported, got type {}", photometric_type
+            ).into());
+        }
+        if vertical_count == 0 || horizontal_count == 0 {
+            return Err("IES file has an empty angle table".into());
+        }
+
+        let vertical_angles = (0..vertical_count).map(|_| next()).collect::<Result<Vec<_>>>()?;
+        let horizontal_angles = (0..horizontal_count).map(|_| next()).collect::<Result<Vec<_>>>()?;
+        let mut candela = Vec::with_capacity(horizontal_count);
+        for _ in 0..horizontal_count {
+            let row = (0..vertical_count)
+                .map(|_| next().map(|c| (c * multiplier * ballast_factor).max(0.)))
+                .collect::<Result<Vec<_>>>()?;
+            candela.push(row);
+        }
+        let ascending = |angles: &[f64]| angles.windows(2).all(|w| w[0] < w[1]);
+        if !ascending(&vertical_angles) || !ascending(&horizontal_angles) {
+            return Err("IES angles must be in ascending order".into());
+        }
+
+        let max_candela = candela.iter().flatten().cloned().fold(0., f64::max);
+        Ok(IesProfile { vertical_angles, horizontal_angles, candela, max_candela })
+    }
+
+    pub fn load(path: &str) -> Result<IesProfile> {
+        // Many manufacturer files are Latin-1 rather than UTF-8.
+        let bytes = std::fs::read(path)?;
+        IesProfile::parse(&String::from_utf8_lossy(&bytes))
+    }
+
+    /// The brightest intensity in the profile, in candela.
+    pub fn max_candela(&self) -> f64 {
+        self.max_candela
+    }
+
+    /// Intensity in candela at `vertical` degrees from the nadir
+    /// and `horizontal` degrees around it, interpolated bilinearly.
+    pub fn candela(&self, vertical: f64, horizontal: f64) -> f64 {
+        let Some((v0, v1, tv)) = bracket(&self.vertical_angles, vertical) else {
+            return 0.
+        };
+        let (h0, h1, th) = self.horizontal_bracket(horizontal);
+        let at = |h: usize, v: usize| self.candela[h][v];
+        let near = at(h0, v0) * (1. - tv) + at(h0, v1) * tv;
+        let far = at(h1, v0) * (1. - tv) + at(h1, v1) * tv;
+        near * (1. - th) + far * th
+    }
+
+    /// Finds the horizontal table entries around an angle, using the
+    /// symmetry implied by the table's range.
+    fn horizontal_bracket(&self, horizontal: f64) -> (usize, usize, f64) {
+        let angles = &self.horizontal_angles;
+        let (first, last) = (angles[0], angles[angles.len() - 1]);
+        let mut h = horizontal.rem_euclid(360.);
+        if angles.len() == 1 {
+            // Rotationally symmetric.
+            return (0, 0, 0.);
+        } else if last - first <= 90. {
+            // Symmetric in each quadrant.
+            if h > 180. { h = 360. - h; }
+            if h > 90. { h = 180. - h; }
+        } else if last - first <= 180. {
+            // Symmetric about the 0-180 degree plane.
+            if h > 180. { h = 360. - h; }
+        }
+        if let Some(found) = bracket(angles, h) {
+            return found;
+        }
+        if last - first > 180. {
+            // A full table that stops short of 360: wrap around to the start.
+            let h = if h < first { h + 360. } else { h };
+            let span = first + 360. - last;
+            return (angles.len() - 1, 0, ((h - last) / span).clamp(0., 1.));
+        }
+        match h < first {
+            true => (0, 0, 0.),
+            false => (angles.len() - 1, angles.len() - 1, 0.),
+        }
+    }
+
+    /// Intensity towards the unit vector `direction`, relative to the
+    /// brightest direction, for a luminaire whose nadir points along `nadir`.
+    pub fn relative_intensity(&self, direction: &Tuple<Vector>, nadir: &Tuple<Vector>) -> f64 {
+        if self.max_candela <= 0. {
+            return 0.;
+        }
+        let vertical = direction.dot(nadir).clamp(-1., 1.).acos().to_degrees();
+        let (tangent, bitangent) = tangent_frame(nadir);
+        let horizontal = direction.dot(&bitangent).atan2(direction.dot(&tangent)).to_degrees();
+        self.candela(vertical, horizontal) / self.max_candela
+    }
+}
+
+
+/// The indices of the table entries on either side of `x`, and how far
+/// `x` lies between them. None if `x` is outside the table.
+fn bracket(angles: &[f64], x: f64) -> Option<(usize, usize, f64)> {
+    let last = angles.len() - 1;
+    if x < angles[0] - 1e-9 || x > angles[last] + 1e-9 {
+        return None;
+    }
+    if last == 0 {
+        return Some((0, 0, 0.));
+    }
+    let upper = angles.partition_point(|a| *a < x).clamp(1, last);
+    let lower = upper - 1;
+    let t = ((x - angles[lower]) / (angles[upper] - angles[lower])).clamp(0., 1.);
+    Some((lower, upper, t))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eq;
+
+    fn profile(horizontal: &str, candela: &str) -> IesProfile {
+        let count = horizontal.split_whitespace().count();
+        IesProfile::parse(&format!(
+            "IESNA:LM-63-2002\n[TEST] unit\nTILT=NONE\n\
+             1 1000 2 3 {} 1 2 0 0 0\n1 1 10\n0 45 90\n{}\n{}\n",
+            count, horizontal, candela
+        )).unwrap()
+    }
+
+    #[test]
+    fn values_are_scaled_by_the_multiplier() {
+        let p = profile("0", "100 50 0");
+        assert!(eq(p.candela(0., 0.), 200.));
+        assert!(eq(p.max_candela(), 200.));
+    }
+
+    #[test]
+    fn vertical_angles_are_interpolated() {
+        let p = profile("0", "100 50 0");
+        assert!(eq(p.candela(22.5, 123.), 150.));
+        assert!(eq(p.candela(120., 0.), 0.));
+    }
+
+    #[test]
+    fn bilateral_tables_are_mirrored() {
+        let p = profile("0 90 180", "100 100 100 10 10 10 40 40 40");
+        assert!(eq(p.candela(0., 45.), 110.));
+        assert!(eq(p.candela(0., 315.), 110.));
+        assert!(eq(p.candela(0., 270.), 20.));
+    }
+
+    #[test]
+    fn quadrant_tables_are_mirrored_four_ways() {
+        let p = profile("0 90", "100 100 100 20 20 20");
+        assert!(eq(p.candela(0., 180.), 200.));
+        assert!(eq(p.candela(0., 270.), 40.));
+        assert!(eq(p.candela(0., 135.), p.candela(0., 45.)));
+    }
+
+    #[test]
+    fn full_tables_wrap_around() {
+        let p = profile("0 120 240", "30 30 30 60 60 60 90 90 90");
+        assert!(eq(p.candela(0., 300.), 120.));
+    }
+
+    #[test]
+    fn nadir_is_the_vertical_axis() {
+        let p = profile("0", "100 50 0");
+        let down = Tuple::vector(0., -1., 0.);
+        assert!(eq(p.relative_intensity(&down, &down), 1.));
+        assert!(eq(p.relative_intensity(&Tuple::vector(1., 0., 0.), &down), 0.));
+    }
+
+    #[test]
+    fn other_photometry_types_are_rejected() {
+        let text = "TILT=NONE\n1 1000 1 1 1 2 2 0 0 0\n1 1 10\n0\n0\n100\n";
+        assert!(IesProfile::parse(text).is_err());
+    }
+}
@@ -1,65 +1,269 @@
 use std::error::Error;
+use std::path::Path;
 
-use crayfish::camera::Camera;
-use crayfish::cli::{make_config, cli};
+use crayfish::camera::{Camera, CameraModel, stereo::StereoCamera};
+use crayfish::camera::aperture::{Aperture, ApertureMask};
+use crayfish::animation::{Track, Interpolation, CameraKeys, frame_path};
+use crayfish::checkpoint::Checkpoint;
+use crayfish::chunk::{self, Chunk};
+use crayfish::cli::{make_config, make_merge_config, cli, Config, MergeConfig};
 use crayfish::colors::Color;
-use crayfish::materials::{Metallic, Lambertian, Dielectric};
+use crayfish::film::Film;
+use crayfish::materials::{Material, Metallic, Lambertian, Dielectric, Dispersion, Volume};
+use crayfish::materials::principled::Principled;
+use crayfish::media::Fog;
+use crayfish::environment::{Environment, EnvironmentMap, sky::SunSky};
 use crayfish::object::Object;
 use crayfish::tuples::Tuple;
 use crayfish::transformations::*;
 use crayfish::groups::ObjectGroup;
 use crayfish::shapes::Shape;
-use crayfish::raytrace::render_scene;
+use crayfish::raytrace::render_progressive;
+use crayfish::world::World;
 
 
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let config = make_config(cli().get_matches())?;
+    let matches = cli().get_matches();
+    if let Some(("merge", merge_matches)) = matches.subcommand() {
+        return merge(&make_merge_config(merge_matches));
+    }
+    let mut config = make_config(matches)?;
+    // A stereo image holds both eyes, each with the requested aspect ratio.
+    let eye_aspect_ratio = config.aspect_ratio;
+    if let Some(layout) = config.stereo {
+        config.aspect_ratio = layout.image_aspect_ratio(eye_aspect_ratio);
+    }
 
-    // Camera
-    let camera = Camera::new(
-        Tuple::point(4., 4., -10.),
-        Tuple::point(1., 1., 0.),
-        config.aspect_ratio,
-        config.fov_radians,
-        None,
-        config.aperture_radius,
-    );
+    // Renders of part of the image are left for `crayfish merge` to put together.
+    let extension = match config.row_range == (0, config.image_height) {
+        true => "ppm",
+        false => "chunk",
+    };
+    let Some((start, end)) = config.frames else {
+        let outpath = format!("{}.{}", config.outfile, extension);
+        return render_frame(&config, eye_aspect_ratio, 0., &outpath, config.resume);
+    };
+    // Only the first unfinished frame of a sequence has a checkpoint to resume.
+    let mut resume = config.resume;
+    for frame in start..=end {
+        let outpath = frame_path(&config.outfile, frame, extension);
+        if Path::new(&outpath).exists() {
+            println!("Skipping frame {}, {} already exists", frame, outpath);
+            continue;
+        }
+        println!("Rendering frame {} ({} to {})", frame, start, end);
+        render_frame(&config, eye_aspect_ratio, frame as f64, &outpath, resume)?;
+        resume = false;
+    }
+
+    Ok(())
+}
+
+
+/// Renders `frame` of the animation to `outpath`, which is a `.ppm`
+/// image or, for part of the image, a `.chunk`.
+///
+/// A progressive render writes the image so far next to it, as
+/// `.partial.ppm`, so that an unfinished frame never looks finished.
+/// With `resume`, it carries on from the checkpoint next to it.
+fn render_frame(
+    config: &Config,
+    eye_aspect_ratio: f64,
+    frame: f64,
+    outpath: &str,
+    resume: bool,
+) -> Result<(), Box<dyn Error>> {
+    // Animation
+    let CameraKeys { lookfrom, lookat } = match &config.camera_keys {
+        Some(path) => CameraKeys::load(path)?,
+        None => CameraKeys {
+            lookfrom: Track::new(Interpolation::Smooth)
+                .with_key(0., Tuple::point(4., 4., -10.))
+                .with_key(100., Tuple::point(-4., 4., -10.)),
+            lookat: Track::constant(Tuple::point(1., 1., 0.)),
+        },
+    };
+    let bounce = Track::new(Interpolation::Smooth)
+        .with_key(0., translation(2., 0., 0.))
+        .with_key(50., translation(2., 1., 0.))
+        .with_key(100., translation(2., 0., 0.));
 
     // World
-    let mut world = ObjectGroup::new();
-    world.add(Object::new_sphere().with_transform(
+    let [diffuse, glass, metal, ground] = scene_materials(config.principled);
+    let mut objects = ObjectGroup::new();
+    objects.add(Object::new_sphere().with_transform(
         translation(0., 2., 0.,)
-    ).with_material(
-        Box::new(Lambertian::new(Color::from_u8(35, 21, 105)))
-    ));
-    world.add(Object::new(Shape::Cube).with_transform(
+    ).with_material(diffuse));
+    objects.add(Object::new(Shape::Cube).with_transform(
         translation(0., 0., 0.)
-    ).with_material(
-        Box::new(Dielectric::new(1.52))
-    ));
+    ).with_material(glass));
     // .with_material(
     //     Box::new(Metallic::new(
     //         Color::new(0.3, 0.3, 0.3,), 0.1))
     // ));
-    world.add(Object::new_sphere().with_transform(
-        translation(2., 0., 0.)
-    ).with_material(
-        Box::new(Metallic::new(
-            Color::new(1., 0.3, 0.3,), 0.02))
-    ));
-    world.add(Object::new_sphere().with_transform(
+    objects.add(bounce.pose(Object::new_sphere(), frame, config.shutter).with_material(metal));
+    objects.add(Object::new_sphere().with_transform(
         scaling(100., 100., 100.)
         .translate(0., -101., 0.)
-    ).with_material(
-        Box::new(Lambertian::new(Color::from_u8(50, 50, 70)))
-    ));
+    ).with_material(ground));
+
+    // Camera
+    let (lookfrom_end, lookat_end) = (lookfrom.at(frame + 1.).unwrap(), lookat.at(frame + 1.).unwrap());
+    let (lookfrom, lookat) = (lookfrom.at(frame).unwrap(), lookat.at(frame).unwrap());
+    // Carry on towards the next frame while the shutter is open.
+    let end = (config.shutter.1 > 0.).then_some((lookfrom_end, lookat_end));
+    let camera: Box<dyn CameraModel> = match config.panorama {
+        Some(panorama) => panorama.camera(
+            lookfrom, lookat, end, config.up, config.roll_radians, eye_aspect_ratio),
+        None => {
+            let aperture = match &config.aperture_image {
+                Some(path) => Aperture::Mask(ApertureMask::load(path)?),
+                None => Aperture::polygon(config.aperture_blades, config.aperture_rotation_radians),
+            };
+            let camera = Camera::new(
+                lookfrom,
+                lookat,
+                eye_aspect_ratio,
+                config.fov_radians,
+                None,
+                config.aperture_radius,
+            )
+                .with_up(config.up)
+                .with_roll(config.roll_radians)
+                .with_aperture(aperture)
+                .with_anamorphic_squeeze(config.anamorphic_squeeze)
+                .with_projection(config.projection);
+            let camera = match end {
+                Some((lookfrom, lookat)) => camera.with_motion(lookfrom, lookat),
+                None => camera,
+            };
+            let camera = match config.focus {
+                Some(focus) => camera.with_focus(focus, &objects)?,
+                None => camera,
+            };
+            match config.stereo {
+                Some(layout) => {
+                    let convergence_distance = config.convergence_distance
+                        .unwrap_or_else(|| (lookat - lookfrom).magnitude());
+                    Box::new(StereoCamera::new(
+                        camera, config.interpupillary_distance, convergence_distance, layout))
+                }
+                None => Box::new(camera),
+            }
+        }
+    };
+
+    let mut world = World::new(objects);
+    if let Some(density) = config.fog_density {
+        world = world.with_fog(Fog::new(
+            density,
+            Volume::henyey_greenstein(Color::new(0.9, 0.9, 0.9), config.fog_anisotropy),
+        ).with_far(config.fog_far));
+    }
 
-    let canvas = render_scene(&world, &camera, &config);
+    if let Some(path) = &config.environment {
+        world = world.with_environment(Environment::Map(
+            EnvironmentMap::load(path)?
+                .with_rotation(config.environment_rotation_radians)
+                .with_intensity(config.environment_intensity)
+        ));
+    }
+    if config.sky {
+        world = world.with_environment(Environment::Sky(SunSky::new(
+            config.sun_elevation_radians,
+            config.sun_azimuth_radians,
+            config.turbidity,
+        )));
+    }
 
-    let outpath = format!("{}.ppm", config.outfile);
-    std::fs::write(outpath, canvas.to_ppm())
+    let stem = outpath.trim_end_matches(".ppm").trim_end_matches(".chunk");
+    let partial_path = format!("{}.partial.ppm", stem);
+    let checkpoint_path = format!("{}.checkpoint", stem);
+    let resume = match resume {
+        true => {
+            if !Path::new(&checkpoint_path).exists() {
+                return Err(format!("Nothing to resume: {} doesn't exist", checkpoint_path).into());
+            }
+            let checkpoint = Checkpoint::load(&checkpoint_path)?;
+            checkpoint.check_resumable(config)
+                .map_err(|e| format!("{}: {}", checkpoint_path, e))?;
+            println!("Resuming from {}, pass {}", checkpoint_path, checkpoint.pass);
+            Some(checkpoint)
+        }
+        false => None,
+    };
+    let film = render_progressive(
+        &world,
+        camera.as_ref(),
+        config,
+        resume,
+        &mut |film| {
+            std::fs::write(&partial_path, film.to_canvas().to_ppm())
+                .expect("Unable to write file");
+        },
+        &mut |checkpoint| {
+            checkpoint.save(&checkpoint_path).expect("Unable to write checkpoint");
+        },
+    );
+    if outpath.ends_with(".chunk") {
+        Chunk::from_film(&film, config.row_range, config.seed, config.scene_fingerprint()).save(outpath)?;
+    } else {
+        write_image(&film, stem, config.sample_heatmap);
+    }
+    for leftover in [&partial_path, &checkpoint_path] {
+        if Path::new(leftover).exists() {
+            std::fs::remove_file(leftover)?;
+        }
+    }
+    Ok(())
+}
+
+
+/// Materials for the scene's diffuse sphere, glass cube, metal sphere and
+/// ground, either as separate materials or as principled ones.
+fn scene_materials(principled: bool) -> [Box<dyn Material>; 4] {
+    let purple = Color::from_u8(35, 21, 105);
+    let red = Color::new(1., 0.3, 0.3);
+    let grey = Color::from_u8(50, 50, 70);
+    if principled {
+        return [
+            Box::new(Principled::new(purple).with_roughness(1.).with_sheen(0.5)),
+            Box::new(Principled::new(Color::new(1., 1., 1.))
+                .with_transmission(1.)
+                .with_roughness(0.)
+                .with_refractive_index(1.52)),
+            Box::new(Principled::new(red).with_metallic(1.).with_roughness(0.1)),
+            Box::new(Principled::new(grey).with_roughness(0.8).with_clearcoat(0.5, 0.1)),
+        ];
+    }
+    [
+        Box::new(Lambertian::new(purple)),
+        Box::new(Dielectric::new(1.52).with_dispersion(Dispersion::bk7())),
+        Box::new(Metallic::new(red, 0.02)),
+        Box::new(Lambertian::new(grey)),
+    ]
+}
+
+
+/// Writes `film` to `stem.ppm`, and its sample counts to `stem.samples.ppm`
+/// if `sample_heatmap` is set.
+fn write_image(film: &Film, stem: &str, sample_heatmap: bool) {
+    std::fs::write(format!("{}.ppm", stem), film.to_canvas().to_ppm())
         .expect("Unable to write file");
+    if sample_heatmap {
+        std::fs::write(format!("{}.samples.ppm", stem), film.sample_heatmap().to_ppm())
+            .expect("Unable to write file");
+    }
+}
+
 
+/// Puts the chunks of a split render back together.
+fn merge(config: &MergeConfig) -> Result<(), Box<dyn Error>> {
+    let chunks = config.chunks.iter()
+        .map(|path| Chunk::load(path))
+        .collect::<Result<Vec<Chunk>, _>>()?;
+    let film = chunk::merge(&chunks)?;
+    write_image(&film, &config.outfile, config.sample_heatmap);
     Ok(())
 }
@@ -2,12 +2,50 @@ use crate::{
     ray::Ray,
     intersection::Intersection,
     colors::Color,
-    tuples::Tuple, normal::{reflect, refract},
+    tuples::{Tuple, Point, Vector}, normal::{reflect, refract},
+    spectrum::REFERENCE_WAVELENGTH,
+    media::PhaseFunction,
 };
 use rand::prelude::*;
+use std::f64::consts::PI;
+
+pub mod principled;
+pub mod subsurface;
 
 pub trait Material: std::fmt::Debug {
     fn scatter(&self, ray: &Ray, hit: &Intersection) -> Option<Scattered>;
+
+    /// The BSDF times the cosine term for scattering `ray` at `hit` towards
+    /// `direction`, together with the pdf with which `scatter` would have
+    /// picked that direction.
+    ///
+    /// Used to light surfaces by sampling lights directly. Materials whose
+    /// scattering is a delta distribution (mirrors, glass) return None.
+    fn evaluate(
+        &self,
+        _ray: &Ray,
+        _hit: &Intersection,
+        _direction: &Tuple<Vector>,
+    ) -> Option<(Color, f64)> {
+        None
+    }
+
+    /// Radiance the surface emits in direction `outgoing`,
+    /// where its unit normal is `normal`.
+    fn emitted(&self, _normal: &Tuple<Vector>, _outgoing: &Tuple<Vector>) -> Color {
+        Color::new(0., 0., 0.)
+    }
+
+    /// Whether objects with this material should be sampled as lights.
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    /// The fraction of light left after travelling `distance` through the
+    /// inside of the object, for materials that absorb light passing through.
+    fn transmittance(&self, _distance: f64) -> Color {
+        Color::new(1., 1., 1.)
+    }
 }
 
 impl Default for Box<dyn Material> {
@@ -20,11 +58,18 @@ impl Default for Box<dyn Material> {
 pub struct Scattered {
     pub attenuation: Color,
     pub ray: Ray,
+    /// The pdf of the sampled direction, if the material can also be
+    /// evaluated for arbitrary directions. None for delta scattering.
+    pub pdf: Option<f64>,
 }
 
 impl Scattered {
     pub fn new(attenuation: Color, ray: Ray) -> Scattered {
-        Scattered { attenuation, ray }
+        Scattered { attenuation, ray, pdf: None }
+    }
+
+    pub fn with_pdf(self, pdf: f64) -> Scattered {
+        Scattered { pdf: Some(pdf), ..self }
     }
 }
 
@@ -43,16 +88,28 @@ impl Lambertian {
 impl Material for Lambertian {
     fn scatter(&self, ray: &Ray, hit: &Intersection) -> Option<Scattered> {
         let hit_position = ray.position(hit.t);
-        let normal = hit.object.normal_at(hit_position);
+        let normal = hit.object.normal_at(hit_position, ray.time);
         let rand_vec = Tuple::random_in_unit_sphere().unit();
         let new_direction = match rand_vec == -normal {
             false => normal + rand_vec,
             true => normal
         };
+        let pdf = normal.dot(&new_direction.unit()).max(0.) / PI;
         Some(Scattered::new(
             self.albedo,
-            Ray::new(hit_position, new_direction)
-        ))
+            ray.spawn(hit_position, new_direction)
+        ).with_pdf(pdf))
+    }
+
+    fn evaluate(
+        &self,
+        ray: &Ray,
+        hit: &Intersection,
+        direction: &Tuple<Vector>,
+    ) -> Option<(Color, f64)> {
+        let normal = hit.object.normal_at(ray.position(hit.t), ray.time);
+        let cos = normal.dot(&direction.unit()).max(0.);
+        Some((self.albedo * (cos / PI), cos / PI))
     }
 }
 
@@ -72,28 +129,204 @@ impl Metallic {
 impl Material for Metallic {
     fn scatter(&self, ray: &Ray, hit: &Intersection) -> Option<Scattered> {
         let position = ray.position(hit.t);
-        let normal = hit.object.normal_at(position);
+        let normal = hit.object.normal_at(position, ray.time);
         let reflected = reflect(&ray.direction, &normal);
         let fuzz = Tuple::random_in_unit_sphere() * self.fuzz;
         Some(Scattered::new(
             self.albedo,
-            Ray::new(position, reflected + fuzz),
+            ray.spawn(position, reflected + fuzz),
         ))
     }
 }
 
 
+/// A surface that glows with constant radiance from its front face,
+/// and reflects nothing. Objects made of it act as area lights.
+#[derive(Debug)]
+pub struct DiffuseLight {
+    radiance: Color,
+}
+
+impl DiffuseLight {
+    pub fn new(radiance: Color) -> DiffuseLight {
+        DiffuseLight { radiance }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _ray: &Ray, _hit: &Intersection) -> Option<Scattered> {
+        None
+    }
+
+    fn emitted(&self, normal: &Tuple<Vector>, outgoing: &Tuple<Vector>) -> Color {
+        match normal.dot(outgoing) > 0. {
+            true => self.radiance,
+            false => Color::new(0., 0., 0.),
+        }
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+}
+
+
+/// Scattering off the particles of a participating medium.
+///
+/// Used as the material of objects filled with a `Medium`, and by fog.
+#[derive(Debug, Clone, Copy)]
+pub struct Volume {
+    albedo: Color,
+    phase: PhaseFunction,
+}
+
+impl Volume {
+    pub fn new(albedo: Color, phase: PhaseFunction) -> Volume {
+        Volume { albedo, phase }
+    }
+
+    pub fn isotropic(albedo: Color) -> Volume {
+        Volume::new(albedo, PhaseFunction::Isotropic)
+    }
+
+    pub fn henyey_greenstein(albedo: Color, asymmetry: f64) -> Volume {
+        Volume::new(albedo, PhaseFunction::HenyeyGreenstein(asymmetry))
+    }
+
+    /// Scatters `ray` off a particle at `position`.
+    pub fn scatter_at(&self, ray: &Ray, position: Tuple<Point>) -> Scattered {
+        let direction = self.phase.sample(&ray.direction);
+        let pdf = self.phase.value(ray.direction.unit().dot(&direction.unit()));
+        Scattered::new(self.albedo, ray.spawn(position, direction)).with_pdf(pdf)
+    }
+
+    /// The albedo-weighted phase function for scattering `ray` towards
+    /// `direction`, and the pdf of sampling it.
+    pub fn evaluate_towards(&self, ray: &Ray, direction: &Tuple<Vector>) -> (Color, f64) {
+        let pdf = self.phase.value(ray.direction.unit().dot(&direction.unit()));
+        (self.albedo * pdf, pdf)
+    }
+}
+
+impl Material for Volume {
+    fn scatter(&self, ray: &Ray, hit: &Intersection) -> Option<Scattered> {
+        Some(self.scatter_at(ray, ray.position(hit.t)))
+    }
+
+    fn evaluate(
+        &self,
+        ray: &Ray,
+        _hit: &Intersection,
+        direction: &Tuple<Vector>,
+    ) -> Option<(Color, f64)> {
+        Some(self.evaluate_towards(ray, direction))
+    }
+}
+
+
+/// How a refractive index varies with wavelength.
+#[derive(Debug, Clone, Copy)]
+pub enum Dispersion {
+    /// n = a + b / λ², with λ in micrometres.
+    Cauchy { a: f64, b: f64 },
+    /// n² = 1 + Σ bᵢ λ² / (λ² - cᵢ), with λ in micrometres and cᵢ in µm².
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    /// Schott N-BK7, a common optical crown glass.
+    pub fn bk7() -> Dispersion {
+        Dispersion::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653],
+        }
+    }
+
+    /// Diamond, which disperses strongly ("fire").
+    pub fn diamond() -> Dispersion {
+        Dispersion::Sellmeier {
+            b: [0.3306, 4.3356, 0.],
+            c: [0.030625, 0.011236, 0.],
+        }
+    }
+
+    /// The refractive index at `wavelength`, given in nanometres.
+    pub fn refractive_index(&self, wavelength: f64) -> f64 {
+        let micrometres = wavelength / 1000.;
+        let l2 = micrometres * micrometres;
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                let sum: f64 = b.iter().zip(c.iter())
+                    .map(|(b, c)| b * l2 / (l2 - c))
+                    .sum();
+                (1. + sum).sqrt()
+            }
+        }
+    }
+}
+
+
+/// A clear (or tinted) refractive material such as glass or water.
+///
+/// Light travelling through the medium is attenuated according to the
+/// Beer-Lambert law: after a distance `d` inside, each channel is scaled by
+/// `exp(-absorption * d)`, so thicker parts of an object get darker and
+/// more saturated.
 #[derive(Debug)]
 pub struct Dielectric {
-    pub refractive_index: f64
+    pub refractive_index: f64,
+    /// Absorption coefficient per unit of distance, per channel.
+    pub absorption: Color,
+    /// If set, rays carrying a wavelength refract by the index at that
+    /// wavelength instead of `refractive_index`.
+    pub dispersion: Option<Dispersion>,
 }
 
 impl Dielectric {
     pub fn new(refractive_index: f64) -> Dielectric {
-        Dielectric { refractive_index }
+        Dielectric {
+            refractive_index,
+            absorption: Color::new(0., 0., 0.),
+            dispersion: None,
+        }
+    }
+
+    /// Makes the refractive index wavelength dependent.
+    ///
+    /// `refractive_index` is set to the index at the reference (d-line)
+    /// wavelength, which is used for rays that do not carry a wavelength.
+    pub fn with_dispersion(self, dispersion: Dispersion) -> Dielectric {
+        Dielectric {
+            refractive_index: dispersion.refractive_index(REFERENCE_WAVELENGTH),
+            dispersion: Some(dispersion),
+            ..self
+        }
     }
 
-    fn schlick_reflectance(cosine: f64, refractive_index: f64) -> f64 {
+    fn refractive_index_at(&self, wavelength: Option<f64>) -> f64 {
+        match (self.dispersion, wavelength) {
+            (Some(dispersion), Some(wavelength)) => dispersion.refractive_index(wavelength),
+            _ => self.refractive_index,
+        }
+    }
+
+    pub fn with_absorption(self, absorption: Color) -> Dielectric {
+        Dielectric { absorption, ..self }
+    }
+
+    /// Sets the absorption so that light which has travelled `distance`
+    /// through the medium is left with color `tint`.
+    pub fn with_tint(self, tint: Color, distance: f64) -> Dielectric {
+        let coefficient = |transmittance: f64| -transmittance.max(1e-6).ln() / distance;
+        self.with_absorption(Color::new(
+            coefficient(tint.red),
+            coefficient(tint.green),
+            coefficient(tint.blue),
+        ))
+    }
+
+    pub(crate) fn schlick_reflectance(cosine: f64, refractive_index: f64) -> f64 {
         let r0 = (1. - refractive_index) / (1. + refractive_index);
         let r0 = r0 * r0;
         r0 + (1. - r0) * (1. - cosine).powi(5)
@@ -103,13 +336,14 @@ impl Dielectric {
 impl Material for Dielectric {
     fn scatter(&self, ray: &Ray, hit: &Intersection) -> Option<Scattered> {
         let position = ray.position(hit.t);
-        let normal = hit.object.normal_at(position);
+        let normal = hit.object.normal_at(position, ray.time);
         // Determine if we're going into or out of the material
         // and set refractive ratio and normal accordingly.
         let into_material = normal.dot(&ray.direction) < 0.;
+        let refractive_index = self.refractive_index_at(ray.wavelength);
         let nfrom_over_nto = match into_material {
-            true => 1./self.refractive_index,
-            false => self.refractive_index,
+            true => 1./refractive_index,
+            false => refractive_index,
         };
         let normal = if into_material {normal} else {-normal};
 
@@ -118,7 +352,7 @@ impl Material for Dielectric {
         let sin_theta = (1. - cos_theta * cos_theta).sqrt();
         let cannot_refract = nfrom_over_nto * sin_theta > 1.;
 
-        let mut rng = rand::thread_rng();
+        let mut rng = crate::rng::thread_rng();
         let should_reflect = Dielectric::schlick_reflectance(
             cos_theta, nfrom_over_nto) > rng.gen_range(0.0..1.0);
         let direction = if cannot_refract || should_reflect {
@@ -127,9 +361,109 @@ impl Material for Dielectric {
             refract(&incoming, normal, nfrom_over_nto)
         };
 
+        // A ray hitting the surface from the inside has just travelled
+        // through the medium from its origin, which is where it entered,
+        // last reflected internally, or scattered in fog inside. Paths
+        // that end at fog inside instead are attenuated by `ray_color`.
+        let attenuation = match into_material {
+            true => Color::new(1., 1., 1.),
+            false => self.transmittance(hit.t * ray.direction.magnitude()),
+        };
+
         Some(Scattered::new(
-            Color::new(1., 1., 1.),
-            Ray::new(position, direction),
+            attenuation,
+            ray.spawn(position, direction),
         ))
     }
+
+    fn transmittance(&self, distance: f64) -> Color {
+        Color::new(
+            (-self.absorption.red * distance).exp(),
+            (-self.absorption.green * distance).exp(),
+            (-self.absorption.blue * distance).exp(),
+        )
+    }
+}
+
+
+#[cfg(test)]
+mod dielectric_tests {
+    use super::*;
+    use crate::eq;
+    use crate::object::Object;
+
+    #[test]
+    fn clear_dielectric_does_not_attenuate() {
+        let material = Dielectric::new(1.5);
+        let sphere = Object::new_sphere();
+        let ray = Ray::from_coords(0., 0., 0., 0., 0., 1.);
+        let hit = Intersection::new(1., &sphere);
+        let scattered = material.scatter(&ray, &hit).unwrap();
+        assert_eq!(scattered.attenuation, Color::new(1., 1., 1.));
+    }
+
+    #[test]
+    fn absorption_applies_over_distance_travelled_inside() {
+        let material = Dielectric::new(1.5)
+            .with_absorption(Color::new(0.5, 1., 2.));
+        let sphere = Object::new_sphere();
+        // Starts at the centre, so travels 1 unit before reaching the surface.
+        let ray = Ray::from_coords(0., 0., 0., 0., 0., 2.);
+        let hit = Intersection::new(0.5, &sphere);
+        let scattered = material.scatter(&ray, &hit).unwrap();
+        assert_eq!(
+            scattered.attenuation,
+            Color::new((-0.5_f64).exp(), (-1.0_f64).exp(), (-2.0_f64).exp())
+        );
+    }
+
+    #[test]
+    fn absorption_does_not_apply_when_entering() {
+        let material = Dielectric::new(1.5)
+            .with_absorption(Color::new(0.5, 1., 2.));
+        let sphere = Object::new_sphere();
+        let ray = Ray::from_coords(0., 0., -5., 0., 0., 1.);
+        let hit = Intersection::new(4., &sphere);
+        let scattered = material.scatter(&ray, &hit).unwrap();
+        assert_eq!(scattered.attenuation, Color::new(1., 1., 1.));
+    }
+
+    #[test]
+    fn absorption_is_known_for_paths_that_end_inside() {
+        let material: Box<dyn Material> = Box::new(Dielectric::new(1.5)
+            .with_absorption(Color::new(0.5, 1., 2.)));
+        assert_eq!(
+            material.transmittance(2.),
+            Color::new((-1.0_f64).exp(), (-2.0_f64).exp(), (-4.0_f64).exp())
+        );
+        assert_eq!(Lambertian::new(Color::new(1., 0., 0.)).transmittance(2.), Color::new(1., 1., 1.));
+    }
+
+    #[test]
+    fn bk7_index_matches_catalogue() {
+        let bk7 = Dispersion::bk7();
+        assert!((bk7.refractive_index(587.56) - 1.5168).abs() < 1e-4);
+        assert!(bk7.refractive_index(450.) > bk7.refractive_index(650.));
+    }
+
+    #[test]
+    fn diamond_index_matches_catalogue() {
+        assert!((Dispersion::diamond().refractive_index(587.56) - 2.417).abs() < 1e-3);
+    }
+
+    #[test]
+    fn dispersive_dielectric_uses_ray_wavelength() {
+        let material = Dielectric::new(1.5).with_dispersion(
+            Dispersion::Cauchy { a: 1.5, b: 0.01 });
+        assert!(eq(material.refractive_index, 1.5 + 0.01 / 0.58756_f64.powi(2)));
+        assert!(eq(material.refractive_index_at(None), material.refractive_index));
+        assert!(eq(material.refractive_index_at(Some(500.)), 1.5 + 0.01 / 0.25));
+    }
+
+    #[test]
+    fn tint_is_reached_at_given_distance() {
+        let tint = Color::new(0.9, 0.5, 0.1);
+        let material = Dielectric::new(1.5).with_tint(tint, 2.);
+        assert_eq!(material.transmittance(2.), tint);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,439 @@
+use std::f64::consts::PI;
+use rand::prelude::*;
+
+use crate::{
+    ray::Ray,
+    intersection::Intersection,
+    colors::Color,
+    tuples::{Tuple, Vector},
+    normal::{reflect, refract, from_local},
+};
+use super::{Material, Scattered, Dielectric};
+
+/// Smallest GGX alpha we sample with; perfectly smooth lobes are singular.
+const MIN_ALPHA: f64 = 1e-3;
+
+
+/// A layered "principled" material in the style of the Disney BSDF.
+///
+/// A single set of artist-facing parameters blends a diffuse base with sheen,
+/// a GGX specular layer (dielectric or metallic), a clearcoat on top and a
+/// (possibly rough) transmissive lobe. Parameters map directly onto the
+/// glTF metallic-roughness model and its clearcoat, sheen, specular,
+/// transmission and ior extensions.
+///
+/// Sampling picks one lobe in proportion to its expected contribution and then
+/// weights the sample by the full BSDF over the combined pdf of all reflective
+/// lobes, so every lobe is sampled without bias regardless of which one
+/// generated the direction.
+#[derive(Debug)]
+pub struct Principled {
+    base_color: Color,
+    metallic: f64,
+    roughness: f64,
+    specular: f64,
+    clearcoat: f64,
+    clearcoat_roughness: f64,
+    sheen: f64,
+    transmission: f64,
+    refractive_index: f64,
+}
+
+/// Probabilities of picking each lobe, for a given outgoing direction.
+struct LobeProbabilities {
+    diffuse: f64,
+    specular: f64,
+    clearcoat: f64,
+    transmission: f64,
+}
+
+impl Principled {
+    pub fn new(base_color: Color) -> Principled {
+        Principled {
+            base_color,
+            metallic: 0.,
+            roughness: 0.5,
+            specular: 0.5,
+            clearcoat: 0.,
+            clearcoat_roughness: 0.03,
+            sheen: 0.,
+            transmission: 0.,
+            refractive_index: 1.5,
+        }
+    }
+
+    pub fn with_metallic(self, metallic: f64) -> Principled {
+        Principled { metallic: metallic.clamp(0., 1.), ..self }
+    }
+
+    pub fn with_roughness(self, roughness: f64) -> Principled {
+        Principled { roughness: roughness.clamp(0., 1.), ..self }
+    }
+
+    /// Strength of the dielectric specular highlight; 0.5 corresponds to an
+    /// index of refraction of 1.5 (4% reflectance at normal incidence).
+    pub fn with_specular(self, specular: f64) -> Principled {
+        Principled { specular: specular.max(0.), ..self }
+    }
+
+    pub fn with_clearcoat(self, clearcoat: f64, clearcoat_roughness: f64) -> Principled {
+        Principled {
+            clearcoat: clearcoat.clamp(0., 1.),
+            clearcoat_roughness: clearcoat_roughness.clamp(0., 1.),
+            ..self
+        }
+    }
+
+    pub fn with_sheen(self, sheen: f64) -> Principled {
+        Principled { sheen: sheen.max(0.), ..self }
+    }
+
+    pub fn with_transmission(self, transmission: f64) -> Principled {
+        Principled { transmission: transmission.clamp(0., 1.), ..self }
+    }
+
+    pub fn with_refractive_index(self, refractive_index: f64) -> Principled {
+        Principled { refractive_index, ..self }
+    }
+
+    fn alpha(&self) -> f64 {
+        (self.roughness * self.roughness).max(MIN_ALPHA)
+    }
+
+    fn clearcoat_alpha(&self) -> f64 {
+        (self.clearcoat_roughness * self.clearcoat_roughness).max(MIN_ALPHA)
+    }
+
+    /// Weight of the part of the surface that is an opaque dielectric.
+    fn diffuse_weight(&self) -> f64 {
+        (1. - self.metallic) * (1. - self.transmission)
+    }
+
+    /// Weight of the part of the surface that is glass-like.
+    fn transmission_weight(&self) -> f64 {
+        (1. - self.metallic) * self.transmission
+    }
+
+    /// Reflectance at normal incidence of the specular layer.
+    fn specular_f0(&self) -> Color {
+        let dielectric = 0.08 * self.specular;
+        let white = Color::new(dielectric, dielectric, dielectric);
+        white * (1. - self.metallic) + self.base_color * self.metallic
+    }
+
+    fn lobe_probabilities(&self, cos_out: f64) -> LobeProbabilities {
+        let diffuse = self.diffuse_weight() * self.base_color.luminance();
+        let specular = (1. - self.transmission_weight())
+            * schlick(self.specular_f0(), cos_out).luminance();
+        let clearcoat = 0.25 * self.clearcoat * schlick_scalar(0.04, cos_out);
+        let transmission = self.transmission_weight();
+        let total = diffuse + specular + clearcoat + transmission;
+        if total <= 0. {
+            return LobeProbabilities { diffuse: 0., specular: 0., clearcoat: 0., transmission: 0. };
+        }
+        LobeProbabilities {
+            diffuse: diffuse / total,
+            specular: specular / total,
+            clearcoat: clearcoat / total,
+            transmission: transmission / total,
+        }
+    }
+
+    /// The reflective part of the BSDF times the cosine term, for unit
+    /// vectors `out` (towards the viewer) and `in_` (towards the light).
+    fn reflectance(
+        &self,
+        out: &Tuple<Vector>,
+        in_: &Tuple<Vector>,
+        normal: &Tuple<Vector>,
+    ) -> Color {
+        let cos_out = normal.dot(out);
+        let cos_in = normal.dot(in_);
+        if cos_out <= 0. || cos_in <= 0. {
+            return Color::new(0., 0., 0.);
+        }
+        let half = (out + in_).unit();
+        let cos_half = normal.dot(&half);
+        let cos_diff = in_.dot(&half);
+
+        // Disney diffuse, with grazing retro-reflection and sheen.
+        let fd90 = 0.5 + 2. * self.roughness * cos_diff * cos_diff;
+        let retro = (1. + (fd90 - 1.) * (1. - cos_in).powi(5))
+            * (1. + (fd90 - 1.) * (1. - cos_out).powi(5));
+        let sheen = self.sheen * (1. - cos_diff).powi(5);
+        let diffuse = (self.base_color * (retro / PI) + Color::new(sheen, sheen, sheen))
+            * self.diffuse_weight();
+
+        let alpha = self.alpha();
+        let specular = schlick(self.specular_f0(), cos_diff)
+            * (ggx_distribution(cos_half, alpha)
+               * smith_masking(cos_out, cos_in, alpha)
+               / (4. * cos_out * cos_in)
+               * (1. - self.transmission_weight()));
+
+        let clearcoat = 0.25 * self.clearcoat
+            * schlick_scalar(0.04, cos_diff)
+            * ggx_distribution(cos_half, self.clearcoat_alpha())
+            * smith_masking(cos_out, cos_in, 0.25)
+            / (4. * cos_out * cos_in);
+
+        (diffuse + specular + Color::new(clearcoat, clearcoat, clearcoat)) * cos_in
+    }
+
+    /// The pdf with which the reflective lobes sample `in_`.
+    fn reflection_pdf(
+        &self,
+        out: &Tuple<Vector>,
+        in_: &Tuple<Vector>,
+        normal: &Tuple<Vector>,
+        lobes: &LobeProbabilities,
+    ) -> f64 {
+        let cos_in = normal.dot(in_);
+        if cos_in <= 0. {
+            return 0.;
+        }
+        let half = (out + in_).unit();
+        let cos_half = normal.dot(&half);
+        let out_dot_half = out.dot(&half).max(1e-8);
+        lobes.diffuse * cos_in / PI
+            + lobes.specular * ggx_distribution(cos_half, self.alpha()) * cos_half
+                / (4. * out_dot_half)
+            + lobes.clearcoat * ggx_distribution(cos_half, self.clearcoat_alpha()) * cos_half
+                / (4. * out_dot_half)
+    }
+
+    /// Scatters through the glass-like lobe: a rough dielectric interface.
+    fn scatter_transmission(
+        &self,
+        incoming: &Tuple<Vector>,
+        normal: &Tuple<Vector>,
+        entering: bool,
+    ) -> (Color, Tuple<Vector>) {
+        let nfrom_over_nto = match entering {
+            true => 1. / self.refractive_index,
+            false => self.refractive_index,
+        };
+        let out = -incoming;
+        let mut microfacet = sample_ggx_normal(normal, self.alpha());
+        if out.dot(&microfacet) <= 0. {
+            microfacet = *normal;
+        }
+
+        let cos_theta = out.dot(&microfacet).min(1.);
+        let sin_theta = (1. - cos_theta * cos_theta).sqrt();
+        let cannot_refract = nfrom_over_nto * sin_theta > 1.;
+        let mut rng = crate::rng::thread_rng();
+        let should_reflect = Dielectric::schlick_reflectance(
+            cos_theta, nfrom_over_nto) > rng.gen_range(0.0..1.0);
+
+        if cannot_refract || should_reflect {
+            let mut direction = reflect(incoming, &microfacet);
+            if direction.dot(normal) <= 0. {
+                direction = reflect(incoming, normal);
+            }
+            (Color::new(1., 1., 1.), direction)
+        } else {
+            let mut direction = refract(incoming, microfacet, nfrom_over_nto);
+            if direction.dot(normal) >= 0. {
+                direction = refract(incoming, *normal, nfrom_over_nto);
+            }
+            // Tint once, on the way in.
+            let tint = if entering { self.base_color } else { Color::new(1., 1., 1.) };
+            (tint, direction)
+        }
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, ray: &Ray, hit: &Intersection) -> Option<Scattered> {
+        let position = ray.position(hit.t);
+        let normal = hit.object.normal_at(position, ray.time);
+        let incoming = ray.direction.unit();
+        let out = -incoming;
+        let entering = normal.dot(&out) > 0.;
+        let normal = if entering {normal} else {-normal};
+
+        // From inside a transmissive object only the glass interface is seen.
+        if !entering && self.transmission_weight() > 0. {
+            let (attenuation, direction) = self.scatter_transmission(&incoming, &normal, false);
+            return Some(Scattered::new(attenuation, ray.spawn(position, direction)));
+        }
+
+        let cos_out = normal.dot(&out);
+        let lobes = self.lobe_probabilities(cos_out);
+        let mut rng = crate::rng::thread_rng();
+        let u: f64 = rng.gen_range(0.0..1.0);
+
+        if u < lobes.transmission {
+            let (tint, direction) = self.scatter_transmission(&incoming, &normal, entering);
+            let attenuation = tint * (self.transmission_weight() / lobes.transmission);
+            return Some(Scattered::new(attenuation, ray.spawn(position, direction)));
+        }
+
+        let u = u - lobes.transmission;
+        let direction = if u < lobes.diffuse {
+            from_local(&random_cosine_direction(), &normal)
+        } else if u < lobes.diffuse + lobes.specular {
+            reflect(&incoming, &sample_ggx_normal(&normal, self.alpha()))
+        } else {
+            reflect(&incoming, &sample_ggx_normal(&normal, self.clearcoat_alpha()))
+        };
+
+        let pdf = self.reflection_pdf(&out, &direction, &normal, &lobes);
+        if pdf <= 0. {
+            // Sampled below the surface: the path is absorbed.
+            return None;
+        }
+        Some(Scattered::new(
+            self.reflectance(&out, &direction, &normal) * (1. / pdf),
+            ray.spawn(position, direction),
+        ).with_pdf(pdf))
+    }
+
+    fn evaluate(
+        &self,
+        ray: &Ray,
+        hit: &Intersection,
+        direction: &Tuple<Vector>,
+    ) -> Option<(Color, f64)> {
+        let normal = hit.object.normal_at(ray.position(hit.t), ray.time);
+        let out = -ray.direction.unit();
+        let entering = normal.dot(&out) > 0.;
+        if !entering && self.transmission_weight() > 0. {
+            return None;
+        }
+        let normal = if entering {normal} else {-normal};
+        let direction = direction.unit();
+        let lobes = self.lobe_probabilities(normal.dot(&out));
+        Some((
+            self.reflectance(&out, &direction, &normal),
+            self.reflection_pdf(&out, &direction, &normal, &lobes),
+        ))
+    }
+}
+
+
+/// Schlick's approximation to the Fresnel reflectance of a colored surface.
+fn schlick(f0: Color, cosine: f64) -> Color {
+    let weight = (1. - cosine.clamp(0., 1.)).powi(5);
+    f0 * (1. - weight) + Color::new(weight, weight, weight)
+}
+
+fn schlick_scalar(f0: f64, cosine: f64) -> f64 {
+    f0 + (1. - f0) * (1. - cosine.clamp(0., 1.)).powi(5)
+}
+
+/// The GGX (Trowbridge-Reitz) normal distribution function.
+fn ggx_distribution(cos_half: f64, alpha: f64) -> f64 {
+    if cos_half <= 0. {
+        return 0.;
+    }
+    let alpha2 = alpha * alpha;
+    let d = cos_half * cos_half * (alpha2 - 1.) + 1.;
+    alpha2 / (PI * d * d)
+}
+
+/// Separable Smith masking-shadowing term for GGX.
+fn smith_masking(cos_out: f64, cos_in: f64, alpha: f64) -> f64 {
+    let g1 = |cosine: f64| {
+        let alpha2 = alpha * alpha;
+        2. * cosine / (cosine + (alpha2 + (1. - alpha2) * cosine * cosine).sqrt())
+    };
+    g1(cos_out) * g1(cos_in)
+}
+
+/// Samples a microfacet normal around `normal` with pdf D(h) cos(theta_h).
+fn sample_ggx_normal(normal: &Tuple<Vector>, alpha: f64) -> Tuple<Vector> {
+    let mut rng = crate::rng::thread_rng();
+    let u: f64 = rng.gen_range(0.0..1.0);
+    let phi = rng.gen_range(0.0..2. * PI);
+    let tan2_theta = alpha * alpha * u / (1. - u);
+    let cos_theta = 1. / (1. + tan2_theta).sqrt();
+    let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+    let local = Tuple::vector(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+    from_local(&local, normal)
+}
+
+/// Samples a direction around the z axis with pdf cos(theta) / pi.
+fn random_cosine_direction() -> Tuple<Vector> {
+    let mut rng = crate::rng::thread_rng();
+    let u: f64 = rng.gen_range(0.0..1.0);
+    let phi = rng.gen_range(0.0..2. * PI);
+    let r = u.sqrt();
+    Tuple::vector(r * phi.cos(), r * phi.sin(), (1. - u).sqrt())
+}
+
+
+#[cfg(test)]
+mod principled_tests {
+    use super::*;
+    use crate::object::Object;
+
+    fn mean_attenuation(material: &Principled, ray: &Ray, samples: usize) -> Color {
+        let sphere = Object::new_sphere();
+        let hit = Intersection::new(4., &sphere);
+        let mut total = Color::new(0., 0., 0.);
+        for _ in 0..samples {
+            if let Some(scattered) = material.scatter(ray, &hit) {
+                total = total + scattered.attenuation;
+            }
+        }
+        total * (1. / samples as f64)
+    }
+
+    #[test]
+    fn smooth_metal_reflects_like_a_mirror() {
+        let material = Principled::new(Color::new(1., 1., 1.))
+            .with_metallic(1.)
+            .with_roughness(0.);
+        let sphere = Object::new_sphere();
+        let ray = Ray::from_coords(0., 0., -5., 0., 0., 1.);
+        let hit = Intersection::new(4., &sphere);
+        let scattered = material.scatter(&ray, &hit).unwrap();
+        let direction = scattered.ray.direction.unit();
+        assert!((direction.z + 1.).abs() < 1e-2);
+    }
+
+    #[test]
+    fn rough_metal_does_not_create_energy() {
+        let material = Principled::new(Color::new(1., 1., 1.))
+            .with_metallic(1.)
+            .with_roughness(0.4);
+        let ray = Ray::from_coords(0., 0., -5., 0., 0., 1.);
+        let mean = mean_attenuation(&material, &ray, 20_000);
+        assert!(mean.red <= 1.02, "{:?}", mean);
+        assert!(mean.red > 0.8, "{:?}", mean);
+    }
+
+    #[test]
+    fn opaque_materials_scatter_above_the_surface() {
+        let material = Principled::new(Color::new(0.5, 0.2, 0.8))
+            .with_roughness(0.7)
+            .with_clearcoat(1., 0.1)
+            .with_sheen(0.5);
+        let sphere = Object::new_sphere();
+        let ray = Ray::from_coords(0., 0., -5., 0., 0., 1.);
+        let hit = Intersection::new(4., &sphere);
+        for _ in 0..1000 {
+            if let Some(scattered) = material.scatter(&ray, &hit) {
+                assert!(scattered.ray.direction.z < 0.);
+                assert!(scattered.attenuation.red >= 0.);
+            }
+        }
+    }
+
+    #[test]
+    fn clear_glass_transmits_most_light() {
+        let material = Principled::new(Color::new(1., 1., 1.))
+            .with_roughness(0.)
+            .with_transmission(1.);
+        let sphere = Object::new_sphere();
+        let ray = Ray::from_coords(0., 0., -5., 0., 0., 1.);
+        let hit = Intersection::new(4., &sphere);
+        let transmitted = (0..1000)
+            .filter_map(|_| material.scatter(&ray, &hit))
+            .filter(|s| s.ray.direction.z > 0.)
+            .count();
+        assert!(transmitted > 900);
+    }
+}
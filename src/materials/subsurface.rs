@@ -0,0 +1,181 @@
+use rand::prelude::*;
+
+use crate::{
+    ray::Ray,
+    intersection::{self, Intersection, intersect},
+    colors::Color,
+    normal::{reflect, refract},
+    media::{PhaseFunction, sample_free_flight},
+};
+use super::{Material, Scattered, Dielectric};
+
+/// How many times a path may scatter inside the object before we give up on it.
+const MAX_STEPS: usize = 256;
+
+/// How far along a ray we look for the object's boundary, to avoid
+/// re-hitting the point we started from.
+const MIN_T: f64 = 1e-4;
+
+
+/// A translucent material for skin, wax, marble and the like.
+///
+/// Light refracts through a dielectric boundary into the object and then
+/// performs a random walk through its volume, scattering off a
+/// homogeneous medium until it leaves through the boundary again.
+/// The object's shape is the boundary, so it should be closed.
+#[derive(Debug)]
+pub struct Subsurface {
+    /// Single-scattering albedo of the medium inside.
+    single_scattering_albedo: Color,
+    mean_free_path: f64,
+    refractive_index: f64,
+    phase: PhaseFunction,
+}
+
+impl Subsurface {
+    /// `albedo` is the color the surface should appear, and `mean_free_path`
+    /// the average distance light travels between scattering events inside.
+    pub fn new(albedo: Color, mean_free_path: f64) -> Subsurface {
+        Subsurface {
+            single_scattering_albedo: Color::new(
+                single_scattering_albedo(albedo.red),
+                single_scattering_albedo(albedo.green),
+                single_scattering_albedo(albedo.blue),
+            ),
+            mean_free_path,
+            refractive_index: 1.4,
+            phase: PhaseFunction::Isotropic,
+        }
+    }
+
+    pub fn with_refractive_index(self, refractive_index: f64) -> Subsurface {
+        Subsurface { refractive_index, ..self }
+    }
+
+    /// Use a Henyey-Greenstein phase function inside, e.g. forward
+    /// scattering (`asymmetry` > 0) for skin.
+    pub fn with_anisotropy(self, asymmetry: f64) -> Subsurface {
+        Subsurface { phase: PhaseFunction::HenyeyGreenstein(asymmetry), ..self }
+    }
+}
+
+/// Inverts the multiple-scattering albedo of a semi-infinite slab to find the
+/// single-scattering albedo that produces it (Chiang et al. 2016).
+fn single_scattering_albedo(albedo: f64) -> f64 {
+    let a = albedo.clamp(0., 1.);
+    let s = 4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt();
+    (1. - s * s).clamp(0., 1.)
+}
+
+impl Material for Subsurface {
+    fn scatter(&self, ray: &Ray, hit: &Intersection) -> Option<Scattered> {
+        let mut rng = crate::rng::thread_rng();
+        let position = ray.position(hit.t);
+        let normal = hit.object.normal_at(position, ray.time);
+        let incoming = ray.direction.unit();
+        let entering = normal.dot(&incoming) < 0.;
+        let normal = if entering {normal} else {-normal};
+
+        // The boundary: reflect off it, or refract into the object.
+        let ratio = if entering {1. / self.refractive_index} else {self.refractive_index};
+        let cos_theta = -incoming.dot(&normal).min(1.);
+        let sin_theta = (1. - cos_theta * cos_theta).sqrt();
+        if ratio * sin_theta > 1.
+            || Dielectric::schlick_reflectance(cos_theta, ratio) > rng.gen_range(0.0..1.0) {
+            return Some(Scattered::new(
+                Color::new(1., 1., 1.),
+                ray.spawn(position, reflect(&incoming, &normal)),
+            ));
+        }
+        if !entering {
+            // We were already inside (e.g. the camera is); just leave.
+            return Some(Scattered::new(
+                Color::new(1., 1., 1.),
+                ray.spawn(position, refract(&incoming, normal, ratio)),
+            ));
+        }
+
+        // Random walk inside the object.
+        let mut throughput = Color::new(1., 1., 1.);
+        let mut walk = ray.spawn(position, refract(&incoming, normal, ratio));
+        for _ in 0..MAX_STEPS {
+            let boundary = intersect(&walk, hit.object);
+            let Some(exit) = intersection::hit(&boundary, MIN_T) else {
+                // Numerically escaped the boundary; treat the path as lost.
+                return None
+            };
+            let distance = sample_free_flight(1. / self.mean_free_path);
+            if distance < exit.t {
+                throughput = throughput * self.single_scattering_albedo;
+                let scatter_point = walk.position(distance);
+                walk = walk.spawn(scatter_point, self.phase.sample(&walk.direction));
+                continue;
+            }
+
+            // Reached the boundary from inside.
+            let exit_point = walk.position(exit.t);
+            let outward = hit.object.normal_at(exit_point, ray.time);
+            let cos_theta = walk.direction.dot(&outward).min(1.);
+            let sin_theta = (1. - cos_theta * cos_theta).sqrt();
+            let ratio = self.refractive_index;
+            if ratio * sin_theta > 1.
+                || Dielectric::schlick_reflectance(cos_theta, ratio) > rng.gen_range(0.0..1.0) {
+                walk = walk.spawn(exit_point, reflect(&walk.direction, &outward));
+                continue;
+            }
+            return Some(Scattered::new(
+                throughput,
+                ray.spawn(exit_point, refract(&walk.direction, -outward, ratio)),
+            ));
+        }
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod subsurface_tests {
+    use super::*;
+    use crate::object::Object;
+    use crate::eq;
+
+    #[test]
+    fn albedo_inversion_keeps_extremes() {
+        assert!(eq(single_scattering_albedo(0.), 0.));
+        assert!((single_scattering_albedo(1.) - 1.).abs() < 1e-3);
+        assert!(single_scattering_albedo(0.5) > 0.5);
+    }
+
+    #[test]
+    fn light_leaves_through_the_surface() {
+        let material = Subsurface::new(Color::new(0.9, 0.6, 0.4), 0.2);
+        let sphere = Object::new_sphere();
+        let ray = Ray::from_coords(0., 0., -5., 0., 0., 1.);
+        let hit = Intersection::new(4., &sphere);
+        for _ in 0..200 {
+            if let Some(scattered) = material.scatter(&ray, &hit) {
+                let origin = scattered.ray.origin;
+                let outward = origin - crate::tuples::Tuple::point(0., 0., 0.);
+                assert!((outward.magnitude() - 1.).abs() < 1e-6);
+                assert!(scattered.ray.direction.dot(&outward) > 0.);
+            }
+        }
+    }
+
+    #[test]
+    fn darker_albedo_absorbs_more() {
+        let sphere = Object::new_sphere();
+        let ray = Ray::from_coords(0., 0., -5., 0., 0., 1.);
+        let hit = Intersection::new(4., &sphere);
+        let mean = |material: Subsurface| {
+            (0..2000)
+                .filter_map(|_| material.scatter(&ray, &hit))
+                .map(|s| s.attenuation.red)
+                .sum::<f64>() / 2000.
+        };
+        let bright = mean(Subsurface::new(Color::new(0.95, 0.95, 0.95), 0.1));
+        let dark = mean(Subsurface::new(Color::new(0.2, 0.2, 0.2), 0.1));
+        assert!(bright > dark);
+        assert!(bright <= 1.);
+    }
+}
@@ -14,6 +14,7 @@ pub struct Matrix {
 impl std::ops::Index<(usize, usize)> for Matrix {
     type Output = f64;
 
+    #[allow(clippy::match_single_binding)]
     fn index(&self, _index: (usize, usize)) -> &f64 {
         match _index {
             (i, j) => &self.contents[i * self.width + j],
@@ -23,6 +24,7 @@ impl std::ops::Index<(usize, usize)> for Matrix {
 
 
 impl std::ops::IndexMut<(usize, usize)> for Matrix {
+    #[allow(clippy::match_single_binding)]
     fn index_mut(&mut self, _index: (usize, usize)) -> &mut f64 {
         match _index {
             (i, j) => &mut self.contents[i * self.width + j],
@@ -129,6 +131,7 @@ impl Matrix {
         }
     }
 
+    #[allow(clippy::ptr_arg)]
     pub fn from_rows(rows: &Vec<Vec<f64>>) -> Result<Matrix> {
         let height = rows.len();
         let widths: Vec<usize> = rows.iter().map(|row| row.len()).collect();
@@ -145,6 +148,7 @@ impl Matrix {
         Ok(m)
     }
 
+    #[allow(clippy::ptr_arg)]
     pub fn from_cols(cols: &Vec<Vec<f64>>) -> Result<Matrix> {
         let width = cols.len();
         let heights: Vec<usize> = cols.iter().map(|col| col.len()).collect();
@@ -291,6 +295,7 @@ pub fn det(m: &Matrix) -> Result<f64> {
 }
 
 
+#[allow(clippy::manual_is_multiple_of)]
 fn cofactor(m: &Matrix, row: usize, col: usize) -> Result<f64> {
     let submatrix = m.submatrix(row, col)?;
     let sign = if (row + col) % 2 == 0 { 1. } else { -1. };
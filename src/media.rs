@@ -0,0 +1,337 @@
+use std::f64::consts::PI;
+use rand::Rng;
+
+use crate::{
+    ray::Ray,
+    intersection::Intersection,
+    object::Object,
+    tuples::{Tuple, Point, Vector},
+    normal::from_local,
+    materials::Volume,
+};
+
+pub mod grid;
+pub mod noise;
+
+use grid::DensityGrid;
+use noise::NoiseDensity;
+
+
+/// Describes how light scattering inside a medium is distributed
+/// around its direction of travel.
+#[derive(Debug, Clone, Copy)]
+pub enum PhaseFunction {
+    /// Scatters equally in all directions.
+    Isotropic,
+    /// The Henyey-Greenstein lobe with asymmetry `g` in (-1, 1):
+    /// positive values scatter forwards, negative values backwards.
+    HenyeyGreenstein(f64),
+}
+
+impl PhaseFunction {
+    /// The phase function's value for an angle with cosine `cos_theta`
+    /// between the incoming direction of travel and the scattered direction.
+    pub fn value(&self, cos_theta: f64) -> f64 {
+        match self {
+            PhaseFunction::Isotropic => 1. / (4. * PI),
+            PhaseFunction::HenyeyGreenstein(g) => {
+                let denominator = 1. + g * g - 2. * g * cos_theta;
+                (1. - g * g) / (4. * PI * denominator * denominator.sqrt())
+            }
+        }
+    }
+
+    /// Samples a scattered direction for light travelling along `incoming`,
+    /// with pdf equal to `value`.
+    pub fn sample(&self, incoming: &Tuple<Vector>) -> Tuple<Vector> {
+        let mut rng = crate::rng::thread_rng();
+        let u: f64 = rng.gen_range(0.0..1.0);
+        let phi = rng.gen_range(0.0..2. * PI);
+        let cos_theta = match self {
+            PhaseFunction::HenyeyGreenstein(g) if g.abs() > 1e-3 => {
+                let s = (1. - g * g) / (1. - g + 2. * g * u);
+                ((1. + g * g - s * s) / (2. * g)).clamp(-1., 1.)
+            }
+            _ => 1. - 2. * u,
+        };
+        let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+        let local = Tuple::vector(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+        from_local(&local, &incoming.unit())
+    }
+}
+
+
+/// Samples how far light travels through a medium with the given
+/// extinction coefficient before colliding with a particle.
+pub fn sample_free_flight(density: f64) -> f64 {
+    let u: f64 = crate::rng::thread_rng().gen_range(0.0..1.0);
+    -(1. - u).ln() / density
+}
+
+
+/// How the extinction coefficient of a medium varies over object space.
+#[derive(Debug, Clone)]
+pub enum Density {
+    /// The same density everywhere.
+    Constant(f64),
+    /// A voxel grid spanning [-1, 1]³, scaled by the given factor.
+    Grid(DensityGrid, f64),
+    /// Procedural fractal noise.
+    Noise(NoiseDensity),
+}
+
+impl Density {
+    /// The density at an object-space point, per unit of world space distance.
+    pub fn at(&self, point: &Tuple<Point>) -> f64 {
+        match self {
+            Density::Constant(density) => *density,
+            Density::Grid(grid, scale) => scale * grid.at(point),
+            Density::Noise(noise) => noise.at(point),
+        }
+    }
+
+    /// An upper bound on the density anywhere.
+    pub fn majorant(&self) -> f64 {
+        match self {
+            Density::Constant(density) => *density,
+            Density::Grid(grid, scale) => scale * grid.max(),
+            Density::Noise(noise) => noise.scale,
+        }
+    }
+}
+
+
+/// A participating medium filling the inside of an object,
+/// such as smoke or a glowing volume.
+///
+/// The object's shape only bounds the medium; it is assumed to be convex.
+#[derive(Debug, Clone)]
+pub struct Medium {
+    /// Extinction coefficient, per unit of (world space) distance.
+    pub density: Density,
+    /// The material describing how light scatters off the medium's particles.
+    pub volume: Volume,
+}
+
+impl Medium {
+    pub fn new(density: Density, volume: Volume) -> Medium {
+        Medium { density, volume }
+    }
+
+    pub fn homogeneous(density: f64, volume: Volume) -> Medium {
+        Medium::new(Density::Constant(density), volume)
+    }
+
+    /// Turns the intersections of `ray` with the medium's boundary
+    /// into at most one intersection at which the ray collides with
+    /// the medium.
+    ///
+    /// `local_ray` is `ray` in object space, where the density is defined.
+    /// Heterogeneous media are sampled by delta tracking against the
+    /// density's majorant.
+    pub fn collide<'a>(
+        &self,
+        ray: &Ray,
+        local_ray: &Ray,
+        obj: &'a Object,
+        boundary: Vec<Intersection<'a>>,
+    ) -> Vec<Intersection<'a>> {
+        let Some((enter, exit)) = segment_inside(&boundary) else {
+            return vec![]
+        };
+        let speed = ray.direction.magnitude();
+        let majorant = self.density.majorant();
+        let mut rng = crate::rng::thread_rng();
+        let mut t = enter;
+        loop {
+            t += sample_free_flight(majorant) / speed;
+            if t >= exit {
+                return vec![]
+            }
+            if let Density::Constant(_) = self.density {
+                return vec![Intersection::new(t, obj)]
+            }
+            let density = self.density.at(&local_ray.position(t));
+            // Real collision with probability density / majorant;
+            // otherwise a null collision, and we keep going.
+            if rng.gen_range(0.0..1.0) * majorant < density {
+                return vec![Intersection::new(t, obj)]
+            }
+        }
+    }
+
+    /// Estimates the fraction of light that makes it through the medium
+    /// along `ray` between parameters `from` and `to`.
+    ///
+    /// Heterogeneous media are estimated by ratio tracking, which is
+    /// unbiased and (unlike a binary hit test) never noisier than needed.
+    pub fn transmittance(&self, ray: &Ray, local_ray: &Ray, from: f64, to: f64) -> f64 {
+        let speed = ray.direction.magnitude();
+        if let Density::Constant(density) = self.density {
+            return (-density * (to - from).max(0.) * speed).exp();
+        }
+        let majorant = self.density.majorant();
+        let mut transmittance = 1.;
+        let mut t = from;
+        loop {
+            t += sample_free_flight(majorant) / speed;
+            if t >= to {
+                return transmittance
+            }
+            transmittance *= 1. - self.density.at(&local_ray.position(t)) / majorant;
+        }
+    }
+}
+
+
+/// The parameter range `(enter, exit)` along a ray that lies inside a
+/// convex boundary with the given intersections, clipped to start at 0.
+pub fn segment_inside(boundary: &[Intersection]) -> Option<(f64, f64)> {
+    let first = boundary.iter().map(|x| x.t).fold(f64::INFINITY, f64::min);
+    let last = boundary.iter().map(|x| x.t).fold(f64::NEG_INFINITY, f64::max);
+    let enter = match boundary.len() {
+        0 => return None,
+        // A single intersection means the ray starts inside.
+        1 => 0.,
+        _ => first.max(0.),
+    };
+    match last > enter {
+        true => Some((enter, last)),
+        false => None,
+    }
+}
+
+
+/// Homogeneous fog filling the whole scene.
+///
+/// The fog fills the space between the scene's surfaces, and rays that
+/// leave the scene go through `far` of it before reaching the background,
+/// so that the background fades into the fog too.
+#[derive(Debug, Clone, Copy)]
+pub struct Fog {
+    /// Extinction coefficient, per unit of distance.
+    pub density: f64,
+    pub volume: Volume,
+    /// How far the fog reaches beyond the surfaces, 100 by default.
+    pub far: f64,
+}
+
+impl Fog {
+    pub fn new(density: f64, volume: Volume) -> Fog {
+        Fog { density, volume, far: 100. }
+    }
+
+    pub fn with_far(self, far: f64) -> Fog {
+        Fog { far, ..self }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors::Color;
+    use crate::shapes::Shape;
+    use crate::intersection::intersect;
+
+    #[test]
+    fn henyey_greenstein_mean_cosine_is_asymmetry() {
+        let incoming = Tuple::vector(0., 1., 0.);
+        for g in [-0.5, 0., 0.7] {
+            let phase = PhaseFunction::HenyeyGreenstein(g);
+            let samples = 20_000;
+            let mean: f64 = (0..samples)
+                .map(|_| phase.sample(&incoming).dot(&incoming))
+                .sum::<f64>() / samples as f64;
+            assert!((mean - g).abs() < 0.03, "g={} mean={}", g, mean);
+        }
+    }
+
+    #[test]
+    fn henyey_greenstein_integrates_to_one() {
+        let phase = PhaseFunction::HenyeyGreenstein(0.6);
+        let steps = 10_000;
+        let integral: f64 = (0..steps)
+            .map(|i| {
+                let cos_theta = -1. + 2. * (i as f64 + 0.5) / steps as f64;
+                phase.value(cos_theta) * 2. * PI * 2. / steps as f64
+            })
+            .sum();
+        assert!((integral - 1.).abs() < 1e-3);
+    }
+
+    #[test]
+    fn collisions_follow_beer_lambert() {
+        let density = 0.4;
+        let medium = Medium::homogeneous(density, Volume::isotropic(Color::new(1., 1., 1.)));
+        let cube = Object::new(Shape::Cube).with_medium(medium);
+        // Crosses 2 units of medium.
+        let ray = Ray::from_coords(-5., 0., 0., 1., 0., 0.);
+        let trials = 20_000;
+        let collisions = (0..trials)
+            .filter(|_| !intersect(&ray, &cube).is_empty())
+            .count();
+        let expected = 1. - (-2. * density).exp();
+        assert!((collisions as f64 / trials as f64 - expected).abs() < 0.02);
+    }
+
+    #[test]
+    fn collisions_stay_inside_the_boundary() {
+        let medium = Medium::homogeneous(2., Volume::isotropic(Color::new(1., 1., 1.)));
+        let sphere = Object::new_sphere().with_medium(medium);
+        let ray = Ray::from_coords(0., 0., -5., 0., 0., 1.);
+        for _ in 0..1000 {
+            for x in intersect(&ray, &sphere) {
+                assert!(x.t > 4. && x.t < 6.);
+            }
+        }
+    }
+
+    #[test]
+    fn segment_inside_starts_at_zero_from_inside() {
+        let sphere = Object::new_sphere();
+        let xs = vec![Intersection::new(-1., &sphere), Intersection::new(1., &sphere)];
+        assert_eq!(segment_inside(&xs), Some((0., 1.)));
+        let xs = vec![Intersection::new(-3., &sphere), Intersection::new(-1., &sphere)];
+        assert_eq!(segment_inside(&xs), None);
+    }
+
+    /// A grid whose density rises linearly from 0 at x = -1 to 2 at x = 1,
+    /// so a ray along the x axis through the cube has optical depth 2.
+    fn ramp() -> Medium {
+        let grid = DensityGrid::new([2, 1, 1], vec![0.5, 1.5]).unwrap();
+        Medium::new(Density::Grid(grid, 1.), Volume::isotropic(Color::new(1., 1., 1.)))
+    }
+
+    #[test]
+    fn delta_tracking_matches_optical_depth() {
+        let cube = Object::new(Shape::Cube).with_medium(ramp());
+        let ray = Ray::from_coords(-5., 0., 0., 1., 0., 0.);
+        let trials = 20_000;
+        let collisions = (0..trials)
+            .filter(|_| !intersect(&ray, &cube).is_empty())
+            .count();
+        // The grid clamps beyond the voxel centres, so the optical depth is
+        // 0.5 * 0.5 + (0.5 + 1.5) / 2 * 1 + 1.5 * 0.5 = 2.
+        let expected = 1. - (-2.0_f64).exp();
+        assert!((collisions as f64 / trials as f64 - expected).abs() < 0.02);
+    }
+
+    #[test]
+    fn ratio_tracking_matches_optical_depth() {
+        let medium = ramp();
+        let ray = Ray::from_coords(-1., 0., 0., 1., 0., 0.);
+        let trials = 20_000;
+        let mean = (0..trials)
+            .map(|_| medium.transmittance(&ray, &ray, 0., 2.))
+            .sum::<f64>() / trials as f64;
+        assert!((mean - (-2.0_f64).exp()).abs() < 0.01);
+    }
+
+    #[test]
+    fn homogeneous_transmittance_is_exponential() {
+        let medium = Medium::homogeneous(0.5, Volume::isotropic(Color::new(1., 1., 1.)));
+        let ray = Ray::from_coords(0., 0., 0., 0., 0., 2.);
+        assert!((medium.transmittance(&ray, &ray, 0., 1.) - (-1.0_f64).exp()).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,152 @@
+use crate::Result;
+use crate::tuples::{Tuple, Point};
+
+
+/// A dense grid of density values spanning the object-space cube
+/// [-1, 1]³, with values at voxel centres and trilinear filtering
+/// in between. Outside the cube the density is zero.
+#[derive(Debug, Clone)]
+pub struct DensityGrid {
+    resolution: [usize; 3],
+    values: Vec<f64>,
+    max: f64,
+}
+
+impl DensityGrid {
+    /// Builds a grid from `values` ordered with x varying fastest, then y, then z.
+    pub fn new(resolution: [usize; 3], values: Vec<f64>) -> Result<DensityGrid> {
+        let [nx, ny, nz] = resolution;
+        if nx == 0 || ny == 0 || nz == 0 {
+            return Err("Density grid must have at least one voxel along each axis".into());
+        }
+        if values.len() != nx * ny * nz {
+            return Err(format!(
+                "Density grid of size {}x{}x{} needs {} values, got {}",
+                nx, ny, nz, nx * ny * nz, values.len()
+            ).into());
+        }
+        if values.iter().any(|v| !v.is_finite() || *v < 0.) {
+            return Err("Density values must be finite and non-negative".into());
+        }
+        let max = values.iter().cloned().fold(0., f64::max);
+        Ok(DensityGrid { resolution, values, max })
+    }
+
+    /// Parses the text format: the resolution `nx ny nz`, followed by
+    /// `nx * ny * nz` whitespace-separated values (x fastest).
+    /// Lines starting with `#` are comments.
+    pub fn from_text(text: &str) -> Result<DensityGrid> {
+        let mut tokens = text
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .flat_map(|line| line.split_whitespace());
+        let mut resolution = [0; 3];
+        for n in resolution.iter_mut() {
+            *n = tokens.next().ok_or("Density grid is missing its resolution")?.parse()?;
+        }
+        let values = tokens.map(|t| t.parse::<f64>()).collect::<std::result::Result<Vec<_>, _>>()?;
+        DensityGrid::new(resolution, values)
+    }
+
+    /// Parses raw little-endian 32-bit floats (x fastest) of the given resolution.
+    pub fn from_raw(bytes: &[u8], resolution: [usize; 3]) -> Result<DensityGrid> {
+        if !bytes.len().is_multiple_of(4) {
+            return Err("Raw density grid length must be a multiple of 4 bytes".into());
+        }
+        let values = bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64)
+            .collect();
+        DensityGrid::new(resolution, values)
+    }
+
+    /// Loads a grid from a text file.
+    pub fn load_text(path: &str) -> Result<DensityGrid> {
+        DensityGrid::from_text(&std::fs::read_to_string(path)?)
+    }
+
+    /// Loads a grid from a raw file of little-endian 32-bit floats.
+    pub fn load_raw(path: &str, resolution: [usize; 3]) -> Result<DensityGrid> {
+        DensityGrid::from_raw(&std::fs::read(path)?, resolution)
+    }
+
+    /// The largest value in the grid, which bounds every lookup.
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f64 {
+        let [nx, ny, _] = self.resolution;
+        self.values[x + nx * (y + ny * z)]
+    }
+
+    /// The trilinearly interpolated density at an object-space point.
+    pub fn at(&self, point: &Tuple<Point>) -> f64 {
+        let coords = [point.x, point.y, point.z];
+        if coords.iter().any(|c| c.abs() > 1.) {
+            return 0.;
+        }
+        // Continuous voxel coordinates, with voxel centres at integers.
+        let mut base = [0; 3];
+        let mut frac = [0.; 3];
+        for axis in 0..3 {
+            let n = self.resolution[axis];
+            let c = ((coords[axis] + 1.) / 2. * n as f64 - 0.5).clamp(0., (n - 1) as f64);
+            base[axis] = (c.floor() as usize).min(n.saturating_sub(2));
+            frac[axis] = if n == 1 { 0. } else { c - base[axis] as f64 };
+        }
+        let next = |axis: usize| (base[axis] + 1).min(self.resolution[axis] - 1);
+
+        let mut value = 0.;
+        for corner in 0..8 {
+            let pick = |axis: usize| corner >> axis & 1 == 1;
+            let mut weight = 1.;
+            let mut index = [0; 3];
+            for axis in 0..3 {
+                index[axis] = if pick(axis) { next(axis) } else { base[axis] };
+                weight *= if pick(axis) { frac[axis] } else { 1. - frac[axis] };
+            }
+            value += weight * self.voxel(index[0], index[1], index[2]);
+        }
+        value
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eq;
+
+    #[test]
+    fn parses_text_grids() {
+        let grid = DensityGrid::from_text("# a comment\n2 1 1\n0.5 1.5\n").unwrap();
+        assert_eq!(grid.resolution, [2, 1, 1]);
+        assert_eq!(grid.max(), 1.5);
+    }
+
+    #[test]
+    fn rejects_grids_of_the_wrong_size() {
+        assert!(DensityGrid::from_text("2 2 2\n1 2 3").is_err());
+        assert!(DensityGrid::from_text("1 1 1\n-1").is_err());
+    }
+
+    #[test]
+    fn parses_raw_grids() {
+        let bytes: Vec<u8> = [0.25_f32, 2.0].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let grid = DensityGrid::from_raw(&bytes, [1, 1, 2]).unwrap();
+        assert_eq!(grid.voxel(0, 0, 1), 2.0);
+    }
+
+    #[test]
+    fn lookups_interpolate_between_voxel_centres() {
+        let grid = DensityGrid::new([2, 1, 1], vec![0., 1.]).unwrap();
+        // Voxel centres are at x = -0.5 and x = 0.5.
+        assert!(eq(grid.at(&Tuple::point(-0.5, 0., 0.)), 0.));
+        assert!(eq(grid.at(&Tuple::point(0., 0., 0.)), 0.5));
+        assert!(eq(grid.at(&Tuple::point(0.25, 0.3, -0.9)), 0.75));
+        // Clamped beyond the outermost centres, and zero outside the cube.
+        assert!(eq(grid.at(&Tuple::point(0.9, 0., 0.)), 1.));
+        assert!(eq(grid.at(&Tuple::point(1.1, 0., 0.)), 0.));
+    }
+}
@@ -0,0 +1,95 @@
+use crate::tuples::{Tuple, Point};
+
+
+/// Procedural density from fractal value noise, for clouds and smoke
+/// without a simulated grid. Values lie in [0, `scale`].
+#[derive(Debug, Clone, Copy)]
+pub struct NoiseDensity {
+    /// The maximum density.
+    pub scale: f64,
+    /// Spatial frequency of the coarsest octave, in object space.
+    pub frequency: f64,
+    pub octaves: usize,
+    pub seed: u32,
+}
+
+impl NoiseDensity {
+    pub fn new(scale: f64, frequency: f64, octaves: usize) -> NoiseDensity {
+        NoiseDensity { scale, frequency, octaves: octaves.max(1), seed: 0 }
+    }
+
+    pub fn with_seed(self, seed: u32) -> NoiseDensity {
+        NoiseDensity { seed, ..self }
+    }
+
+    /// The density at an object-space point.
+    pub fn at(&self, point: &Tuple<Point>) -> f64 {
+        let mut total = 0.;
+        let mut amplitude = 1.;
+        let mut norm = 0.;
+        let mut frequency = self.frequency;
+        for octave in 0..self.octaves {
+            let seed = self.seed.wrapping_add(octave as u32);
+            total += amplitude * value_noise(
+                point.x * frequency, point.y * frequency, point.z * frequency, seed);
+            norm += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.;
+        }
+        self.scale * total / norm
+    }
+}
+
+/// Hashes a lattice point to a value in [0, 1].
+fn lattice(x: i64, y: i64, z: i64, seed: u32) -> f64 {
+    let mut h = (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+        ^ (z as u64).wrapping_mul(0x1656_67B1_9E37_79F9)
+        ^ (seed as u64).wrapping_mul(0x27D4_EB2F_1656_67C5);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xFF51_AFD7_ED55_8CCD);
+    h ^= h >> 33;
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Smoothly interpolated lattice noise in [0, 1].
+fn value_noise(x: f64, y: f64, z: f64, seed: u32) -> f64 {
+    let (x0, y0, z0) = (x.floor(), y.floor(), z.floor());
+    let smooth = |t: f64| t * t * (3. - 2. * t);
+    let (fx, fy, fz) = (smooth(x - x0), smooth(y - y0), smooth(z - z0));
+    let (ix, iy, iz) = (x0 as i64, y0 as i64, z0 as i64);
+    let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+    let plane = |dz: i64| {
+        let row = |dy: i64| lerp(
+            lattice(ix, iy + dy, iz + dz, seed),
+            lattice(ix + 1, iy + dy, iz + dz, seed),
+            fx,
+        );
+        lerp(row(0), row(1), fy)
+    };
+    lerp(plane(0), plane(1), fz)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noise_stays_within_scale() {
+        let noise = NoiseDensity::new(3., 2.5, 4);
+        for i in 0..1000 {
+            let t = i as f64 * 0.0137;
+            let d = noise.at(&Tuple::point(t.sin(), t * 0.3, t.cos() - 0.5));
+            assert!((0. ..=3.).contains(&d));
+        }
+    }
+
+    #[test]
+    fn noise_is_continuous() {
+        let noise = NoiseDensity::new(1., 3., 3);
+        let a = noise.at(&Tuple::point(0.2, 0.4, 0.6));
+        let b = noise.at(&Tuple::point(0.2001, 0.4, 0.6));
+        assert!((a - b).abs() < 1e-2);
+    }
+}
@@ -0,0 +1,274 @@
+use crate::matrix::Matrix;
+
+type Matrix3 = [[f64; 3]; 3];
+
+
+/// An affine transform split into translation, rotation and stretch,
+/// `T * R * S`, so it can be interpolated without shrinking or shearing
+/// in between.
+#[derive(Debug, Clone, Copy)]
+struct Decomposed {
+    translation: [f64; 3],
+    /// Unit quaternion, `[w, x, y, z]`.
+    rotation: [f64; 4],
+    stretch: Matrix3,
+}
+
+impl Decomposed {
+    fn new(m: &Matrix) -> Decomposed {
+        let translation = [m[(0, 3)], m[(1, 3)], m[(2, 3)]];
+        let mut linear = [[0.; 3]; 3];
+        for (i, row) in linear.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = m[(i, j)];
+            }
+        }
+        let (rotation, stretch) = match polar_decomposition(&linear) {
+            Some((rotation, stretch)) => (to_quaternion(&rotation), stretch),
+            None => ([1., 0., 0., 0.], linear),
+        };
+        Decomposed { translation, rotation, stretch }
+    }
+
+    fn matrix(&self) -> Matrix {
+        let rs = multiply(&from_quaternion(&self.rotation), &self.stretch);
+        let mut m = Matrix::identity(4);
+        for i in 0..3 {
+            for j in 0..3 {
+                m[(i, j)] = rs[i][j];
+            }
+            m[(i, 3)] = self.translation[i];
+        }
+        m
+    }
+}
+
+
+/// An object's transform at some moment, with the inverses that rays
+/// and normals are mapped through.
+#[derive(Debug, Clone)]
+pub struct Pose {
+    pub transform: Matrix,
+    pub inverse: Matrix,
+    pub inverse_transposed: Matrix,
+}
+
+impl Pose {
+    pub fn new(transform: Matrix) -> Pose {
+        let inverse = transform.inverse().unwrap();
+        let inverse_transposed = inverse.transpose();
+        Pose { transform, inverse, inverse_transposed }
+    }
+}
+
+
+/// A transform that changes over time, from `start` at time 0 to `end`
+/// at time 1. Rotation is interpolated along the shortest arc.
+#[derive(Debug, Clone)]
+pub struct Motion {
+    start: Decomposed,
+    end: Decomposed,
+}
+
+impl Motion {
+    pub fn new(start: &Matrix, end: &Matrix) -> Motion {
+        Motion { start: Decomposed::new(start), end: Decomposed::new(end) }
+    }
+
+    /// The transform at `time`, clamped to [0, 1].
+    pub fn transform_at(&self, time: f64) -> Matrix {
+        let t = time.clamp(0., 1.);
+        let lerp = |a: f64, b: f64| a + (b - a) * t;
+        let mut stretch = [[0.; 3]; 3];
+        for (i, row) in stretch.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = lerp(self.start.stretch[i][j], self.end.stretch[i][j]);
+            }
+        }
+        Decomposed {
+            translation: [0, 1, 2].map(|i| lerp(self.start.translation[i], self.end.translation[i])),
+            rotation: slerp(&self.start.rotation, &self.end.rotation, t),
+            stretch,
+        }.matrix()
+    }
+
+    /// The pose at `time`, clamped to [0, 1].
+    pub fn at(&self, time: f64) -> Pose {
+        Pose::new(self.transform_at(time))
+    }
+
+    /// The angle, in radians, that the rotation turns through.
+    pub fn rotation_angle(&self) -> f64 {
+        2. * dot4(&self.start.rotation, &self.end.rotation).abs().min(1.).acos()
+    }
+}
+
+
+/// Splits `m` into a rotation and a symmetric stretch, `m = R * S`, by
+/// averaging with the inverse transpose until it converges. Reflections
+/// are kept in the stretch. None for singular matrices.
+fn polar_decomposition(m: &Matrix3) -> Option<(Matrix3, Matrix3)> {
+    let mut r = *m;
+    for _ in 0..100 {
+        let inverse_transpose = transpose(&inverse(&r)?);
+        let mut next = [[0.; 3]; 3];
+        let mut change: f64 = 0.;
+        for i in 0..3 {
+            for j in 0..3 {
+                next[i][j] = 0.5 * (r[i][j] + inverse_transpose[i][j]);
+                change = change.max((next[i][j] - r[i][j]).abs());
+            }
+        }
+        r = next;
+        if change < 1e-12 {
+            break;
+        }
+    }
+    if determinant(&r) < 0. {
+        r = r.map(|row| row.map(|v| -v));
+    }
+    Some((r, multiply(&transpose(&r), m)))
+}
+
+
+fn multiply(a: &Matrix3, b: &Matrix3) -> Matrix3 {
+    let mut out = [[0.; 3]; 3];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    out
+}
+
+fn transpose(m: &Matrix3) -> Matrix3 {
+    let mut out = [[0.; 3]; 3];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = m[j][i];
+        }
+    }
+    out
+}
+
+fn determinant(m: &Matrix3) -> f64 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+fn inverse(m: &Matrix3) -> Option<Matrix3> {
+    let det = determinant(m);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let mut out = [[0.; 3]; 3];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            // Cofactor of m[j][i], from the cyclic neighbours of row j and column i.
+            let (r1, r2) = ((j + 1) % 3, (j + 2) % 3);
+            let (c1, c2) = ((i + 1) % 3, (i + 2) % 3);
+            *value = (m[r1][c1] * m[r2][c2] - m[r1][c2] * m[r2][c1]) / det;
+        }
+    }
+    Some(out)
+}
+
+
+fn to_quaternion(r: &Matrix3) -> [f64; 4] {
+    let trace = r[0][0] + r[1][1] + r[2][2];
+    let q = if trace > 0. {
+        let s = 2. * (trace + 1.).sqrt();
+        [0.25 * s, (r[2][1] - r[1][2]) / s, (r[0][2] - r[2][0]) / s, (r[1][0] - r[0][1]) / s]
+    } else if r[0][0] > r[1][1] && r[0][0] > r[2][2] {
+        let s = 2. * (1. + r[0][0] - r[1][1] - r[2][2]).sqrt();
+        [(r[2][1] - r[1][2]) / s, 0.25 * s, (r[0][1] + r[1][0]) / s, (r[0][2] + r[2][0]) / s]
+    } else if r[1][1] > r[2][2] {
+        let s = 2. * (1. + r[1][1] - r[0][0] - r[2][2]).sqrt();
+        [(r[0][2] - r[2][0]) / s, (r[0][1] + r[1][0]) / s, 0.25 * s, (r[1][2] + r[2][1]) / s]
+    } else {
+        let s = 2. * (1. + r[2][2] - r[0][0] - r[1][1]).sqrt();
+        [(r[1][0] - r[0][1]) / s, (r[0][2] + r[2][0]) / s, (r[1][2] + r[2][1]) / s, 0.25 * s]
+    };
+    normalize(q)
+}
+
+fn from_quaternion(q: &[f64; 4]) -> Matrix3 {
+    let [w, x, y, z] = *q;
+    [
+        [1. - 2. * (y * y + z * z), 2. * (x * y - w * z), 2. * (x * z + w * y)],
+        [2. * (x * y + w * z), 1. - 2. * (x * x + z * z), 2. * (y * z - w * x)],
+        [2. * (x * z - w * y), 2. * (y * z + w * x), 1. - 2. * (x * x + y * y)],
+    ]
+}
+
+fn dot4(a: &[f64; 4], b: &[f64; 4]) -> f64 {
+    (0..4).map(|i| a[i] * b[i]).sum()
+}
+
+fn normalize(q: [f64; 4]) -> [f64; 4] {
+    let length = dot4(&q, &q).sqrt();
+    q.map(|v| v / length)
+}
+
+/// Spherical interpolation between unit quaternions, the short way round.
+fn slerp(a: &[f64; 4], b: &[f64; 4], t: f64) -> [f64; 4] {
+    let mut cos = dot4(a, b);
+    let b = if cos < 0. {
+        cos = -cos;
+        b.map(|v| -v)
+    } else {
+        *b
+    };
+    if cos > 0.9995 {
+        // Nearly parallel: a normalized lerp is accurate and stable.
+        return normalize([0, 1, 2, 3].map(|i| a[i] + (b[i] - a[i]) * t));
+    }
+    let angle = cos.acos();
+    let (wa, wb) = (((1. - t) * angle).sin(), (t * angle).sin());
+    let sin = angle.sin();
+    [0, 1, 2, 3].map(|i| (a[i] * wa + b[i] * wb) / sin)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+    use crate::transformations::{rotation, scaling, translation, Axis, Transformable};
+    use crate::tuples::Tuple;
+
+    #[test]
+    fn endpoints_are_the_given_transforms() {
+        let start = scaling(1., 2., 3.).rotate(Axis::Y, 0.3).translate(1., 0., 0.);
+        let end = scaling(2., 1., 1.).rotate(Axis::X, -1.2).translate(0., 5., 0.);
+        let motion = Motion::new(&start, &end);
+        assert_eq!(motion.transform_at(0.), start);
+        assert_eq!(motion.transform_at(1.), end);
+    }
+
+    #[test]
+    fn rotation_is_interpolated_along_the_arc() {
+        let motion = Motion::new(&Matrix::identity(4), &rotation(Axis::Z, PI / 2.));
+        let halfway = motion.transform_at(0.5);
+        // A linear blend of the matrices would shrink the point towards the axis.
+        let p = &halfway / Tuple::point(1., 0., 0.);
+        let s = std::f64::consts::FRAC_1_SQRT_2;
+        assert_eq!(p, Tuple::point(s, s, 0.));
+        assert!((motion.rotation_angle() - PI / 2.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn translation_and_scale_are_interpolated_linearly() {
+        let motion = Motion::new(&translation(0., 0., 0.), &scaling(3., 3., 3.).translate(4., 0., 0.));
+        assert_eq!(motion.transform_at(0.5), scaling(2., 2., 2.).translate(2., 0., 0.));
+        assert_eq!(motion.transform_at(2.), motion.transform_at(1.));
+    }
+
+    #[test]
+    fn reflections_survive_decomposition() {
+        let mirrored = scaling(-1., 1., 1.).rotate(Axis::Y, 0.5);
+        let motion = Motion::new(&mirrored, &mirrored);
+        assert_eq!(motion.transform_at(0.5), mirrored);
+    }
+}
@@ -66,3 +66,58 @@ pub fn refract(
     let out_parallel = normal * -((1.-out_perp.magnitude_squared()).abs().sqrt());
     out_perp + out_parallel
 }
+
+
+/// Builds two unit tangents that, together with the unit `normal`,
+/// form a right-handed orthonormal basis `(tangent, bitangent, normal)`.
+///
+/// Uses the branchless construction of Duff et al. (2017).
+pub fn tangent_frame(normal: &Tuple<Vector>) -> (Tuple<Vector>, Tuple<Vector>) {
+    let sign = 1.0_f64.copysign(normal.z);
+    let a = -1. / (sign + normal.z);
+    let b = normal.x * normal.y * a;
+    let tangent = Tuple::vector(1. + sign * normal.x * normal.x * a, sign * b, -sign * normal.x);
+    let bitangent = Tuple::vector(b, sign + normal.y * normal.y * a, -normal.y);
+    (tangent, bitangent)
+}
+
+
+/// Maps `local`, given in a frame where `normal` is the z axis, to world space.
+pub fn from_local(local: &Tuple<Vector>, normal: &Tuple<Vector>) -> Tuple<Vector> {
+    let (tangent, bitangent) = tangent_frame(normal);
+    tangent * local.x + bitangent * local.y + normal * local.z
+}
+
+
+#[cfg(test)]
+mod tangent_frame_tests {
+    use proptest::prelude::*;
+    use crate::eq;
+    use crate::tuples::proptest_strategies::vector;
+
+    use super::*;
+
+    proptest! {
+
+        #[test]
+        fn tangent_frame_is_orthonormal(
+            normal in vector(100.).prop_map(|v| v.unit()),
+        ) {
+            let (t, b) = tangent_frame(&normal);
+            assert!(eq(t.magnitude(), 1.));
+            assert!(eq(b.magnitude(), 1.));
+            assert!(eq(t.dot(&b), 0.));
+            assert!(eq(t.dot(&normal), 0.));
+            assert!(eq(b.dot(&normal), 0.));
+        }
+
+        #[test]
+        fn local_z_axis_maps_to_normal(
+            normal in vector(100.).prop_map(|v| v.unit()),
+        ) {
+            let z = Tuple::vector(0., 0., 1.);
+            assert_eq!(from_local(&z, &normal), normal);
+        }
+
+    }
+}
@@ -1,15 +1,33 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::rc::Rc;
+
 use crate::materials::Material;
 use crate::matrix::Matrix;
 use crate::tuples::{Tuple, Point, Vector};
 use crate::shapes::Shape;
+use crate::media::Medium;
+use crate::motion::{Motion, Pose};
+use crate::bounds::Aabb;
+
+/// Number of poses along a motion that its bounding box is built from.
+const MOTION_BOUND_STEPS: usize = 32;
 
 #[derive(Debug)]
 pub struct Object {
     pub shape: Shape,
     pub material: Box<dyn Material>,
     pub transform: Matrix,
-    pub inverse_transform: Matrix,
-    pub inverse_transform_transposed: Matrix,
+    /// The transform above with its inverses.
+    pose: Rc<Pose>,
+    /// If set, the object is a volume: its shape bounds the medium.
+    pub medium: Option<Medium>,
+    /// If set, the object moves over time, from the transform above at
+    /// time 0, and is contained in the given box throughout.
+    motion: Option<(Motion, Aabb)>,
+    /// The last pose of a moving object and its time, since a ray and
+    /// the hit it finds ask for the same pose many times over.
+    posed: RefCell<Option<(f64, Rc<Pose>)>>,
 }
 
 impl Object {
@@ -18,8 +36,10 @@ impl Object {
             shape,
             material: Default::default(),
             transform: Matrix::identity(4),
-            inverse_transform: Matrix::identity(4),
-            inverse_transform_transposed: Matrix::identity(4),
+            pose: Rc::new(Pose::new(Matrix::identity(4))),
+            medium: None,
+            motion: None,
+            posed: RefCell::new(None),
         }
     }
 
@@ -34,19 +54,86 @@ impl Object {
         }
     }
 
+    /// Fills the object with a participating medium.
+    ///
+    /// This also makes the medium's volume the object's material.
+    pub fn with_medium(self, medium: Medium) -> Object {
+        Object {
+            material: Box::new(medium.volume),
+            medium: Some(medium),
+            ..self
+        }
+    }
+
     pub fn with_transform(self, transform: Matrix) -> Object {
-        let inverse_transform = transform.inverse().unwrap();
-        let inverse_transform_transposed = inverse_transform.transpose();
         Object {
+            pose: Rc::new(Pose::new(transform.clone())),
             transform,
-            inverse_transform,
-            inverse_transform_transposed,
+            motion: None,
+            posed: RefCell::new(None),
             ..self
         }
     }
 
-    pub fn normal_at(&self, position: Tuple<Point>) -> Tuple<Vector> {
-        self.shape.normal_at(self, &position)
+    /// Moves the object from `start` at time 0 to `end` at time 1.
+    ///
+    /// In between, translation and stretch are blended linearly and
+    /// rotation turns steadily along the shortest arc.
+    pub fn with_animated_transform(self, start: Matrix, end: Matrix) -> Object {
+        let motion = Motion::new(&start, &end);
+        let object = self.with_transform(start);
+        let object_bounds = object.shape.object_bounds();
+        let mut bounds = Aabb::empty();
+        for step in 0..=MOTION_BOUND_STEPS {
+            let time = step as f64 / MOTION_BOUND_STEPS as f64;
+            bounds = bounds.union(&object_bounds.transform(&motion.transform_at(time)));
+        }
+        // Between steps, corners swing out along arcs by at most this much.
+        let step_angle = motion.rotation_angle() / MOTION_BOUND_STEPS as f64;
+        let bounds = bounds.pad(bounds.diagonal() * (1. - (step_angle / 2.).cos()));
+        Object { motion: Some((motion, bounds)), ..object }
+    }
+
+    /// Whether the object moves over time.
+    pub fn is_moving(&self) -> bool {
+        self.motion.is_some()
+    }
+
+    /// A box containing the object at all times.
+    pub fn bounds(&self) -> Aabb {
+        match &self.motion {
+            Some((_, bounds)) => *bounds,
+            None => self.shape.object_bounds().transform(&self.transform),
+        }
+    }
+
+    pub fn transform_at(&self, time: f64) -> Cow<'_, Matrix> {
+        match &self.motion {
+            Some(_) => Cow::Owned(self.pose_at(time).transform.clone()),
+            None => Cow::Borrowed(&self.transform),
+        }
+    }
+
+    /// The transform and its inverses with the object posed as at `time`.
+    ///
+    /// A moving object keeps its last pose, so that it's only worked out
+    /// once for each ray time.
+    pub fn pose_at(&self, time: f64) -> Rc<Pose> {
+        let Some((motion, _)) = &self.motion else { return Rc::clone(&self.pose) };
+        let mut posed = self.posed.borrow_mut();
+        match &*posed {
+            Some((posed_time, pose)) if posed_time.to_bits() == time.to_bits() => Rc::clone(pose),
+            _ => {
+                let pose = Rc::new(motion.at(time));
+                *posed = Some((time, Rc::clone(&pose)));
+                pose
+            }
+        }
+    }
+
+    /// The surface normal at `position`, with the object posed as at `time`.
+    pub fn normal_at(&self, position: Tuple<Point>, time: f64) -> Tuple<Vector> {
+        self.shape.normal_at(self, &position, time)
     }
 }
 
@@ -54,7 +141,9 @@ impl Object {
 #[cfg(test)]
 mod object_transform_tests {
     use super::*;
-    use crate::transformations::translation;
+    use crate::transformations::{translation, rotation, Axis, Transformable};
+    use crate::intersection::Intersectable;
+    use crate::ray::Ray;
 
     #[test]
     fn a_spheres_default_transformation() {
@@ -68,4 +157,45 @@ mod object_transform_tests {
             .with_transform(translation(2.0, 3.0, 4.0));
         assert_eq!(s.transform, translation(2.0, 3.0, 4.0));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn moving_spheres_are_hit_where_they_are_at_the_time() {
+        let s = Object::new_sphere()
+            .with_animated_transform(translation(0., 0., 0.), translation(4., 0., 0.));
+        let ray = Ray::from_coords(4., 0., -5., 0., 0., 1.);
+        assert!(Object::intersect(&ray, &s).is_empty());
+        let later = Ray::from_coords(4., 0., -5., 0., 0., 1.).with_time(1.);
+        assert_eq!(Object::intersect(&later, &s).len(), 2);
+        assert_eq!(s.normal_at(Tuple::point(4., 0., -1.), 1.), Tuple::vector(0., 0., -1.));
+    }
+
+    #[test]
+    fn moving_objects_are_posed_once_per_time() {
+        let s = Object::new_sphere()
+            .with_animated_transform(translation(0., 0., 0.), translation(4., 0., 0.));
+        let pose = s.pose_at(0.5);
+        assert!(Rc::ptr_eq(&pose, &s.pose_at(0.5)));
+        assert!(!Rc::ptr_eq(&pose, &s.pose_at(0.25)));
+        assert_eq!(pose.transform, translation(2., 0., 0.));
+        assert_eq!(pose.inverse, translation(-2., 0., 0.));
+    }
+
+    #[test]
+    fn bounds_cover_the_whole_motion() {
+        let s = Object::new(Shape::Cube).with_animated_transform(
+            translation(0., 0., 0.),
+            rotation(Axis::Z, std::f64::consts::PI).translate(0., 3., 0.),
+        );
+        let bounds = s.bounds();
+        for step in 0..=100 {
+            let pose = s.transform_at(step as f64 / 100.);
+            let posed = s.shape.object_bounds().transform(&pose);
+            for p in [posed.min, posed.max] {
+                assert!(bounds.min.x <= p.x && bounds.min.y <= p.y && bounds.min.z <= p.z);
+                assert!(p.x <= bounds.max.x && p.y <= bounds.max.y && p.z <= bounds.max.z);
+            }
+        }
+        // Setting a fixed transform stops the motion.
+        assert!(!s.with_transform(translation(1., 0., 0.)).is_moving());
+    }
+}
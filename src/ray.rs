@@ -5,27 +5,45 @@ use crate::matrix::Matrix;
 pub struct Ray {
     pub origin: Tuple<Point>,
     pub direction: Tuple<Vector>,
+    /// The wavelength (in nm) this ray carries, if it is a spectral sample.
+    pub wavelength: Option<f64>,
+    /// When the ray was cast, for objects that move while the shutter is open.
+    pub time: f64,
 }
 
 impl Ray {
     pub fn new(origin: Tuple<Point>, direction: Tuple<Vector>) -> Ray {
-        Ray { origin, direction }
+        Ray { origin, direction, wavelength: None, time: 0. }
     }
 
     pub fn from_coords(x: f64, y: f64, z: f64, dx: f64, dy: f64, dz: f64) -> Ray {
-        Ray {
-            origin: Tuple::point(x, y, z),
-            direction: Tuple::vector(dx, dy, dz),
-        }
+        Ray::new(
+            Tuple::point(x, y, z),
+            Tuple::vector(dx, dy, dz),
+        )
+    }
+
+    pub fn with_wavelength(self, wavelength: Option<f64>) -> Ray {
+        Ray { wavelength, ..self }
+    }
+
+    pub fn with_time(self, time: f64) -> Ray {
+        Ray { time, ..self }
+    }
+
+    /// Starts a new ray that continues this ray's path,
+    /// e.g. after scattering off a surface.
+    ///
+    /// The new ray carries over the per-path state, such as the wavelength and time.
+    pub fn spawn(&self, origin: Tuple<Point>, direction: Tuple<Vector>) -> Ray {
+        Ray { origin, direction, ..*self }
     }
 
     pub fn transform(&self, m: &Matrix) -> Ray {
-        Ray {
-            origin: m / &self.origin,
-            direction: m / &self.direction,
-        }
+        self.spawn(m / self.origin, m / self.direction)
     }
 
+    #[allow(clippy::op_ref)]
     pub fn position(&self, t: f64) -> Tuple<Point> {
         &self.origin + &self.direction * t
     }
@@ -55,5 +73,21 @@ mod ray_transform_tests {
         assert_eq!(r2.origin, Tuple::point(2., 6., 12.));
         assert_eq!(r2.direction, Tuple::vector(0., 3., 0.));
     }
+
+    #[test]
+    fn spawned_rays_keep_their_wavelength() {
+        let r = Ray::from_coords(1., 2., 3., 0., 1., 0.,)
+            .with_wavelength(Some(500.));
+        let r2 = r.spawn(Tuple::point(0., 0., 0.), Tuple::vector(1., 0., 0.));
+        assert_eq!(r2.wavelength, Some(500.));
+        assert_eq!(r.transform(&scaling(2., 2., 2.)).wavelength, Some(500.));
+    }
+
+    #[test]
+    fn spawned_rays_keep_their_time() {
+        let r = Ray::from_coords(1., 2., 3., 0., 1., 0.,).with_time(0.25);
+        let r2 = r.spawn(Tuple::point(0., 0., 0.), Tuple::vector(1., 0., 0.));
+        assert_eq!(r2.time, 0.25);
+    }
 }
 
@@ -1,94 +1,513 @@
+use std::ops::Range;
 use std::time::Instant;
 use rand::Rng;
 
 use crate::{
     ray::Ray,
-    intersection::{
-        Intersectable, hit, intersect
-    },
+    intersection::{hit, intersect},
     cli::Config,
     colors::Color,
     materials::Scattered,
     canvas::Canvas,
-    camera::Camera,
+    film::Film,
+    checkpoint::{Checkpoint, SamplingSettings},
+    rng,
+    camera::CameraModel,
+    spectrum::{sample_wavelength_stratified, wavelength_to_rgb, rgb_to_spectrum, Xyz},
+    media::sample_free_flight,
+    world::World,
+    tuples::{Tuple, Point, Vector},
 };
 
 
+/// Radiance arriving along `ray`.
+///
+/// `scatter_pdf` is the pdf with which the previous bounce sampled `ray`,
+/// if that bounce also sampled a light directly; it is used to weight
+/// light that the ray finds by chance against the direct light sample.
 pub fn ray_color(
     ray: &Ray,
-    world: &impl Intersectable,
+    world: &World,
     min_t: f64,
     depth: usize,
     config: &Config,
+    scatter_pdf: Option<f64>,
 ) -> Color {
 
     if depth > config.max_scatter_depth {
         return Color::new(0., 0., 0.)
     }
 
-    if let Some(h) = hit(
-        intersect(ray, world).as_slice(), min_t,
-    ) {
-        return h.object.material
+    let intersections = intersect(ray, world);
+    let surface_hit = hit(intersections.as_slice(), min_t);
+
+    // Fog fills the space up to the nearest surface, or up to its far
+    // distance for rays that leave the scene.
+    if let Some(fog) = &world.fog {
+        let end = surface_hit.map_or(fog.far / ray.direction.magnitude(), |h| h.t);
+        let t = min_t + sample_free_flight(fog.density) / ray.direction.magnitude();
+        if t < end {
+            let position = ray.position(t);
+            let direct = direct_lighting(ray, world, position, depth, config,
+                |direction| Some(fog.volume.evaluate_towards(ray, direction)));
+            let Scattered{ attenuation, ray: scattered_ray, pdf } =
+                fog.volume.scatter_at(ray, position);
+            // Inside an absorbing object, the light is also absorbed on its
+            // way here from the ray's origin.
+            let absorbed = match surface_hit {
+                Some(h) if h.object.normal_at(ray.position(h.t), ray.time).dot(&ray.direction) > 0. =>
+                    h.object.material.transmittance(t * ray.direction.magnitude()),
+                _ => Color::new(1., 1., 1.),
+            };
+            let scattered = at_wavelength(attenuation, ray, config)
+                * ray_color(&scattered_ray, world, 0.001, depth+1, config, pdf);
+            return at_wavelength(absorbed, ray, config) * (direct + scattered)
+        }
+    }
+
+    if let Some(h) = surface_hit {
+        let material = &h.object.material;
+        let position = ray.position(h.t);
+        let emitted = match material.is_emissive() {
+            false => Color::new(0., 0., 0.),
+            true => {
+                let radiance = material.emitted(&h.object.normal_at(position, ray.time), &-ray.direction.unit());
+                let weight = match scatter_pdf {
+                    Some(pdf) => power_heuristic(
+                        pdf, world.area_light_pdf(h.object, &ray.origin, &position, ray.time)),
+                    None => 1.,
+                };
+                at_wavelength(radiance, ray, config) * weight
+            }
+        };
+        let direct = direct_lighting(ray, world, position, depth, config,
+            |direction| material.evaluate(ray, h, direction));
+        return emitted + direct + material
             .scatter(ray, h)
-            .map(|Scattered{ attenuation, ray: scattered_ray }|
-                attenuation * ray_color(&scattered_ray, world, 0.001, depth+1, config)
+            .map(|Scattered{ attenuation, ray: scattered_ray, pdf }|
+                at_wavelength(attenuation, ray, config)
+                * ray_color(&scattered_ray, world, 0.001, depth+1, config, pdf)
             ).unwrap_or(Color::new(0., 0., 0.))
     };
 
 
-    // Missed everything in the world: draw the sky.
-    let t = (1. + ray.direction.unit().y) / 2.;
-    let blue = Color::from_u8(135, 181, 235);
-    let lightblue = Color::from_u8(135, 231, 235);
-     blue * t + lightblue * (1. - t)
+    // Missed everything in the world: draw the environment.
+    let radiance = at_wavelength(world.environment.radiance(&ray.direction), ray, config);
+    match scatter_pdf {
+        Some(pdf) if world.environment.is_sampled() =>
+            radiance * power_heuristic(pdf, world.environment_pdf(&ray.direction)),
+        _ => radiance,
+    }
+}
+
+
+/// Light arriving at `position` straight from a sampled light, scattered
+/// back along `ray`. `evaluate` gives the scattering function times the
+/// cosine term towards a direction, and the pdf of scattering that way.
+fn direct_lighting(
+    ray: &Ray,
+    world: &World,
+    position: Tuple<Point>,
+    depth: usize,
+    config: &Config,
+    evaluate: impl Fn(&Tuple<Vector>) -> Option<(Color, f64)>,
+) -> Color {
+    let black = Color::new(0., 0., 0.);
+    let Some(light) = world.sample_light(&position, ray.time) else { return black };
+    let Some((scattering, scatter_pdf)) = evaluate(&light.direction) else { return black };
+    if scattering.red + scattering.green + scattering.blue <= 0. {
+        return black;
+    }
+    let shadow_ray = ray.spawn(position, light.direction);
+    // Stop short of the light, so that area lights don't shadow themselves.
+    let visibility = world.transmittance(&shadow_ray, 0.001, light.distance - 0.001);
+    if visibility <= 0. {
+        return black;
+    }
+    // On the last bounce, scattered rays can't find the light by chance.
+    let scatter_pdf = if depth < config.max_scatter_depth { scatter_pdf } else { 0. };
+    let weight = match light.pdf {
+        Some(light_pdf) => power_heuristic(light_pdf, scatter_pdf) / light_pdf,
+        None => 1.,
+    };
+    at_wavelength(scattering, ray, config)
+        * at_wavelength(light.radiance, ray, config)
+        * (visibility * weight)
+}
+
+
+/// Veach's power heuristic for weighting a sample from one of two strategies.
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b <= 0. { 0. } else { a / (a + b) }
+}
+
+
+/// In spectral mode, replaces an RGB color by the value of its upsampled
+/// spectrum at the ray's wavelength, so that only channel values matter.
+fn at_wavelength(color: Color, ray: &Ray, config: &Config) -> Color {
+    match (config.spectral, ray.wavelength) {
+        (true, Some(wavelength)) => {
+            let value = rgb_to_spectrum(color, wavelength);
+            Color::new(value, value, value)
+        }
+        _ => color,
+    }
+}
+
+
+/// Adds radiance samples through a pixel to `film`, with the pixel
+/// counted from the bottom left. Wavelengths are stratified over the
+/// `samples` taken together.
+fn sample_pixel(
+    world: &World,
+    camera: &dyn CameraModel,
+    config: &Config,
+    film: &mut Film,
+    (x_pixel, y_pixel): (usize, usize),
+    samples: Range<usize>,
+) {
+    let mut rng = crate::rng::thread_rng();
+    let pixel_height = 1. / film.height as f64;
+    let pixel_width = 1. / film.width as f64;
+    let y = y_pixel as f64 / film.height as f64;
+    let x = x_pixel as f64 / film.width as f64;
+    let strata = samples.len();
+
+    for stratum in 0..strata {
+        let x_sample = rng.gen_range(x..x+pixel_width);
+        let y_sample = rng.gen_range(y..y+pixel_height);
+        let (open, close) = config.shutter;
+        let time = if close > open { rng.gen_range(open..close) } else { open };
+
+        // Points the camera doesn't cover stay black.
+        let sample = match camera.cast_ray(x_sample, y_sample, time) {
+            None => Color::new(0., 0., 0.),
+            Some(ray) if config.spectral => {
+                let wavelength = sample_wavelength_stratified(stratum, strata);
+                let ray = ray.with_wavelength(Some(wavelength));
+                let radiance = ray_color(&ray, world, 0., 0, config, None).red;
+                Xyz::from_spectral_sample(radiance, wavelength).to_linear_srgb()
+            }
+            Some(ray) if config.dispersion => {
+                // Trace a single hero wavelength, and convert back to RGB here.
+                let wavelength = sample_wavelength_stratified(stratum, strata);
+                let ray = ray.with_wavelength(Some(wavelength));
+                ray_color(&ray, world, 0., 0, config, None) * wavelength_to_rgb(wavelength)
+            }
+            Some(ray) => ray_color(&ray, world, 0., 0, config, None),
+        };
+        // Film uses an inverted y coordinate, like Canvas.
+        film.add_sample(x_pixel, film.height - 1 - y_pixel, sample);
+    }
+}
+
+
+/// The most samples any pixel gets.
+fn samples_per_pixel(config: &Config) -> usize {
+    match config.noise_threshold {
+        Some(_) => config.max_samples,
+        None => config.rays_per_pixel,
+    }
+}
+
+
+/// Whether a pixel has been sampled enough, with the pixel addressed
+/// like the film.
+fn is_done(config: &Config, film: &Film, x: usize, y: usize) -> bool {
+    let samples = film.samples_at(x, y);
+    samples >= samples_per_pixel(config) || config.noise_threshold.is_some_and(|threshold|
+        samples >= config.min_samples && film.relative_error(x, y) <= threshold)
 }
 
 
 pub fn render_scene(
-    world: &impl Intersectable,
-    camera: &Camera,
+    world: &World,
+    camera: &dyn CameraModel,
     config: &Config,
 ) -> Canvas {
-    let mut rng = rand::thread_rng();
+    render_progressive(world, camera, config, None, &mut |_| {}, &mut |_| {}).to_canvas()
+}
+
 
+/// Renders the image in passes over every pixel, of `samples_per_pass`
+/// samples each, until each pixel has `rays_per_pixel` samples or the
+/// `time_limit` is up, whichever comes first. The time limit is only
+/// checked between passes, so that all pixels get the same number of
+/// passes, and the last pass may run past it.
+///
+/// With a `noise_threshold`, sampling is adaptive instead: a pixel stops
+/// once it has at least `min_samples` samples and its relative error is
+/// under the threshold, or once it has `max_samples`.
+///
+/// When sampling adaptively or to a time limit, passes default to
+/// `min_samples` each.
+///
+/// Between passes, hands the film so far to `snapshot` every
+/// `snapshot_passes` passes or `snapshot_seconds` seconds, whichever
+/// comes first, or after every pass if neither is set. Between rows,
+/// hands where it has got to to `checkpoint` every `checkpoint_seconds`.
+///
+/// Starting from a checkpoint carries on from there, and gives the same
+/// image as a render that never stopped, as long as the checkpoint passes
+/// `Checkpoint::check_resumable`. Time spent before the checkpoint counts
+/// towards the time limit.
+pub fn render_progressive(
+    world: &World,
+    camera: &dyn CameraModel,
+    config: &Config,
+    resume: Option<Checkpoint>,
+    snapshot: &mut dyn FnMut(&Film),
+    checkpoint: &mut dyn FnMut(&Checkpoint),
+) -> Film {
     let image_width = (config.aspect_ratio * config.image_height as f64) as usize;
-    let mut canvas = Canvas::new(image_width, config.image_height);
-    let pixel_height = 1. / config.image_height as f64;
-    let pixel_width = 1. / image_width as f64;
+    let (from_row, to_row) = config.row_range;
+    let mut state = resume.unwrap_or_else(|| Checkpoint {
+        seed: config.seed,
+        pass: 1,
+        row: from_row,
+        elapsed: 0.,
+        fingerprint: config.scene_fingerprint(),
+        settings: SamplingSettings::from_config(config),
+        film: Film::new(image_width, config.image_height),
+    });
+    let total = samples_per_pixel(config);
+    let pass_size = match (config.noise_threshold, config.time_limit) {
+        (None, None) => config.samples_per_pass.unwrap_or(total),
+        _ => config.samples_per_pass.unwrap_or(config.min_samples),
+    }.max(1);
+    let max_passes = total.div_ceil(pass_size);
 
     let start_time = Instant::now();
-    let (from_row, to_row) = config.row_range;
-    for y_pixel in from_row..to_row {
-        if y_pixel % 1 == 0 {
-            if config.verbose {
+    let mut last_snapshot = start_time;
+    let mut last_checkpoint = start_time;
+    let elapsed_before = state.elapsed;
+    let elapsed = || elapsed_before + start_time.elapsed().as_secs_f64();
+    let out_of_time = || config.time_limit.is_some_and(|limit| elapsed() >= limit);
+    while state.pass <= max_passes {
+        if config.verbose && max_passes > 1 {
+            println!(
+                "Rendering pass {}, time elapsed: {:?}",
+                state.pass, start_time.elapsed()
+            );
+        }
+        while state.row < to_row {
+            if config.checkpoint_seconds
+                .is_some_and(|t| last_checkpoint.elapsed().as_secs_f64() >= t) {
+                state.elapsed = elapsed();
+                checkpoint(&state);
+                last_checkpoint = Instant::now();
+            }
+            let y_pixel = state.row;
+            if config.verbose && max_passes == 1 {
                 let duration = start_time.elapsed();
                 println!(
                     "Rendering row {} of {}, time elapsed: {:?}",
                     y_pixel, to_row-from_row, duration
                 );
             }
-        }
-        for x_pixel in 0..canvas.width {
-            let y = y_pixel as f64 / canvas.height as f64;
-            let x = x_pixel as f64 / canvas.width as f64;
-
-            let mut color = Color::new(0., 0., 0.);
-            for _ in 0..config.rays_per_pixel {
-                let x_sample = rng.gen_range(x..x+pixel_width);
-                let y_sample = rng.gen_range(y..y+pixel_height);
-
-                let ray = camera.cast_ray(x_sample, y_sample);
-                let sample = ray_color(&ray, world, 0., 0, config);
-                color = color + sample;
+            let film = &mut state.film;
+            let film_y = film.height - 1 - y_pixel;
+            for x_pixel in 0..film.width {
+                if is_done(config, film, x_pixel, film_y) {
+                    continue;
+                }
+                // Each pixel's pass draws from its own stream, so it comes out
+                // the same however the render was interrupted.
+                rng::reseed(rng::pixel_seed(state.seed, state.pass, x_pixel, y_pixel));
+                let taken = film.samples_at(x_pixel, film_y);
+                let samples = taken..(taken + pass_size).min(total);
+                sample_pixel(world, camera, config, film, (x_pixel, y_pixel), samples);
             }
-            canvas.write_pixel(
-                x_pixel,
-                canvas.height - 1 - y_pixel,  // Canvas uses an inverted y coordinate.
-                (color * (1./config.rays_per_pixel as f64)).gamma_encode()
-            );
+            state.row += 1;
+        }
+
+        let film = &state.film;
+        let unfinished = (from_row..to_row).any(|y_pixel| (0..film.width)
+            .any(|x_pixel| !is_done(config, film, x_pixel, film.height - 1 - y_pixel)));
+        if !unfinished || out_of_time() {
+            break;
+        }
+
+        let due = match (config.snapshot_passes, config.snapshot_seconds) {
+            (None, None) => true,
+            (every_passes, every_seconds) =>
+                every_passes.is_some_and(|k| state.pass.is_multiple_of(k))
+                || every_seconds.is_some_and(|t| last_snapshot.elapsed().as_secs_f64() >= t),
+        };
+        if due {
+            snapshot(&state.film);
+            last_snapshot = Instant::now();
+        }
+        state.pass += 1;
+        state.row = from_row;
+    }
+
+    state.film
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+    use super::*;
+    use crate::{
+        cli::{cli, make_config},
+        groups::ObjectGroup,
+        lights::Light,
+        materials::{Lambertian, DiffuseLight},
+        object::Object,
+        shapes::Shape,
+        environment::{Environment, EnvironmentMap},
+        hdr::HdrImage,
+        transformations::{scaling, rotation, Axis, Transformable},
+    };
+
+    #[test]
+    fn point_lights_light_diffuse_surfaces_directly() {
+        let config = make_config(cli().get_matches_from(
+            ["crayfish", "--max_scatter_depth", "0"])).unwrap();
+        let mut objects = ObjectGroup::new();
+        objects.add(Object::new_sphere()
+            .with_material(Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))));
+        let world = World::new(objects)
+            .with_light(Light::point(Tuple::point(0., 3., 0.), Color::new(4., 4., 4.)));
+
+        // The top of the sphere is 2 units below the light, facing it.
+        let ray = Ray::from_coords(0., 5., 0., 0., -1., 0.);
+        let expected = 0.5 / PI;
+        assert_eq!(ray_color(&ray, &world, 0., 0, &config, None),
+                   Color::new(expected, expected, expected));
+
+        // The bottom is in shadow.
+        let ray = Ray::from_coords(0., -5., 0., 0., 1., 0.);
+        assert_eq!(ray_color(&ray, &world, 0., 0, &config, None), Color::new(0., 0., 0.));
+    }
+
+    #[test]
+    fn area_lights_match_the_analytic_irradiance() {
+        // A floor lit by a disk of radius 1 facing down from a height of 1.
+        let mut objects = ObjectGroup::new();
+        objects.add(Object::new(Shape::Quad)
+            .with_transform(scaling(100., 1., 100.))
+            .with_material(Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))));
+        objects.add(Object::new(Shape::Disk)
+            .with_transform(rotation(Axis::X, PI).translate(0., 1., 0.))
+            .with_material(Box::new(DiffuseLight::new(Color::new(2., 2., 2.)))));
+        let darkness = HdrImage::new(1, 1, vec![Color::new(0., 0., 0.)]).unwrap();
+        let world = World::new(objects)
+            .with_environment(Environment::Map(EnvironmentMap::new(darkness)));
+
+        // Irradiance is π L R² / (h² + R²) = π, so the floor's radiance is 0.5.
+        let ray = Ray::from_coords(0., 0.5, 0., 0., -1., 0.);
+        for depth in ["0", "1"] {
+            let config = make_config(cli().get_matches_from(
+                ["crayfish", "--max_scatter_depth", depth])).unwrap();
+            let samples = 20000;
+            let mean = (0..samples)
+                .map(|_| ray_color(&ray, &world, 0., 0, &config, None).red)
+                .sum::<f64>() / samples as f64;
+            assert!((mean - 0.5).abs() < 0.015, "depth {}: {}", depth, mean);
         }
     }
 
-    canvas
-}
\ No newline at end of file
+    /// A world of `objects` under a sky of `colors`, from west to east.
+    fn under_sky(objects: ObjectGroup, colors: Vec<Color>) -> World {
+        let sky = HdrImage::new(colors.len(), 1, colors).unwrap();
+        World::new(objects).with_environment(Environment::Map(EnvironmentMap::new(sky)))
+    }
+
+    /// The config for a square image, with `options` on the command line.
+    fn square(options: &str) -> Config {
+        let args = format!("crayfish --aspect_ratio 1 1 {}", options);
+        make_config(cli().get_matches_from(args.split(' '))).unwrap()
+    }
+
+    /// Renders a square image of `world` from the origin, looking along z,
+    /// with `options` on the command line. Returns the film, how many
+    /// samples the top left pixel had at each snapshot, and the checkpoints.
+    fn render(
+        world: &World,
+        options: &str,
+        resume: Option<Checkpoint>,
+    ) -> (Film, Vec<usize>, Vec<Checkpoint>) {
+        let config = square(options);
+        let camera = crate::camera::Camera::new(
+            Tuple::point(0., 0., 0.), Tuple::point(0., 0., 1.), 1., PI / 2., None, 0.);
+        let (mut snapshots, mut checkpoints) = (vec![], vec![]);
+        let film = render_progressive(world, &camera, &config, resume,
+            &mut |film| snapshots.push(film.samples_at(0, 0)),
+            &mut |checkpoint| checkpoints.push(checkpoint.clone()));
+        (film, snapshots, checkpoints)
+    }
+
+    #[test]
+    fn progressive_renders_snapshot_between_passes() {
+        let world = under_sky(ObjectGroup::new(), vec![Color::new(1., 1., 1.)]);
+        let (film, snapshots, _) = render(&world,
+            "--image_height 2 --rays_per_pixel 5 --progressive 2", None);
+
+        // Passes of 2, 2 and 1 samples, with snapshots after the first two.
+        assert_eq!(snapshots, vec![2, 4]);
+        assert_eq!(film.samples_at(1, 1), 5);
+        assert_eq!(film.pixel_at(1, 1), Color::new(1., 1., 1.));
+    }
+
+    #[test]
+    fn adaptive_sampling_stops_at_converged_pixels() {
+        let world = under_sky(ObjectGroup::new(), vec![Color::new(1., 1., 1.)]);
+        let (film, _, _) = render(&world,
+            "--image_height 2 --noise_threshold 0.01 --min_samples 4 --max_samples 64", None);
+
+        // A flat sky has no noise at all.
+        for (x, y) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+            assert_eq!(film.samples_at(x, y), 4);
+        }
+    }
+
+    #[test]
+    fn time_limits_stop_rendering_after_the_first_pass() {
+        let world = under_sky(ObjectGroup::new(), vec![Color::new(1., 1., 1.)]);
+        let (film, _, _) = render(&world,
+            "--image_height 2 --progressive 3 --time_limit 1e-9", None);
+        assert_eq!(film.samples_at(0, 0), 3);
+        assert_eq!(film.samples_at(1, 1), 3);
+    }
+
+    #[test]
+    fn resumed_renders_keep_their_time_spent() {
+        let world = under_sky(ObjectGroup::new(), vec![Color::new(1., 1., 1.)]);
+        let options = "--image_height 2 --progressive 1 --time_limit 100";
+        let checkpoint = Checkpoint {
+            seed: 0,
+            pass: 1,
+            row: 0,
+            elapsed: 1000.,
+            fingerprint: square(options).scene_fingerprint(),
+            settings: SamplingSettings::from_config(&square(options)),
+            film: Film::new(2, 2),
+        };
+        let (film, _, _) = render(&world, options, Some(checkpoint));
+        // Already out of time, so only the pass under way is finished.
+        for (x, y) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+            assert_eq!(film.samples_at(x, y), 1);
+        }
+    }
+
+    #[test]
+    fn resumed_renders_match_uninterrupted_ones() {
+        let mut objects = ObjectGroup::new();
+        objects.add(Object::new_sphere()
+            .with_transform(scaling(0.5, 0.5, 0.5).translate(0., 0., 2.))
+            .with_material(Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))));
+        let world = under_sky(objects, vec![Color::new(1., 1., 1.), Color::new(0., 0., 1.)]);
+        let options = "--image_height 3 --rays_per_pixel 4 --progressive 2 --checkpoint 1e-9";
+
+        let (film, _, checkpoints) = render(&world, options, None);
+        // Part way through the second pass.
+        let checkpoint = checkpoints.into_iter()
+            .find(|c| (c.pass, c.row) == (2, 1))
+            .unwrap();
+        let (resumed, _, _) = render(&world, options, Some(checkpoint));
+        assert_eq!(resumed.to_bytes(), film.to_bytes());
+    }
+}
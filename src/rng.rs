@@ -0,0 +1,82 @@
+use std::cell::RefCell;
+use rand::{RngCore, SeedableRng, rngs::StdRng};
+
+
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+/// Handle to this thread's random number generator, which all sampling
+/// in the renderer draws from, so that reseeding it makes a render
+/// repeatable.
+#[derive(Debug, Clone, Copy)]
+pub struct ThreadRng;
+
+pub fn thread_rng() -> ThreadRng {
+    ThreadRng
+}
+
+/// Restarts this thread's generator from `seed`.
+pub fn reseed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+/// A uniform random value in [0, 1).
+pub fn random() -> f64 {
+    use rand::Rng;
+    thread_rng().gen()
+}
+
+/// Combines a render's seed with where a sample is taken, so that every
+/// pixel of every pass gets its own stream regardless of what was
+/// sampled before it.
+pub fn pixel_seed(seed: u64, pass: usize, x: usize, y: usize) -> u64 {
+    [pass as u64, x as u64, y as u64].iter().fold(seed, |hash, value| {
+        // SplitMix64 finaliser.
+        let mut z = hash ^ value.wrapping_add(0x9e37_79b9_7f4a_7c15).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    })
+}
+
+impl RngCore for ThreadRng {
+    fn next_u32(&mut self) -> u32 {
+        RNG.with(|rng| rng.borrow_mut().next_u32())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        RNG.with(|rng| rng.borrow_mut().next_u64())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        RNG.with(|rng| rng.borrow_mut().fill_bytes(dest))
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        RNG.with(|rng| rng.borrow_mut().try_fill_bytes(dest))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn reseeding_repeats_the_sequence() {
+        reseed(7);
+        let first: Vec<f64> = (0..4).map(|_| thread_rng().gen()).collect();
+        reseed(7);
+        let second: Vec<f64> = (0..4).map(|_| random()).collect();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn neighbouring_pixels_get_different_seeds() {
+        assert_ne!(pixel_seed(0, 0, 0, 1), pixel_seed(0, 0, 1, 0));
+        assert_ne!(pixel_seed(0, 1, 0, 0), pixel_seed(0, 0, 0, 0));
+        assert_eq!(pixel_seed(3, 2, 1, 0), pixel_seed(3, 2, 1, 0));
+    }
+}
@@ -1,19 +1,254 @@
-use crate::{tuples::{Tuple, Point, Vector}, object::Object};
+use std::f64::consts::PI;
+use rand::Rng;
+
+use crate::{tuples::{Tuple, Point, Vector}, object::Object, bounds::Aabb};
 
 pub mod sphere;
 pub mod cube;
+pub mod quad;
+pub mod disk;
 
 #[derive(Debug)]
 pub enum Shape {
     Sphere,
     Cube,
+    Quad,
+    Disk,
+}
+
+/// A point picked at random on the surface of an object.
+#[derive(Debug, Clone, Copy)]
+pub struct SurfaceSample {
+    pub point: Tuple<Point>,
+    /// Unit outward normal at `point`.
+    pub normal: Tuple<Vector>,
+    /// Probability density of the pick: per unit of world space area,
+    /// or per unit solid angle when sampled towards a point.
+    pub pdf: f64,
 }
 
 impl Shape {
-    pub fn normal_at(&self, obj: &Object, position: &Tuple<Point>) -> Tuple<Vector> {
+    pub fn normal_at(&self, obj: &Object, position: &Tuple<Point>, time: f64) -> Tuple<Vector> {
+        match self {
+            Shape::Sphere => sphere::normal_at_sphere(obj, position, time),
+            Shape::Cube => cube::normal_at_cube(obj, position, time),
+            Shape::Quad => quad::normal_at_quad(obj, position, time),
+            Shape::Disk => disk::normal_at_disk(obj, position, time),
+        }
+    }
+
+    /// Bounding box in object space.
+    pub fn object_bounds(&self) -> Aabb {
+        match self {
+            Shape::Sphere | Shape::Cube => Aabb::new(
+                Tuple::point(-1., -1., -1.), Tuple::point(1., 1., 1.)),
+            Shape::Quad | Shape::Disk => Aabb::new(
+                Tuple::point(-1., 0., -1.), Tuple::point(1., 0., 1.)),
+        }
+    }
+
+    /// Surface area in object space.
+    fn object_area(&self) -> f64 {
+        match self {
+            Shape::Sphere => 4. * PI,
+            Shape::Cube => 24.,
+            Shape::Quad => 4.,
+            Shape::Disk => PI,
+        }
+    }
+
+    /// The outward unit normal at a point on the object space surface.
+    fn object_normal(&self, object_point: &Tuple<Point>) -> Tuple<Vector> {
         match self {
-            Shape::Sphere => sphere::normal_at_sphere(obj, position),
-            Shape::Cube => cube::normal_at_cube(obj, position),
+            Shape::Sphere => (object_point - Tuple::point(0., 0., 0.)).unit(),
+            Shape::Cube => cube::object_normal_at_cube(object_point).unit(),
+            Shape::Quad | Shape::Disk => Tuple::vector(0., 1., 0.),
+        }
+    }
+
+    /// A point distributed uniformly over the object space surface.
+    fn sample_object_surface(&self) -> Tuple<Point> {
+        let mut rng = crate::rng::thread_rng();
+        match self {
+            Shape::Sphere => {
+                let v = Tuple::random_in_unit_sphere().unit();
+                Tuple::point(v.x, v.y, v.z)
+            }
+            Shape::Cube => {
+                let mut p = [rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.];
+                let face = rng.gen_range(0..6);
+                p.swap(2, face / 2);
+                p[face / 2] = if face % 2 == 0 { -1. } else { 1. };
+                Tuple::point(p[0], p[1], p[2])
+            }
+            Shape::Quad => Tuple::point(rng.gen_range(-1.0..1.0), 0., rng.gen_range(-1.0..1.0)),
+            Shape::Disk => {
+                let v = Tuple::random_in_unit_disc();
+                Tuple::point(v.x, 0., v.y)
+            }
+        }
+    }
+
+    /// Picks a point on the surface of `obj` as posed at `time`, uniformly
+    /// over the shape's object space surface. The pdf is per unit of world
+    /// space area.
+    pub fn sample_surface(&self, obj: &Object, time: f64) -> SurfaceSample {
+        let object_point = self.sample_object_surface();
+        let object_normal = self.object_normal(&object_point);
+        let pose = obj.pose_at(time);
+        SurfaceSample {
+            point: &pose.transform / object_point,
+            normal: (&pose.inverse_transposed / object_normal).unit(),
+            pdf: 1. / (self.object_area() * area_scale(obj, &object_normal, time)),
+        }
+    }
+
+    /// The pdf, per unit of world space area, with which `sample_surface`
+    /// picks `point` on the surface of `obj`.
+    pub fn surface_pdf(&self, obj: &Object, point: &Tuple<Point>, time: f64) -> f64 {
+        let object_normal = self.object_normal(&(&obj.pose_at(time).inverse / point));
+        1. / (self.object_area() * area_scale(obj, &object_normal, time))
+    }
+
+    /// Picks a point on the surface of `obj` to light `from`, with a pdf
+    /// per unit solid angle as seen from `from`.
+    ///
+    /// Uniformly scaled spheres are sampled within the cone they subtend,
+    /// other shapes by area. None if the pick can't light `from`.
+    pub fn sample_towards(&self, obj: &Object, from: &Tuple<Point>, time: f64) -> Option<SurfaceSample> {
+        if let Shape::Sphere = self {
+            if let Some(sample) = sphere::sample_sphere_towards(obj, from, time) {
+                return Some(sample);
+            }
+        }
+        let sample = self.sample_surface(obj, time);
+        let pdf = area_to_solid_angle(sample.pdf, from, &sample.point, &sample.normal)?;
+        Some(SurfaceSample { pdf, ..sample })
+    }
+
+    /// The solid angle pdf with which `sample_towards` picks `point`.
+    pub fn pdf_towards(&self, obj: &Object, from: &Tuple<Point>, point: &Tuple<Point>, time: f64) -> f64 {
+        if let Shape::Sphere = self {
+            if let Some(pdf) = sphere::sphere_pdf_towards(obj, from, time) {
+                return pdf;
+            }
         }
+        let normal = self.normal_at(obj, point, time);
+        area_to_solid_angle(self.surface_pdf(obj, point, time), from, point, &normal).unwrap_or(0.)
     }
-}
\ No newline at end of file
+}
+
+
+/// How much the object's transform scales the area of a surface element
+/// with object space unit normal `normal`, by Nanson's formula.
+fn area_scale(obj: &Object, normal: &Tuple<Vector>, time: f64) -> f64 {
+    let pose = obj.pose_at(time);
+    let x = &pose.transform / Tuple::vector(1., 0., 0.);
+    let y = &pose.transform / Tuple::vector(0., 1., 0.);
+    let z = &pose.transform / Tuple::vector(0., 0., 1.);
+    let determinant = x.dot(&y.cross(&z));
+    (determinant * (&pose.inverse_transposed / normal).magnitude()).abs()
+}
+
+
+/// Converts an area pdf at `point` into a solid angle pdf seen from `from`.
+fn area_to_solid_angle(
+    pdf: f64,
+    from: &Tuple<Point>,
+    point: &Tuple<Point>,
+    normal: &Tuple<Vector>,
+) -> Option<f64> {
+    let to_point = point - from;
+    let distance_squared = to_point.magnitude_squared();
+    let cos = normal.dot(&to_point.unit()).abs();
+    match cos > 1e-9 && distance_squared > 0. {
+        true => Some(pdf * distance_squared / cos),
+        false => None,
+    }
+}
+
+
+#[cfg(test)]
+mod sampling_tests {
+    use super::*;
+    use crate::transformations::{scaling, translation, Axis, Transformable};
+    use crate::matrix::Matrix;
+
+    const SAMPLES: usize = 20000;
+
+    /// Estimates the world space area of an object as E[1 / pdf].
+    fn estimated_area(obj: &Object) -> f64 {
+        (0..SAMPLES).map(|_| 1. / obj.shape.sample_surface(obj, 0.).pdf).sum::<f64>()
+            / SAMPLES as f64
+    }
+
+    fn assert_close(estimate: f64, expected: f64) {
+        assert!((estimate - expected).abs() < 0.02 * expected,
+                "estimated {}, expected {}", estimate, expected);
+    }
+
+    #[test]
+    fn area_pdfs_integrate_to_one() {
+        let quad = Object::new(Shape::Quad)
+            .with_transform(scaling(2., 1., 3.).rotate(Axis::X, 0.3));
+        assert_close(estimated_area(&quad), 24.);
+
+        let disk = Object::new(Shape::Disk).with_transform(scaling(2., 5., 2.));
+        assert_close(estimated_area(&disk), 4. * PI);
+
+        let sphere = Object::new_sphere()
+            .with_transform(scaling(2., 2., 2.).translate(1., 0., 0.));
+        assert_close(estimated_area(&sphere), 16. * PI);
+
+        let cube = Object::new(Shape::Cube).with_transform(scaling(1., 2., 3.));
+        assert_close(estimated_area(&cube), 8. * (2. + 3. + 6.));
+    }
+
+    #[test]
+    fn area_pdfs_match_surface_pdf() {
+        let cube = Object::new(Shape::Cube)
+            .with_transform(scaling(1., 2., 3.).rotate(Axis::Y, 0.5));
+        for _ in 0..100 {
+            let sample = cube.shape.sample_surface(&cube, 0.);
+            let pdf = cube.shape.surface_pdf(&cube, &sample.point, 0.);
+            assert!((pdf - sample.pdf).abs() < 1e-9);
+            assert_eq!(sample.normal, cube.normal_at(sample.point, 0.));
+        }
+    }
+
+    #[test]
+    fn solid_angle_pdfs_integrate_to_subtended_solid_angle() {
+        // A disk of radius 1, 2 units away on its axis.
+        let disk = Object::new(Shape::Disk).with_transform(translation(0., 2., 0.));
+        let from = Tuple::point(0., 0., 0.);
+        let estimate = (0..SAMPLES)
+            .filter_map(|_| disk.shape.sample_towards(&disk, &from, 0.))
+            .map(|s| 1. / s.pdf)
+            .sum::<f64>() / SAMPLES as f64;
+        assert_close(estimate, 2. * PI * (1. - 2. / 5f64.sqrt()));
+
+        // A sphere of radius 1, 3 units away, sampled within its cone.
+        let sphere = Object::new_sphere().with_transform(translation(0., 0., 3.));
+        let sample = sphere.shape.sample_towards(&sphere, &from, 0.).unwrap();
+        let cone = 2. * PI * (1. - (8f64 / 9.).sqrt());
+        assert!((1. / sample.pdf - cone).abs() < 1e-9);
+        assert!(sample.point.z < 3.);
+        assert_eq!(sphere.shape.pdf_towards(&sphere, &from, &sample.point, 0.), sample.pdf);
+    }
+
+    #[test]
+    fn solid_angle_pdfs_match_pdf_towards() {
+        let from = Tuple::point(0.3, -2., 0.1);
+        let quad = Object::new(Shape::Quad)
+            .with_transform(Matrix::identity(4).scale(2., 1., 0.5).rotate(Axis::Z, 0.2));
+        let squashed = Object::new_sphere()
+            .with_transform(scaling(1., 0.5, 1.).translate(0., 1., 0.));
+        for obj in [quad, squashed] {
+            for _ in 0..100 {
+                let Some(sample) = obj.shape.sample_towards(&obj, &from, 0.) else { continue };
+                let pdf = obj.shape.pdf_towards(&obj, &from, &sample.point, 0.);
+                assert!((pdf - sample.pdf).abs() < 1e-6 * pdf);
+            }
+        }
+    }
+}
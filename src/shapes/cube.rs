@@ -46,6 +46,7 @@ pub fn intersect_cube<'a>(ray: &Ray, obj: &'a Object) -> Vec<Intersection<'a>>{
 }
 
 #[cfg(test)]
+#[allow(clippy::items_after_test_module)]
 mod cube_intersection_tests {
 
     use super::*;
@@ -96,18 +97,25 @@ mod cube_intersection_tests {
 }
 
 
-pub fn normal_at_cube(obj: &Object, world_point: &Tuple<Point>) -> Tuple<Vector> {
-    let object_point: Tuple<Point> = &obj.inverse_transform / world_point;
+pub fn normal_at_cube(obj: &Object, world_point: &Tuple<Point>, time: f64) -> Tuple<Vector> {
+    let pose = obj.pose_at(time);
+    let object_point: Tuple<Point> = &pose.inverse / world_point;
+    let object_normal = object_normal_at_cube(&object_point);
+
+    let normal = &pose.inverse_transposed / object_normal;
+    normal.unit()
+}
+
+
+/// The (unnormalised) normal of the cube face nearest to a point in cube-space.
+pub fn object_normal_at_cube(object_point: &Tuple<Point>) -> Tuple<Vector> {
     let obj_point_array = [object_point.x, object_point.y, object_point.z];
     // To compute max of f(), we find min of -f().
     let max_abs_dir = minimum_by_key([0, 1, 2].iter(), |i| -obj_point_array[*i].abs()).unwrap();
-    let object_normal = match max_abs_dir {
+    match max_abs_dir {
         0 => Tuple::vector(object_point.x, 0., 0.),
         1 => Tuple::vector(0., object_point.y, 0.),
         2 => Tuple::vector(0., 0., object_point.z),
         _ => unreachable!(),
-    };
-
-    let normal = &obj.inverse_transform_transposed / object_normal;
-    normal.unit()
+    }
 }
@@ -0,0 +1,46 @@
+use crate::{ray::Ray, object::Object, intersection::Intersection, eq, tuples::{Tuple, Point, Vector}};
+
+
+/// Returns the intersection of a ray (in disk-space) with a disk.
+///
+/// In disk-space, the disk has radius 1 around the origin,
+/// lying in the y = 0 plane and facing +y.
+pub fn intersect_disk<'a>(ray: &Ray, obj: &'a Object) -> Vec<Intersection<'a>> {
+    if eq(ray.direction.y, 0.) {
+        return vec![]
+    }
+    let t = -ray.origin.y / ray.direction.y;
+    let p = ray.position(t);
+    if p.x * p.x + p.z * p.z > 1. {
+        return vec![]
+    }
+    vec![Intersection::new(t, obj)]
+}
+
+
+pub fn normal_at_disk(obj: &Object, _world_point: &Tuple<Point>, time: f64) -> Tuple<Vector> {
+    (&obj.pose_at(time).inverse_transposed / Tuple::vector(0., 1., 0.)).unit()
+}
+
+
+#[cfg(test)]
+mod disk_tests {
+    use super::*;
+    use crate::shapes::Shape;
+
+    #[test]
+    fn ray_hits_the_disk_inside_its_radius() {
+        let disk = Object::new(Shape::Disk);
+        let ray = Ray::from_coords(0.6, -3., 0.6, 0., 1., 0.);
+        let xs = intersect_disk(&ray, &disk);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 3.);
+    }
+
+    #[test]
+    fn ray_misses_the_disk_corners() {
+        let disk = Object::new(Shape::Disk);
+        let ray = Ray::from_coords(0.8, -3., 0.8, 0., 1., 0.);
+        assert!(intersect_disk(&ray, &disk).is_empty());
+    }
+}
@@ -0,0 +1,62 @@
+use crate::{ray::Ray, object::Object, intersection::Intersection, eq, tuples::{Tuple, Point, Vector}};
+
+
+/// Returns the intersection of a ray (in quad-space) with a quad.
+///
+/// In quad-space, the quad is the square from -1 to 1 in x and z,
+/// lying in the y = 0 plane and facing +y.
+pub fn intersect_quad<'a>(ray: &Ray, obj: &'a Object) -> Vec<Intersection<'a>> {
+    if eq(ray.direction.y, 0.) {
+        return vec![]
+    }
+    let t = -ray.origin.y / ray.direction.y;
+    let p = ray.position(t);
+    if p.x.abs() > 1. || p.z.abs() > 1. {
+        return vec![]
+    }
+    vec![Intersection::new(t, obj)]
+}
+
+
+pub fn normal_at_quad(obj: &Object, _world_point: &Tuple<Point>, time: f64) -> Tuple<Vector> {
+    (&obj.pose_at(time).inverse_transposed / Tuple::vector(0., 1., 0.)).unit()
+}
+
+
+#[cfg(test)]
+mod quad_tests {
+    use super::*;
+    use crate::shapes::Shape;
+    use crate::transformations::{rotation, Axis};
+
+    #[test]
+    fn ray_hits_the_quad_from_above() {
+        let quad = Object::new(Shape::Quad);
+        let ray = Ray::from_coords(0.5, 2., -0.5, 0., -1., 0.);
+        let xs = intersect_quad(&ray, &quad);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 2.);
+    }
+
+    #[test]
+    fn ray_misses_beside_the_quad() {
+        let quad = Object::new(Shape::Quad);
+        let ray = Ray::from_coords(1.5, 2., 0., 0., -1., 0.);
+        assert!(intersect_quad(&ray, &quad).is_empty());
+    }
+
+    #[test]
+    fn parallel_ray_misses_the_quad() {
+        let quad = Object::new(Shape::Quad);
+        let ray = Ray::from_coords(0., 0., -2., 0., 0., 1.);
+        assert!(intersect_quad(&ray, &quad).is_empty());
+    }
+
+    #[test]
+    fn normal_follows_the_transform() {
+        let quad = Object::new(Shape::Quad)
+            .with_transform(rotation(Axis::X, std::f64::consts::PI));
+        let normal = quad.normal_at(Tuple::point(0., 0., 0.), 0.);
+        assert_eq!(normal, Tuple::vector(0., -1., 0.));
+    }
+}
@@ -1,10 +1,16 @@
+use std::f64::consts::PI;
+use rand::Rng;
+
 use crate::{ray::Ray, object::Object, intersection::Intersection, tuples::{Tuple, Point, Vector}};
+use crate::normal::from_local;
+use super::SurfaceSample;
 
 
 /// Returns the intersection(s) of a ray (in sphere-space) with a sphere.
 ///
 /// By 'in sphere-space', we mean that we're using coordinates where
 /// the sphere's origin is at the origin, and its radius is 1.0.
+#[allow(clippy::op_ref)]
 pub fn intersect_sphere<'a>(ray: &Ray, obj: &'a Object) -> Vec<Intersection<'a>>{
 
     let sphere_to_ray = &ray.origin - crate::tuples::Tuple::point(0.0, 0.0, 0.0);
@@ -82,14 +88,15 @@ mod sphere_intersection_tests {
 }
 
 
-pub fn normal_at_sphere(obj: &Object, world_point: &Tuple<Point>) -> Tuple<Vector> {
-    let object_point = &obj.inverse_transform / world_point;
+pub fn normal_at_sphere(obj: &Object, world_point: &Tuple<Point>, time: f64) -> Tuple<Vector> {
+    let pose = obj.pose_at(time);
+    let object_point = &pose.inverse / world_point;
     let object_normal = object_point - Tuple::point(0., 0., 0.);
     // The correct transformation for normals isn't what you expect!
     // (applying the inverse will squash normals,
     // preventing them from being perpendicular to the surface.)
 
-    let normal = &obj.inverse_transform_transposed / object_normal;
+    let normal = &pose.inverse_transposed / object_normal;
     normal.unit()
 }
 
@@ -111,7 +118,7 @@ mod sphere_normal_tests {
             z in -1.0..1.0,
         ) {
             let s = Object::new_sphere();
-            let n = s.normal_at(Tuple::point(x, y, z));
+            let n = s.normal_at(Tuple::point(x, y, z), 0.);
             assert_eq!(n, n.unit());
         }
     }
@@ -119,31 +126,32 @@ mod sphere_normal_tests {
     #[test]
     fn normal_on_sphere_at_point_on_x_axis() {
         let s = Object::new_sphere();
-        let n = s.normal_at(Tuple::point(1., 0., 0.));
+        let n = s.normal_at(Tuple::point(1., 0., 0.), 0.);
         assert_eq!(n, Tuple::vector(1., 0., 0.));
     }
 
     #[test]
     fn normal_on_sphere_at_point_on_y_axis() {
         let s = Object::new_sphere();
-        let n = s.normal_at(Tuple::point(0., 1., 0.));
+        let n = s.normal_at(Tuple::point(0., 1., 0.), 0.);
         assert_eq!(n, Tuple::vector(0., 1., 0.));
     }
 
     #[test]
     fn normal_on_sphere_at_point_on_z_axis() {
         let s = Object::new_sphere();
-        let n = s.normal_at(Tuple::point(0., 0., 1.));
+        let n = s.normal_at(Tuple::point(0., 0., 1.), 0.);
         assert_eq!(n, Tuple::vector(0., 0., 1.));
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn normal_on_a_translated_sphere() {
         let s = Object::new_sphere().with_transform(
             translation(0., 1., 0.)
         );
         assert_eq!(
-            s.normal_at(Tuple::point(0., 1.70711, -0.70711)),
+            s.normal_at(Tuple::point(0., 1.70711, -0.70711), 0.),
             Tuple::vector(0., 0.70711, -0.70711)
         )
     }
@@ -155,9 +163,80 @@ mod sphere_normal_tests {
             .scale(1., 0.5, 1.)
         );
         assert_eq!(
-            s.normal_at(Tuple::point(0., 2.0_f64.sqrt()/2.0, -(2.0_f64.sqrt())/2.0)),
+            s.normal_at(Tuple::point(0., 2.0_f64.sqrt()/2.0, -(2.0_f64.sqrt())/2.0), 0.),
             Tuple::vector(0., 0.97014, -0.24254)
         )
     }
 
-}
\ No newline at end of file
+}
+
+/// World space centre and radius of a sphere, if its transform
+/// keeps it round (up to rotation and translation).
+fn world_sphere(obj: &Object, time: f64) -> Option<(Tuple<Point>, f64)> {
+    let pose = obj.pose_at(time);
+    let x = &pose.transform / Tuple::vector(1., 0., 0.);
+    let y = &pose.transform / Tuple::vector(0., 1., 0.);
+    let z = &pose.transform / Tuple::vector(0., 0., 1.);
+    let r2 = x.magnitude_squared();
+    let tolerance = 1e-9 * r2;
+    let is_round = (y.magnitude_squared() - r2).abs() < tolerance
+        && (z.magnitude_squared() - r2).abs() < tolerance
+        && x.dot(&y).abs() < tolerance
+        && y.dot(&z).abs() < tolerance
+        && z.dot(&x).abs() < tolerance;
+    match is_round {
+        true => Some((&pose.transform / Tuple::point(0., 0., 0.), r2.sqrt())),
+        false => None,
+    }
+}
+
+
+/// `1 - cos θ` for the half-angle θ of the cone that a sphere subtends
+/// from a point outside it.
+fn subtended_cone(distance_squared: f64, radius: f64) -> Option<f64> {
+    let sin2_max = radius * radius / distance_squared;
+    if sin2_max >= 1. {
+        return None;
+    }
+    let cos_max = (1. - sin2_max).sqrt();
+    // Avoids cancellation for small, distant spheres.
+    Some(sin2_max / (1. + cos_max))
+}
+
+
+/// Samples the visible side of a round sphere uniformly by the solid
+/// angle it subtends from `from`. None if `from` is inside the sphere
+/// or the sphere has been squashed.
+pub fn sample_sphere_towards(obj: &Object, from: &Tuple<Point>, time: f64) -> Option<SurfaceSample> {
+    let (centre, radius) = world_sphere(obj, time)?;
+    let to_centre = centre - from;
+    let distance_squared = to_centre.magnitude_squared();
+    let one_minus_cos_max = subtended_cone(distance_squared, radius)?;
+
+    let mut rng = crate::rng::thread_rng();
+    let cos_theta = 1. - rng.gen_range(0.0..1.0) * one_minus_cos_max;
+    let sin2_theta = (1. - cos_theta * cos_theta).max(0.);
+    let sin_theta = sin2_theta.sqrt();
+    let phi = rng.gen_range(0.0..2. * PI);
+    let local = Tuple::vector(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+    let direction = from_local(&local, &to_centre.unit());
+
+    // Distance to the near side of the sphere along `direction`.
+    let distance = distance_squared.sqrt();
+    let t = distance * cos_theta
+        - (radius * radius - distance_squared * sin2_theta).max(0.).sqrt();
+    let point = from + direction * t;
+    Some(SurfaceSample {
+        point,
+        normal: (point - centre).unit(),
+        pdf: 1. / (2. * PI * one_minus_cos_max),
+    })
+}
+
+
+/// The solid angle pdf of `sample_sphere_towards`, if it applies.
+pub fn sphere_pdf_towards(obj: &Object, from: &Tuple<Point>, time: f64) -> Option<f64> {
+    let (centre, radius) = world_sphere(obj, time)?;
+    let one_minus_cos_max = subtended_cone((centre - from).magnitude_squared(), radius)?;
+    Some(1. / (2. * PI * one_minus_cos_max))
+}
@@ -0,0 +1,286 @@
+use std::sync::OnceLock;
+
+use rand::Rng;
+
+use crate::colors::Color;
+
+/// Shortest wavelength (in nanometres) we trace.
+pub const MIN_WAVELENGTH: f64 = 380.;
+/// Longest wavelength (in nanometres) we trace.
+pub const MAX_WAVELENGTH: f64 = 780.;
+/// Wavelength of the helium d-line, at which refractive indices are quoted.
+pub const REFERENCE_WAVELENGTH: f64 = 587.56;
+
+/// Picks a wavelength uniformly from the visible range.
+pub fn sample_wavelength() -> f64 {
+    crate::rng::thread_rng().gen_range(MIN_WAVELENGTH..MAX_WAVELENGTH)
+}
+
+/// Picks a wavelength uniformly from the `stratum`-th of `strata` equal
+/// sub-ranges of the visible range.
+///
+/// Spreading a pixel's samples over the strata gives much less color
+/// noise than independent samples, with the same (uniform) pdf overall.
+pub fn sample_wavelength_stratified(stratum: usize, strata: usize) -> f64 {
+    let width = (MAX_WAVELENGTH - MIN_WAVELENGTH) / strata as f64;
+    let offset: f64 = crate::rng::thread_rng().gen_range(0.0..1.0);
+    MIN_WAVELENGTH + (stratum as f64 + offset) * width
+}
+
+/// The pdf of `sample_wavelength`.
+pub fn wavelength_pdf() -> f64 {
+    1. / (MAX_WAVELENGTH - MIN_WAVELENGTH)
+}
+
+/// Piecewise Gaussian used by the colour matching function fit.
+fn lobe(wavelength: f64, mean: f64, sigma_below: f64, sigma_above: f64) -> f64 {
+    let sigma = if wavelength < mean {sigma_below} else {sigma_above};
+    let t = (wavelength - mean) / sigma;
+    (-0.5 * t * t).exp()
+}
+
+/// The CIE 1931 2° colour matching functions at `wavelength` (nm).
+///
+/// Uses the multi-lobe analytic fit of Wyman, Sloan and Shirley (2013),
+/// which is within a few percent of the tabulated data.
+pub fn cie_xyz(wavelength: f64) -> (f64, f64, f64) {
+    let x = 1.056 * lobe(wavelength, 599.8, 37.9, 31.0)
+        + 0.362 * lobe(wavelength, 442.0, 16.0, 26.7)
+        - 0.065 * lobe(wavelength, 501.1, 20.4, 26.2);
+    let y = 0.821 * lobe(wavelength, 568.8, 46.9, 40.5)
+        + 0.286 * lobe(wavelength, 530.9, 16.3, 31.1);
+    let z = 1.217 * lobe(wavelength, 437.0, 11.8, 36.0)
+        + 0.681 * lobe(wavelength, 459.0, 26.0, 13.8);
+    (x, y, z)
+}
+
+/// Converts CIE XYZ to linear sRGB (D65 white point).
+pub fn xyz_to_linear_srgb(x: f64, y: f64, z: f64) -> Color {
+    Color::new(
+        3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+        -0.9692660 * x + 1.8760108 * y + 0.0415560 * z,
+        0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+    )
+}
+
+/// Unnormalised, non-negative sRGB response to a single wavelength.
+fn raw_wavelength_rgb(wavelength: f64) -> Color {
+    let (x, y, z) = cie_xyz(wavelength);
+    let rgb = xyz_to_linear_srgb(x, y, z);
+    Color::new(rgb.red.max(0.), rgb.green.max(0.), rgb.blue.max(0.))
+}
+
+/// Mean of `raw_wavelength_rgb` over the visible range.
+fn mean_wavelength_rgb() -> Color {
+    static MEAN: OnceLock<Color> = OnceLock::new();
+    *MEAN.get_or_init(|| {
+        let steps = 4000;
+        let step = (MAX_WAVELENGTH - MIN_WAVELENGTH) / steps as f64;
+        let mut total = Color::new(0., 0., 0.);
+        for i in 0..steps {
+            let wavelength = MIN_WAVELENGTH + (i as f64 + 0.5) * step;
+            total = total + raw_wavelength_rgb(wavelength);
+        }
+        total * (1. / steps as f64)
+    })
+}
+
+/// The color a single-wavelength sample contributes to the pixel.
+///
+/// This is normalised so that averaging it over uniformly sampled
+/// wavelengths gives white: a path whose throughput does not depend on
+/// wavelength comes out with its usual RGB color.
+pub fn wavelength_to_rgb(wavelength: f64) -> Color {
+    let raw = raw_wavelength_rgb(wavelength);
+    let mean = mean_wavelength_rgb();
+    Color::new(raw.red / mean.red, raw.green / mean.green, raw.blue / mean.blue)
+}
+
+
+/// A color in CIE XYZ coordinates.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Xyz {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Xyz {
+    /// The XYZ estimate from one radiance sample at a uniformly sampled wavelength.
+    pub fn from_spectral_sample(radiance: f64, wavelength: f64) -> Xyz {
+        let (x, y, z) = cie_xyz(wavelength);
+        let weight = radiance / wavelength_pdf();
+        Xyz { x: x * weight, y: y * weight, z: z * weight }
+    }
+
+    /// Converts to linear sRGB, white balanced so that a flat spectrum of
+    /// value 1 (the upsampling of RGB white) maps to RGB white.
+    pub fn to_linear_srgb(self) -> Color {
+        let rgb = xyz_to_linear_srgb(self.x, self.y, self.z);
+        let white = flat_spectrum_rgb();
+        Color::new(rgb.red / white.red, rgb.green / white.green, rgb.blue / white.blue)
+    }
+}
+
+impl std::ops::Add<Xyz> for Xyz {
+    type Output = Xyz;
+
+    fn add(self, _rhs: Xyz) -> Xyz {
+        Xyz {
+            x: self.x + _rhs.x,
+            y: self.y + _rhs.y,
+            z: self.z + _rhs.z,
+        }
+    }
+}
+
+impl std::ops::Mul<f64> for Xyz {
+    type Output = Xyz;
+
+    fn mul(self, _rhs: f64) -> Xyz {
+        Xyz {
+            x: self.x * _rhs,
+            y: self.y * _rhs,
+            z: self.z * _rhs,
+        }
+    }
+}
+
+/// Linear sRGB (before white balancing) of a flat spectrum of value 1.
+fn flat_spectrum_rgb() -> Color {
+    static WHITE: OnceLock<Color> = OnceLock::new();
+    *WHITE.get_or_init(|| {
+        let steps = 4000;
+        let step = (MAX_WAVELENGTH - MIN_WAVELENGTH) / steps as f64;
+        let mut xyz = Xyz::default();
+        for i in 0..steps {
+            let wavelength = MIN_WAVELENGTH + (i as f64 + 0.5) * step;
+            xyz = xyz + Xyz::from_spectral_sample(1., wavelength);
+        }
+        let xyz = xyz * (1. / steps as f64);
+        xyz_to_linear_srgb(xyz.x, xyz.y, xyz.z)
+    })
+}
+
+
+/// Basis spectra for RGB upsampling, from Smits (1999), "An RGB-to-spectrum
+/// conversion for reflectances". Ten equal bins spanning 380nm to 720nm.
+const SMITS_WHITE: [f64; 10] = [1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000];
+const SMITS_CYAN: [f64; 10] = [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000];
+const SMITS_MAGENTA: [f64; 10] = [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959];
+const SMITS_YELLOW: [f64; 10] = [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840];
+const SMITS_RED: [f64; 10] = [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149];
+const SMITS_GREEN: [f64; 10] = [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025];
+const SMITS_BLUE: [f64; 10] = [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496];
+
+/// Evaluates the smooth spectrum that Smits' method assigns to an RGB
+/// color at `wavelength` (nm).
+///
+/// Used to upsample RGB albedos and emissions for spectral rendering.
+/// Wavelengths past the last bin reuse its value.
+pub fn rgb_to_spectrum(color: Color, wavelength: f64) -> f64 {
+    let bin = ((wavelength - 380.) / 34.).floor().clamp(0., 9.) as usize;
+    let (r, g, b) = (color.red, color.green, color.blue);
+    if r <= g && r <= b {
+        let base = r * SMITS_WHITE[bin];
+        if g <= b {
+            base + (g - r) * SMITS_CYAN[bin] + (b - g) * SMITS_BLUE[bin]
+        } else {
+            base + (b - r) * SMITS_CYAN[bin] + (g - b) * SMITS_GREEN[bin]
+        }
+    } else if g <= r && g <= b {
+        let base = g * SMITS_WHITE[bin];
+        if r <= b {
+            base + (r - g) * SMITS_MAGENTA[bin] + (b - r) * SMITS_BLUE[bin]
+        } else {
+            base + (b - g) * SMITS_MAGENTA[bin] + (r - b) * SMITS_RED[bin]
+        }
+    } else {
+        let base = b * SMITS_WHITE[bin];
+        if r <= g {
+            base + (r - b) * SMITS_YELLOW[bin] + (g - r) * SMITS_GREEN[bin]
+        } else {
+            base + (g - b) * SMITS_YELLOW[bin] + (r - g) * SMITS_RED[bin]
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn luminance_peaks_in_the_green() {
+        let (_, y_green, _) = cie_xyz(555.);
+        let (_, y_blue, _) = cie_xyz(450.);
+        let (_, y_red, _) = cie_xyz(650.);
+        assert!((y_green - 1.).abs() < 0.05);
+        assert!(y_green > y_blue);
+        assert!(y_green > y_red);
+    }
+
+    #[test]
+    fn wavelength_weights_average_to_white() {
+        let steps = 1000;
+        let step = (MAX_WAVELENGTH - MIN_WAVELENGTH) / steps as f64;
+        let mut total = Color::new(0., 0., 0.);
+        for i in 0..steps {
+            total = total + wavelength_to_rgb(MIN_WAVELENGTH + (i as f64 + 0.5) * step);
+        }
+        let mean = total * (1. / steps as f64);
+        assert!((mean.red - 1.).abs() < 1e-2);
+        assert!((mean.green - 1.).abs() < 1e-2);
+        assert!((mean.blue - 1.).abs() < 1e-2);
+    }
+
+    #[test]
+    fn short_wavelengths_are_blue_and_long_ones_red() {
+        let blue = wavelength_to_rgb(450.);
+        let red = wavelength_to_rgb(650.);
+        assert!(blue.blue > blue.red);
+        assert!(red.red > red.blue);
+    }
+
+    fn spectrum_to_rgb(spectrum: impl Fn(f64) -> f64) -> Color {
+        let steps = 1000;
+        let step = (MAX_WAVELENGTH - MIN_WAVELENGTH) / steps as f64;
+        let mut xyz = Xyz::default();
+        for i in 0..steps {
+            let wavelength = MIN_WAVELENGTH + (i as f64 + 0.5) * step;
+            xyz = xyz + Xyz::from_spectral_sample(spectrum(wavelength), wavelength);
+        }
+        (xyz * (1. / steps as f64)).to_linear_srgb()
+    }
+
+    #[test]
+    fn flat_spectrum_is_white() {
+        let rgb = spectrum_to_rgb(|_| 1.);
+        assert_eq!(rgb, Color::new(1., 1., 1.));
+    }
+
+    #[test]
+    fn white_upsamples_to_flat_spectrum() {
+        for wavelength in [400., 500., 600., 700.] {
+            assert!((rgb_to_spectrum(Color::new(1., 1., 1.), wavelength) - 1.).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn upsampled_colors_round_trip_to_similar_colors() {
+        for color in [
+            Color::new(0.8, 0.1, 0.1),
+            Color::new(0.1, 0.7, 0.2),
+            Color::new(0.2, 0.3, 0.9),
+        ] {
+            let rgb = spectrum_to_rgb(|wavelength| rgb_to_spectrum(color, wavelength));
+            for (a, b) in [
+                (rgb.red, color.red),
+                (rgb.green, color.green),
+                (rgb.blue, color.blue),
+            ] {
+                assert!((a - b).abs() < 0.15, "{:?} became {:?}", color, rgb);
+            }
+        }
+    }
+}
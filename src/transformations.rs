@@ -198,6 +198,7 @@ mod tests {
 
 
     #[test]
+    #[allow(clippy::op_ref)]
     fn fluent_api() -> Result<()> {
         let p = Tuple::point(1.0, 0., 0.);
         let transform = Matrix::identity(4)
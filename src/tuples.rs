@@ -96,12 +96,14 @@ impl std::ops::Add<&Tuple<Vector>> for &Tuple<Vector> {
 }
 impl std::ops::Add<Tuple<Vector>> for &Tuple<Vector> {
     type Output = Tuple<Vector>;
+    #[allow(clippy::op_ref)]
     fn add(self, _rhs: Tuple<Vector>) -> Tuple<Vector> {
         self + &_rhs
     }
 }
 impl std::ops::Add<&Tuple<Vector>> for Tuple<Vector> {
     type Output = Tuple<Vector>;
+    #[allow(clippy::op_ref)]
     fn add(self, _rhs: &Tuple<Vector>) -> Tuple<Vector> {
         &self + _rhs
     }
@@ -129,12 +131,14 @@ impl std::ops::Add<&Tuple<Vector>> for &Tuple<Point> {
 }
 impl std::ops::Add<Tuple<Vector>> for &Tuple<Point> {
     type Output = Tuple<Point>;
+    #[allow(clippy::op_ref)]
     fn add(self, _rhs: Tuple<Vector>) -> Tuple<Point> {
         self + &_rhs
     }
 }
 impl std::ops::Add<&Tuple<Vector>> for Tuple<Point> {
     type Output = Tuple<Point>;
+    #[allow(clippy::op_ref)]
     fn add(self, _rhs: &Tuple<Vector>) -> Tuple<Point> {
         &self + _rhs
     }
@@ -161,12 +165,14 @@ impl std::ops::Add<&Tuple<Point>> for &Tuple<Vector> {
 }
 impl std::ops::Add<Tuple<Point>> for &Tuple<Vector> {
     type Output = Tuple<Point>;
+    #[allow(clippy::op_ref)]
     fn add(self, _rhs: Tuple<Point>) -> Tuple<Point> {
         self + &_rhs
     }
 }
 impl std::ops::Add<&Tuple<Point>> for Tuple<Vector> {
     type Output = Tuple<Point>;
+    #[allow(clippy::op_ref)]
     fn add(self, _rhs: &Tuple<Point>) -> Tuple<Point> {
         &self + _rhs
     }
@@ -193,12 +199,14 @@ impl std::ops::Sub<&Tuple<Vector>> for &Tuple<Vector> {
 }
 impl std::ops::Sub<Tuple<Vector>> for &Tuple<Vector> {
     type Output = Tuple<Vector>;
+    #[allow(clippy::op_ref)]
     fn sub(self, _rhs: Tuple<Vector>) -> Tuple<Vector> {
         self - &_rhs
     }
 }
 impl std::ops::Sub<&Tuple<Vector>> for Tuple<Vector> {
     type Output = Tuple<Vector>;
+    #[allow(clippy::op_ref)]
     fn sub(self, _rhs: &Tuple<Vector>) -> Tuple<Vector> {
         &self - _rhs
     }
@@ -225,12 +233,14 @@ impl std::ops::Sub<&Tuple<Point>> for &Tuple<Point> {
 }
 impl std::ops::Sub<Tuple<Point>> for &Tuple<Point> {
     type Output = Tuple<Vector>;
+    #[allow(clippy::op_ref)]
     fn sub(self, _rhs: Tuple<Point>) -> Tuple<Vector> {
         self - &_rhs
     }
 }
 impl std::ops::Sub<&Tuple<Point>> for Tuple<Point> {
     type Output = Tuple<Vector>;
+    #[allow(clippy::op_ref)]
     fn sub(self, _rhs: &Tuple<Point>) -> Tuple<Vector> {
         &self - _rhs
     }
@@ -257,12 +267,14 @@ impl std::ops::Sub<&Tuple<Vector>> for &Tuple<Point> {
 }
 impl std::ops::Sub<Tuple<Vector>> for &Tuple<Point> {
     type Output = Tuple<Point>;
+    #[allow(clippy::op_ref)]
     fn sub(self, _rhs: Tuple<Vector>) -> Tuple<Point> {
         self - &_rhs
     }
 }
 impl std::ops::Sub<&Tuple<Vector>> for Tuple<Point> {
     type Output = Tuple<Point>;
+    #[allow(clippy::op_ref)]
     fn sub(self, _rhs: &Tuple<Vector>) -> Tuple<Point> {
         &self - _rhs
     }
@@ -347,9 +359,9 @@ impl Tuple<Vector> {
 
     pub fn random_in_unit_sphere() -> Tuple<Vector> {
         loop {
-            let x = rand::random::<f64>() * 2.0 - 1.0;
-            let y = rand::random::<f64>() * 2.0 - 1.0;
-            let z = rand::random::<f64>() * 2.0 - 1.0;
+            let x = crate::rng::random() * 2.0 - 1.0;
+            let y = crate::rng::random() * 2.0 - 1.0;
+            let z = crate::rng::random() * 2.0 - 1.0;
             let vec = Tuple::vector(x, y, z);
             if vec.magnitude() < 1. {
                 return vec
@@ -360,8 +372,8 @@ impl Tuple<Vector> {
     /// Returns a random vector in the unit disc of the x-y plane.
     pub fn random_in_unit_disc() -> Tuple<Vector> {
         loop {
-            let x = rand::random::<f64>() * 2.0 - 1.0;
-            let y = rand::random::<f64>() * 2.0 - 1.0;
+            let x = crate::rng::random() * 2.0 - 1.0;
+            let y = crate::rng::random() * 2.0 - 1.0;
             let vec = Tuple::vector(x, y, 0.0);
             if vec.magnitude() < 1. {
                 return vec
@@ -0,0 +1,207 @@
+use rand::Rng;
+
+use crate::{
+    groups::ObjectGroup,
+    object::Object,
+    intersection::{Intersectable, Intersection, intersect, intersect_shape},
+    media::{Fog, segment_inside},
+    environment::Environment,
+    lights::{Light, LightSample},
+    ray::Ray,
+    tuples::{Tuple, Point, Vector},
+};
+
+/// Everything in a scene: the objects, the lights, what surrounds them,
+/// and scene-wide effects such as fog.
+pub struct World {
+    pub objects: ObjectGroup,
+    pub lights: Vec<Light>,
+    /// Indices of the emissive objects, which are sampled as area lights.
+    area_lights: Vec<usize>,
+    pub fog: Option<Fog>,
+    pub environment: Environment,
+}
+
+impl World {
+    pub fn new(objects: ObjectGroup) -> World {
+        let area_lights = objects.iter()
+            .enumerate()
+            .filter(|(_, object)| object.material.is_emissive())
+            .map(|(index, _)| index)
+            .collect();
+        World {
+            objects,
+            lights: vec![],
+            area_lights,
+            fog: None,
+            environment: Environment::Gradient,
+        }
+    }
+
+    pub fn with_light(mut self, light: Light) -> World {
+        self.lights.push(light);
+        self
+    }
+
+    pub fn with_fog(self, fog: Fog) -> World {
+        World { fog: Some(fog), ..self }
+    }
+
+    pub fn with_environment(self, environment: Environment) -> World {
+        World { environment, ..self }
+    }
+
+    /// How many lights `sample_light` picks from.
+    fn sampled_light_count(&self) -> usize {
+        self.lights.len() + self.area_lights.len() + self.environment.is_sampled() as usize
+    }
+
+    /// Picks a light uniformly at random, and a direction towards it,
+    /// to light `position` directly at `time`.
+    ///
+    /// The sample accounts for the chance of picking that light.
+    /// None if the scene has no lights that can be sampled.
+    pub fn sample_light(&self, position: &Tuple<Point>, time: f64) -> Option<LightSample> {
+        let count = self.sampled_light_count();
+        if count == 0 {
+            return None;
+        }
+        let index = crate::rng::thread_rng().gen_range(0..count);
+        if let Some(light) = self.lights.get(index) {
+            let sample = light.sample(position)?;
+            return Some(LightSample { radiance: sample.radiance * count as f64, ..sample });
+        }
+        if let Some(&object_index) = self.area_lights.get(index - self.lights.len()) {
+            let object = self.objects.get(object_index)?;
+            let surface = object.shape.sample_towards(object, position, time)?;
+            let to_light = surface.point - position;
+            let distance = to_light.magnitude();
+            let direction = to_light * (1. / distance);
+            return Some(LightSample {
+                direction,
+                distance,
+                radiance: object.material.emitted(&surface.normal, &-direction),
+                pdf: Some(surface.pdf / count as f64),
+            });
+        }
+        let (direction, radiance, pdf) = self.environment.sample()?;
+        Some(LightSample {
+            direction,
+            distance: f64::INFINITY,
+            radiance,
+            pdf: Some(pdf / count as f64),
+        })
+    }
+
+    /// The solid angle pdf with which `sample_light` picks `point` on the
+    /// emissive `object`, as seen from `from` at `time`.
+    pub fn area_light_pdf(
+        &self,
+        object: &Object,
+        from: &Tuple<Point>,
+        point: &Tuple<Point>,
+        time: f64,
+    ) -> f64 {
+        match object.material.is_emissive() {
+            true => object.shape.pdf_towards(object, from, point, time) / self.sampled_light_count() as f64,
+            false => 0.,
+        }
+    }
+
+    /// The solid angle pdf with which `sample_light` picks the environment
+    /// in `direction`.
+    pub fn environment_pdf(&self, direction: &Tuple<Vector>) -> f64 {
+        match self.sampled_light_count() {
+            0 => 0.,
+            count => self.environment.pdf(direction) / count as f64,
+        }
+    }
+
+    /// The fraction of light that makes it along `ray` between `min_t` and
+    /// `max_t`: zero if a surface is in the way, and less than one through
+    /// media and fog.
+    pub fn transmittance(&self, ray: &Ray, min_t: f64, max_t: f64) -> f64 {
+        let mut transmittance = 1.;
+        for object in self.objects.iter() {
+            let local_ray = ray.transform(&object.pose_at(ray.time).inverse);
+            let boundary = intersect_shape(&local_ray, object);
+            match &object.medium {
+                None => if boundary.iter().any(|x| min_t < x.t && x.t < max_t) {
+                    return 0.
+                },
+                Some(medium) => if let Some((enter, exit)) = segment_inside(&boundary) {
+                    let (from, to) = (enter.max(min_t), exit.min(max_t));
+                    if from < to {
+                        transmittance *= medium.transmittance(ray, &local_ray, from, to);
+                    }
+                },
+            }
+        }
+        // Towards the sky, the fog ends `far` away.
+        if let Some(fog) = &self.fog {
+            let max_t = max_t.min(fog.far / ray.direction.magnitude());
+            if max_t > min_t {
+                let distance = (max_t - min_t) * ray.direction.magnitude();
+                transmittance *= (-fog.density * distance).exp();
+            }
+        }
+        transmittance
+    }
+}
+
+impl Intersectable for World {
+    fn intersect<'a>(ray: &Ray, obj: &'a Self) -> Vec<Intersection<'a>> {
+        intersect(ray, &obj.objects)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{transformations::translation, materials::Volume, colors::Color};
+    use crate::media::{Medium, Fog};
+
+    fn world_with(object: Object) -> World {
+        let mut objects = ObjectGroup::new();
+        objects.add(object);
+        World::new(objects)
+    }
+
+    #[test]
+    fn surfaces_block_light() {
+        let world = world_with(Object::new_sphere().with_transform(translation(0., 0., 5.)));
+        let ray = Ray::from_coords(0., 0., 0., 0., 0., 1.);
+        assert_eq!(world.transmittance(&ray, 0.001, f64::INFINITY), 0.);
+        assert_eq!(world.transmittance(&ray, 0.001, 3.), 1.);
+    }
+
+    #[test]
+    fn sampling_accounts_for_the_choice_of_light() {
+        let mut objects = ObjectGroup::new();
+        objects.add(Object::new_sphere());
+        let world = World::new(objects)
+            .with_light(Light::point(Tuple::point(0., 2., 0.), Color::new(4., 4., 4.)))
+            .with_light(Light::point(Tuple::point(0., -2., 0.), Color::new(4., 4., 4.)));
+        let sample = world.sample_light(&Tuple::point(0., 0., 0.), 0.).unwrap();
+        assert_eq!(sample.radiance, Color::new(2., 2., 2.));
+    }
+
+    #[test]
+    fn media_attenuate_light() {
+        let medium = Medium::homogeneous(0.5, Volume::isotropic(Color::new(1., 1., 1.)));
+        let world = world_with(Object::new_sphere().with_medium(medium));
+        let ray = Ray::from_coords(0., 0., -5., 0., 0., 1.);
+        let transmittance = world.transmittance(&ray, 0.001, f64::INFINITY);
+        assert!((transmittance - (-1f64).exp()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn fog_reaches_past_the_scene() {
+        let fog = Fog::new(0.1, Volume::isotropic(Color::new(1., 1., 1.))).with_far(10.);
+        let world = World::new(ObjectGroup::new()).with_fog(fog);
+        let ray = Ray::from_coords(0., 0., 0., 0., 0., 2.);
+        assert!((world.transmittance(&ray, 0., f64::INFINITY) - (-1f64).exp()).abs() < 1e-9);
+        assert!((world.transmittance(&ray, 0., 1.) - (-0.2f64).exp()).abs() < 1e-9);
+    }
+}
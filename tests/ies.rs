@@ -0,0 +1,65 @@
+use crayfish::lights::{Light, ies::IesProfile};
+use crayfish::colors::Color;
+use crayfish::tuples::Tuple;
+use crayfish::Result;
+
+fn load(name: &str) -> Result<IesProfile> {
+    IesProfile::load(&format!("{}/tests/ies/{}", env!("CARGO_MANIFEST_DIR"), name))
+}
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-6 * b.abs().max(1.)
+}
+
+#[test]
+fn all_sample_profiles_parse() -> Result<()> {
+    for name in ["downlight.ies", "wallwasher.ies", "quadrant.ies", "tilt_include.ies", "legacy_1986.ies"] {
+        let profile = load(name)?;
+        assert!(profile.max_candela() > 0., "{} has no light", name);
+    }
+    Ok(())
+}
+
+#[test]
+fn rotationally_symmetric_profile_ignores_horizontal_angle() -> Result<()> {
+    let profile = load("downlight.ies")?;
+    assert!(close(profile.candela(0., 0.), 1200.));
+    assert!(close(profile.candela(15., 0.), 1000.));
+    assert!(close(profile.candela(15., 200.), 1000.));
+    assert!(close(profile.candela(100., 0.), 0.));
+    Ok(())
+}
+
+#[test]
+fn multiplier_and_ballast_factor_scale_candela() -> Result<()> {
+    let profile = load("wallwasher.ies")?;
+    assert!(close(profile.candela(22.5, 0.), 500. * 1.5 * 0.9));
+    // Bilateral symmetry: 270 degrees mirrors 90.
+    assert!(close(profile.candela(45., 270.), profile.candela(45., 90.)));
+    Ok(())
+}
+
+#[test]
+fn tilt_data_is_skipped() -> Result<()> {
+    let profile = load("tilt_include.ies")?;
+    assert!(close(profile.candela(0., 0.), 400.));
+    assert!(close(profile.candela(67.5, 0.), 225.));
+    Ok(())
+}
+
+#[test]
+fn external_tilt_files_are_refused() {
+    let error = load("tilt_file.ies").unwrap_err();
+    assert_eq!(error.to_string(), "IES tilt data in a separate file (lamp.tlt) is not supported");
+}
+
+#[test]
+fn profiles_shape_point_lights() -> Result<()> {
+    let light = Light::point(Tuple::point(0., 2., 0.), Color::new(1., 1., 1.))
+        .with_profile(load("downlight.ies")?);
+    let below = light.sample(&Tuple::point(0., 0., 0.)).unwrap();
+    assert_eq!(below.radiance, Color::new(0.25, 0.25, 0.25));
+    let beside = light.sample(&Tuple::point(2., 2., 0.)).unwrap();
+    assert_eq!(beside.radiance, Color::new(0., 0., 0.));
+    Ok(())
+}